@@ -0,0 +1,236 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use sha2::{Sha256, Digest};
+
+use crate::persist;
+use crate::timeutil::{SECONDS_PER_DAY, civil_from_days, now_millis};
+
+pub const DEFAULT_BACKUP_COUNT: usize = 10;
+pub const DEFAULT_BACKUP_MAX_AGE: u64 = 0;
+
+// YYYYMMDD-HHMMSS-mmm
+const TIME_STAMP_LEN: usize = 19;
+// Number of hex digits of the hash which identifies the password file a backup belongs to
+const STORE_TAG_LEN: usize = 12;
+
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    pub time_stamp: String,
+    pub size: u64
+}
+
+impl BackupEntry {
+    // Turns 20240131-235959-123 into 2024-01-31 23:59:59.123 UTC
+    pub fn display_time(&self) -> String {
+        let t = &self.time_stamp;
+        return format!("{}-{}-{} {}:{}:{}.{} UTC", &t[0..4], &t[4..6], &t[6..8], &t[9..11], &t[11..13], &t[13..15], &t[16..19]);
+    }
+}
+
+// Keeps a number of timestamped copies of the encrypted password file. The names of the backups are derived
+// from the configured backup file name and a hash of the id of the password file, i.e. rustpwman_last.enc
+// becomes rustpwman_last-3f2a9c01d4e7-20240131-235959-123.enc. Backups of other password files which share
+// the same directory are therefore never listed, restored or deleted.
+#[derive(Debug, Clone)]
+pub struct BackupManager {
+    dir: PathBuf,
+    stem: String,
+    ext: String,
+    max_count: usize,
+    max_age_days: u64
+}
+
+// Identifies a password file. Remote files are identified by their URL, local files by their absolute path.
+pub fn store_id(p: &persist::SendSyncPersister, file_name: &str) -> String {
+    if let Ok(id) = p.get_canonical_path() {
+        return id;
+    }
+
+    // The file does not exist yet
+    return match std::path::absolute(file_name) {
+        Ok(a) => a.to_string_lossy().to_string(),
+        Err(_) => String::from(file_name)
+    };
+}
+
+fn store_tag(store_id: &str) -> String {
+    let hash = Sha256::digest(store_id.as_bytes());
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+
+    return String::from(&hex[..STORE_TAG_LEN]);
+}
+
+// Time stamps are in UTC and sort in the same order as the points in time they represent
+pub fn format_time_stamp(millis: u128) -> String {
+    let secs = (millis / 1000) as u64;
+    let (y, m, d) = civil_from_days((secs / SECONDS_PER_DAY) as i64);
+    let rem = secs % SECONDS_PER_DAY;
+
+    return format!("{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}", y, m, d, rem / 3600, (rem % 3600) / 60, rem % 60, millis % 1000);
+}
+
+impl BackupManager {
+    pub fn new(backup_file_name: &PathBuf, store_id: &str, max_count: usize, max_age_days: u64) -> Self {
+        let dir = match backup_file_name.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+
+        let stem = match backup_file_name.file_stem().and_then(|s| s.to_str()) {
+            Some(s) => format!("{}-{}", s, store_tag(store_id)),
+            None => format!("rustpwman_last-{}", store_tag(store_id))
+        };
+
+        let ext = match backup_file_name.extension().and_then(|s| s.to_str()) {
+            Some(s) => String::from(s),
+            None => String::from("")
+        };
+
+        return BackupManager {
+            dir: dir,
+            stem: stem,
+            ext: ext,
+            // At least one backup is always kept
+            max_count: if max_count == 0 { 1 } else { max_count },
+            max_age_days: max_age_days
+        };
+    }
+
+    fn make_file_name(&self, time_stamp: &str) -> String {
+        if self.ext == "" {
+            return format!("{}-{}", self.stem, time_stamp);
+        }
+
+        return format!("{}-{}.{}", self.stem, time_stamp, self.ext);
+    }
+
+    // Returns the time stamp part of name if name is the name of a backup file
+    fn parse_file_name(&self, name: &str) -> Option<String> {
+        let prefix = format!("{}-", self.stem);
+        let suffix = if self.ext == "" { String::from("") } else { format!(".{}", self.ext) };
+
+        if !name.starts_with(&prefix) || !name.ends_with(&suffix) || (name.len() != prefix.len() + TIME_STAMP_LEN + suffix.len()) {
+            return None;
+        }
+
+        let stamp = &name[prefix.len()..prefix.len() + TIME_STAMP_LEN];
+        let well_formed = stamp.char_indices().all(|(i, c)| {
+            return if (i == 8) || (i == 15) { c == '-' } else { c.is_ascii_digit() };
+        });
+
+        if !well_formed {
+            return None;
+        }
+
+        return Some(String::from(stamp));
+    }
+
+    // Returns all existing backups. The newest backup is the first element.
+    pub fn list(&self) -> std::io::Result<Vec<BackupEntry>> {
+        let mut res: Vec<BackupEntry> = Vec::new();
+
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(res),
+            Err(e) => return Err(e)
+        };
+
+        for entry in entries {
+            let entry = entry?;
+
+            let name = match entry.file_name().to_str() {
+                Some(n) => String::from(n),
+                None => continue
+            };
+
+            if let Some(stamp) = self.parse_file_name(&name) {
+                let meta = entry.metadata()?;
+                if !meta.is_file() {
+                    continue;
+                }
+
+                res.push(BackupEntry { path: entry.path(), time_stamp: stamp, size: meta.len() });
+            }
+        }
+
+        res.sort_by(|a, b| b.time_stamp.cmp(&a.time_stamp));
+
+        return Ok(res);
+    }
+
+    // Stores data as a new backup unless it is identical to the newest backup and afterwards removes old backups
+    pub fn write(&self, data: &Vec<u8>) -> std::io::Result<()> {
+        let existing = self.list()?;
+
+        if let Some(newest) = existing.first() {
+            if (newest.size == data.len() as u64) && (fs::read(&newest.path)? == *data) {
+                return Ok(());
+            }
+        }
+
+        let mut millis = now_millis();
+        let mut file_name = self.dir.join(self.make_file_name(&format_time_stamp(millis)));
+
+        // Never overwrite an existing backup
+        while file_name.exists() {
+            millis += 1;
+            file_name = self.dir.join(self.make_file_name(&format_time_stamp(millis)));
+        }
+
+        let name_str = match file_name.to_str() {
+            Some(n) => n,
+            None => return Err(Error::new(ErrorKind::Other, "Backup file name is not valid UTF-8"))
+        };
+
+        persist::write_file_atomic(name_str, data)?;
+
+        return self.prune();
+    }
+
+    // Deletes all backups which exceed the configured number or age. The newest backup is never deleted.
+    pub fn prune(&self) -> std::io::Result<()> {
+        let existing = self.list()?;
+
+        let cut_off = match self.max_age_days {
+            0 => None,
+            days => {
+                let age = (days as u128) * (SECONDS_PER_DAY as u128) * 1000;
+                Some(format_time_stamp(now_millis().saturating_sub(age)))
+            }
+        };
+
+        for (i, entry) in existing.iter().enumerate() {
+            if i == 0 {
+                continue;
+            }
+
+            let too_old = match &cut_off {
+                Some(c) => entry.time_stamp < *c,
+                None => false
+            };
+
+            if (i >= self.max_count) || too_old {
+                fs::remove_file(&entry.path)?;
+            }
+        }
+
+        return Ok(());
+    }
+}
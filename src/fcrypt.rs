@@ -17,13 +17,13 @@ limitations under the License. */
 mod rijndael;
 mod chacha20;
 mod derivers;
+pub mod envelope;
 pub mod totpcalc;
 
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
-use std::io::BufWriter;
 use std::io::{Error, ErrorKind};
 use rand::Rng;
 
@@ -32,7 +32,7 @@ use cipher::consts::{U12, U16};
 use base64::prelude::*;
 use crate::persist::SendSyncPersister;
 use crate::pwgen::StrGetter;
-use aead::{Aead, KeyInit, AeadInOut, AeadCore, KeySizeUser};
+use aead::{Aead, KeyInit, AeadInOut, AeadCore, KeySizeUser, Payload};
 
 
 const DEFAULT_TAG_SIZE: usize = 16;
@@ -49,17 +49,49 @@ const CIP_CHACHA20: &str = "chacha20";
 
 pub const DEFAULT_KDF_ID: KdfId = KdfId::Argon2;
 
+// Files without a version field have been written by versions of rustpwman which did not record the cipher
+const FORMAT_VERSION_LEGACY: u32 = 1;
+const FORMAT_VERSION_CIPHER: u32 = 2;
+// Files of this version may record the cost parameters of the PBKDF
+const FORMAT_VERSION_KDF_PARAMS: u32 = 3;
+// In files of this version the header is authenticated as associated data of the AEAD cipher
+const FORMAT_VERSION_AUTH_HEADER: u32 = 4;
+// Files of this version may require a key file in addition to or instead of the password
+const FORMAT_VERSION_KEY_FILE: u32 = 5;
+// Starting with this version a file may be shared by several recipients, see envelope.rs
+const FORMAT_VERSION_ENVELOPE: u32 = 6;
+pub const FORMAT_VERSION: u32 = FORMAT_VERSION_ENVELOPE;
+
+// These cost parameters are used for files which do not record them
+const ARGON2_DEFAULT_M_COST: u32 = 15 * 1024; // 15 MiB
+const ARGON2_DEFAULT_T_COST: u32 = 2;
+const ARGON2_DEFAULT_P_COST: u32 = 1;
+const SCRYPT_DEFAULT_LOG_N: u8 = 15; // N = 2^15
+const SCRYPT_DEFAULT_R: u32 = 8;
+const SCRYPT_DEFAULT_P: u32 = 2;
+
+// Upper bounds which prevent a manipulated file or config from making key derivation exhaust memory or
+// run (nearly) forever. The parameters are checked before the header is authenticated, i.e. anyone who
+// can write the file chooses them.
+const ARGON2_MAX_M_COST: u32 = 2 * 1024 * 1024; // 2 GiB
+const ARGON2_MAX_T_COST: u32 = 10;
+const ARGON2_MAX_P_COST: u32 = 16;
+const SCRYPT_MIN_LOG_N: u8 = 10;
+const SCRYPT_MAX_LOG_N: u8 = 20; // 128 * r * 2^20 bytes, i.e. 2 GiB for r = 16
+const SCRYPT_MAX_R: u32 = 16;
+const SCRYPT_MAX_P: u32 = 4;
+
 #[macro_export]
 macro_rules! make_creator {
-    ($strct:ident) => {
+    ($strct:ident, $cip_id:expr) => {
         impl $strct {
             #![allow(dead_code)]
             pub fn new() -> $strct {
-                return $strct($crate::fcrypt::AeadContext::new_with_kdf($crate::fcrypt::derivers::sha256_deriver, $crate::fcrypt::KdfId::Sha256));
+                return $strct($crate::fcrypt::AeadContext::new_with_kdf($crate::fcrypt::derivers::sha256_deriver, $crate::fcrypt::KdfId::Sha256, $cip_id));
             }
 
             pub fn new_with_kdf(derive: $crate::fcrypt::KeyDeriver, deriver_id: $crate::fcrypt::KdfId) -> $strct {
-                return $strct($crate::fcrypt::AeadContext::new_with_kdf(derive, deriver_id));
+                return $strct($crate::fcrypt::AeadContext::new_with_kdf(derive, deriver_id, $cip_id));
             }
         }        
     }
@@ -88,6 +120,30 @@ macro_rules! cryptor_impl {
             fn algo_name(&self) -> &'static str {
                 return $name;
             }
+
+            fn cipher_id(&self) -> $crate::fcrypt::CipherId {
+                return self.0.cipher_id;
+            }
+
+            fn kdf_id(&self) -> $crate::fcrypt::KdfId {
+                return self.0.kdf_id;
+            }
+
+            fn kdf_params(&self) -> $crate::fcrypt::KdfParams {
+                return self.0.kdf_params;
+            }
+
+            fn set_kdf_params(&mut self, params: $crate::fcrypt::KdfParams) {
+                self.0.kdf_params = params;
+            }
+
+            fn set_key_file(&mut self, key_file: Option<Vec<u8>>) {
+                self.0.key_file = key_file;
+            }
+
+            fn key_file_required(&self) -> bool {
+                return self.0.key_file_required;
+            }
         }        
     };
 }
@@ -100,12 +156,23 @@ pub trait Cryptor {
     fn to_dyn_writer(&self, writer: &mut dyn Write, data: &Vec<u8>) -> std::io::Result<()>;
     fn from_dyn_reader(&mut self, reader: &mut dyn Read)-> std::io::Result<Vec<u8>>;
     fn algo_name(&self) -> &'static str;
+    fn cipher_id(&self) -> CipherId;
+    fn kdf_id(&self) -> KdfId;
+    fn kdf_params(&self) -> KdfParams;
+    fn set_kdf_params(&mut self, params: KdfParams);
+    fn set_key_file(&mut self, key_file: Option<Vec<u8>>);
+    fn key_file_required(&self) -> bool;
+
+    // Returns the state of a shared file. None for files which are protected by a single password.
+    fn envelope(&self) -> Option<envelope::EnvelopeContext> {
+        return None;
+    }
 
     fn to_file(&self, data: &Vec<u8>, file_name: &str) -> std::io::Result<()> {
-        let file = File::create(file_name)?;
-        let mut w = BufWriter::new(file);
+        let mut res_data: Vec<u8> = vec![];
+        self.to_dyn_writer(&mut res_data, data)?;
 
-        return self.to_dyn_writer(&mut w, data);
+        return crate::persist::write_file_atomic(file_name, res_data.as_slice());
     }
 
     fn persist(&self, data: &Vec<u8>, p: &mut SendSyncPersister) -> std::io::Result<()> {
@@ -203,6 +270,9 @@ pub enum CipherId {
 }
 
 impl CipherId {
+    pub fn to_string(self) -> String {
+        return String::from(self.to_str())
+    }
 
     pub fn from_str(name: &str) -> Option<Self> {
         return match name {
@@ -244,11 +314,149 @@ impl StrGetter for CipherId {
 
 }
 
+// Cost parameters of a PBKDF. In files and in the config they are represented as a PHC style string,
+// i.e. "m=15360,t=2,p=1" for argon2 and "ln=15,r=8,p=2" for scrypt.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum KdfParams {
+    Argon2 { m_cost: u32, t_cost: u32, p_cost: u32 },
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    None
+}
+
+impl KdfParams {
+    pub fn default_for(id: KdfId) -> KdfParams {
+        return match id {
+            #[cfg(feature = "withscrypt")]
+            KdfId::Scrypt => KdfParams::Scrypt { log_n: SCRYPT_DEFAULT_LOG_N, r: SCRYPT_DEFAULT_R, p: SCRYPT_DEFAULT_P },
+            KdfId::Argon2 => KdfParams::Argon2 { m_cost: ARGON2_DEFAULT_M_COST, t_cost: ARGON2_DEFAULT_T_COST, p_cost: ARGON2_DEFAULT_P_COST },
+            KdfId::Sha256 => KdfParams::None
+        };
+    }
+
+    // Returns true if these parameters can be used with the PBKDF specified by id
+    pub fn is_for(&self, id: KdfId) -> bool {
+        return std::mem::discriminant(self) == std::mem::discriminant(&KdfParams::default_for(id));
+    }
+
+    pub fn to_string(self) -> String {
+        return match self {
+            KdfParams::Argon2 { m_cost, t_cost, p_cost } => format!("m={},t={},p={}", m_cost, t_cost, p_cost),
+            KdfParams::Scrypt { log_n, r, p } => format!("ln={},r={},p={}", log_n, r, p),
+            KdfParams::None => String::from("")
+        };
+    }
+
+    pub fn from_str(id: KdfId, params: &str) -> std::io::Result<KdfParams> {
+        let mut values: std::collections::HashMap<&str, u32> = std::collections::HashMap::new();
+
+        for i in params.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            let (k, v) = match i.split_once('=') {
+                Some(kv) => kv,
+                None => return Err(Error::new(ErrorKind::Other, format!("Malformed KDF parameter '{}'", i)))
+            };
+
+            let value: u32 = match v.trim().parse() {
+                Ok(n) => n,
+                Err(_) => return Err(Error::new(ErrorKind::Other, format!("KDF parameter '{}' is not a number", k.trim())))
+            };
+
+            if values.insert(k.trim(), value).is_some() {
+                return Err(Error::new(ErrorKind::Other, format!("KDF parameter '{}' specified more than once", k.trim())));
+            }
+        }
+
+        let mut get_value = |name: &str| -> std::io::Result<u32> {
+            return match values.remove(name) {
+                Some(v) => Ok(v),
+                None => Err(Error::new(ErrorKind::Other, format!("KDF parameter '{}' is missing", name)))
+            };
+        };
+
+        let res = match KdfParams::default_for(id) {
+            KdfParams::Argon2 { .. } => KdfParams::Argon2 { m_cost: get_value("m")?, t_cost: get_value("t")?, p_cost: get_value("p")? },
+            KdfParams::Scrypt { .. } => {
+                let log_n = get_value("ln")?;
+                if log_n > (u8::MAX as u32) {
+                    return Err(Error::new(ErrorKind::Other, "scrypt parameter ln is too large"));
+                }
+
+                KdfParams::Scrypt { log_n: log_n as u8, r: get_value("r")?, p: get_value("p")? }
+            },
+            KdfParams::None => KdfParams::None
+        };
+
+        if let Some(k) = values.keys().next() {
+            return Err(Error::new(ErrorKind::Other, format!("Unknown KDF parameter '{}' for {}", k, id.to_str())));
+        }
+
+        res.validate()?;
+
+        return Ok(res);
+    }
+
+    pub fn validate(&self) -> std::io::Result<()> {
+        match *self {
+            KdfParams::Argon2 { m_cost, t_cost, p_cost } => {
+                if (p_cost == 0) || (p_cost > ARGON2_MAX_P_COST) {
+                    return Err(Error::new(ErrorKind::Other, format!("argon2 parameter p has to be between 1 and {}", ARGON2_MAX_P_COST)));
+                }
+
+                if (t_cost == 0) || (t_cost > ARGON2_MAX_T_COST) {
+                    return Err(Error::new(ErrorKind::Other, format!("argon2 parameter t has to be between 1 and {}", ARGON2_MAX_T_COST)));
+                }
+
+                // argon2 needs at least 8 KiB per lane
+                if (m_cost < 8 * p_cost) || (m_cost > ARGON2_MAX_M_COST) {
+                    return Err(Error::new(ErrorKind::Other, format!("argon2 parameter m has to be between {} and {}", 8 * p_cost, ARGON2_MAX_M_COST)));
+                }
+            },
+            KdfParams::Scrypt { log_n, r, p } => {
+                if !(SCRYPT_MIN_LOG_N..=SCRYPT_MAX_LOG_N).contains(&log_n) {
+                    return Err(Error::new(ErrorKind::Other, format!("scrypt parameter ln has to be between {} and {}", SCRYPT_MIN_LOG_N, SCRYPT_MAX_LOG_N)));
+                }
+
+                if (r == 0) || (r > SCRYPT_MAX_R) {
+                    return Err(Error::new(ErrorKind::Other, format!("scrypt parameter r has to be between 1 and {}", SCRYPT_MAX_R)));
+                }
+
+                if (p == 0) || (p > SCRYPT_MAX_P) {
+                    return Err(Error::new(ErrorKind::Other, format!("scrypt parameter p has to be between 1 and {}", SCRYPT_MAX_P)));
+                }
+            },
+            KdfParams::None => ()
+        }
+
+        return Ok(());
+    }
+}
+
+fn legacy_format_version() -> u32 {
+    return FORMAT_VERSION_LEGACY;
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct CryptedJson {
+    #[serde(rename(deserialize = "Version"))]
+    #[serde(rename(serialize = "Version"))]
+    #[serde(default = "legacy_format_version")]
+    version: u32,
     #[serde(rename(deserialize = "PbKdf"))]
     #[serde(rename(serialize = "PbKdf"))]
     pbkdf: String,    
+    #[serde(rename(deserialize = "Cipher"))]
+    #[serde(rename(serialize = "Cipher"))]
+    #[serde(default)]
+    cipher: Option<String>,
+    #[serde(rename(deserialize = "KdfParams"))]
+    #[serde(rename(serialize = "KdfParams"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf_params: Option<String>,
+    #[serde(rename(deserialize = "KeyFile"))]
+    #[serde(rename(serialize = "KeyFile"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    key_file: bool,
     #[serde(rename(deserialize = "Salt"))]
     #[serde(rename(serialize = "Salt"))]
     salt: String,
@@ -260,13 +468,124 @@ struct CryptedJson {
     data: String
 }
 
-pub type KeyDeriver = fn(&Vec<u8>, &str) -> Vec<u8>;
+// Everything in CryptedJson except the ciphertext. Its serialized form is used as associated data.
+#[derive(Serialize, Debug)]
+struct AuthenticatedHeader<'a> {
+    #[serde(rename(serialize = "Version"))]
+    version: u32,
+    #[serde(rename(serialize = "PbKdf"))]
+    pbkdf: &'a str,
+    #[serde(rename(serialize = "Cipher"))]
+    cipher: &'a str,
+    #[serde(rename(serialize = "KdfParams"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf_params: Option<String>,
+    #[serde(rename(serialize = "KeyFile"))]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    key_file: bool,
+    #[serde(rename(serialize = "Salt"))]
+    salt: String,
+    #[serde(rename(serialize = "Nonce"))]
+    nonce: String
+}
+
+// The part of the header which is needed to decide how an encrypted file has to be processed
+#[derive(Deserialize, Debug)]
+struct ContainerHeader {
+    #[serde(rename(deserialize = "Version"))]
+    #[serde(default = "legacy_format_version")]
+    version: u32,
+    #[serde(rename(deserialize = "PbKdf"))]
+    #[serde(default)]
+    pbkdf: Option<String>,
+    #[serde(rename(deserialize = "Cipher"))]
+    #[serde(default)]
+    cipher: Option<String>,
+    #[serde(rename(deserialize = "KeyFile"))]
+    #[serde(default)]
+    key_file: bool,
+    #[serde(rename(deserialize = "Recipients"))]
+    #[serde(default)]
+    recipients: Option<Vec<serde::de::IgnoredAny>>
+}
+
+pub struct ContainerInfo {
+    pub version: u32,
+    // Shared files record a PBKDF per recipient. For them this is DEFAULT_KDF_ID.
+    pub kdf_id: KdfId,
+    // None for files which do not record the cipher that was used to encrypt them
+    pub cipher_id: Option<CipherId>,
+    // True if a key file is needed to decrypt the file
+    pub key_file: bool,
+    // True if the file is shared by several recipients
+    pub shared: bool
+}
+
+// Parses the header of a serialized encrypted file without decrypting it
+pub fn peek_container_info(data: &[u8]) -> std::io::Result<ContainerInfo> {
+    let header: ContainerHeader = serde_json::from_slice(data)?;
+
+    if header.version > FORMAT_VERSION {
+        return Err(Error::new(ErrorKind::Other, format!("Unsupported file format version {}", header.version)));
+    }
+
+    let shared = header.recipients.is_some();
+
+    let kdf_id = match (&header.pbkdf, shared) {
+        (None, true) => DEFAULT_KDF_ID,
+        (None, false) => return Err(Error::new(ErrorKind::Other, "Key derivation function missing")),
+        (Some(p), _) => {
+            match KdfId::from_str(p) {
+                Some(id) => id,
+                None => return Err(Error::new(ErrorKind::Other, format!("Unknown key derivation function '{}'", p)))
+            }
+        }
+    };
+
+    let cipher_id = match header.cipher {
+        None => None,
+        Some(c) => {
+            match CipherId::from_str(&c) {
+                Some(id) => Some(id),
+                None => return Err(Error::new(ErrorKind::Other, format!("Unknown cipher '{}'", c)))
+            }
+        }
+    };
+
+    return Ok(ContainerInfo {
+        version: header.version,
+        kdf_id: kdf_id,
+        cipher_id: cipher_id,
+        key_file: header.key_file,
+        shared: shared
+    });
+}
+
+// Reads a key file and returns the value which is mixed into the key derived from the password
+pub fn load_key_file(file_name: &str) -> std::io::Result<Vec<u8>> {
+    let data = std::fs::read(file_name)?;
+
+    if data.len() == 0 {
+        return Err(Error::new(ErrorKind::Other, format!("Key file '{}' is empty", file_name)));
+    }
+
+    return Ok(derivers::hash_key_file(&data));
+}
+
+pub type KeyDeriver = fn(&Vec<u8>, &str, &KdfParams) -> Vec<u8>;
 
 pub struct AeadContext {
     pub salt: Vec<u8>,
     pub nonce: Vec<u8>,
     pub kdf: KeyDeriver,
-    pub kdf_id: KdfId
+    pub kdf_id: KdfId,
+    pub kdf_params: KdfParams,
+    // Hash of the key file which is used in addition to the password
+    pub key_file: Option<Vec<u8>>,
+    // Set when a file was read which can only be decrypted with a key file
+    pub key_file_required: bool,
+    pub cipher_id: CipherId,
+    pub version: u32
 } 
 
 // This struct knows how to generarate, maintain, parse, serialze and deserialize a data structure which can be used to
@@ -274,19 +593,24 @@ pub struct AeadContext {
 impl AeadContext {
     #![allow(dead_code)]
     pub fn new() -> AeadContext {
-        return AeadContext::new_with_kdf_id(derivers::sha256_deriver, KdfId::Sha256)
+        return AeadContext::new_with_kdf_id(derivers::sha256_deriver, KdfId::Sha256, CipherId::Aes256Gcm)
     }
 
-    pub fn new_with_kdf_id(derive: KeyDeriver, deriver_id: KdfId) -> AeadContext {
-        return AeadContext::new_with_kdf(derive, deriver_id);
+    pub fn new_with_kdf_id(derive: KeyDeriver, deriver_id: KdfId, cipher_id: CipherId) -> AeadContext {
+        return AeadContext::new_with_kdf(derive, deriver_id, cipher_id);
     }
 
-    pub fn new_with_kdf(derive: KeyDeriver, deriver_id: KdfId) -> AeadContext {
+    pub fn new_with_kdf(derive: KeyDeriver, deriver_id: KdfId, cipher_id: CipherId) -> AeadContext {
         let mut res = AeadContext {
             salt: vec![0; DEFAULT_SALT_SIZE],
             nonce: vec![0; DEFAULT_NONCE_SIZE],
             kdf: derive,
-            kdf_id: deriver_id
+            kdf_id: deriver_id,
+            kdf_params: KdfParams::default_for(deriver_id),
+            key_file: None,
+            key_file_required: false,
+            cipher_id: cipher_id,
+            version: FORMAT_VERSION
         };
 
         res.fill_random();
@@ -297,10 +621,27 @@ impl AeadContext {
     pub fn from_reader<T: Read>(&mut self, reader: T) -> std::io::Result<Vec<u8>> {
         let json_struct: CryptedJson = serde_json::from_reader(reader)?;
 
+        if json_struct.version > FORMAT_VERSION {
+            return Err(Error::new(ErrorKind::Other, format!("Unsupported file format version {}", json_struct.version)));
+        }
+
         if json_struct.pbkdf != self.kdf_id.to_string() {
             return Err(Error::new(ErrorKind::Other, format!("Key derivation function mismatch. {} was used not {}", &json_struct.pbkdf, &self.kdf_id.to_string())));
         }
 
+        // Files which do not record the cipher can only be checked by attempting to decrypt them
+        if let Some(c) = &json_struct.cipher {
+            if *c != self.cipher_id.to_str() {
+                return Err(Error::new(ErrorKind::Other, format!("Cipher mismatch. {} was used not {}", c, self.cipher_id.to_str())));
+            }
+        }
+
+        // Files which do not record the KDF parameters have been created with the default values
+        let kdf_params = match &json_struct.kdf_params {
+            Some(p) => KdfParams::from_str(self.kdf_id, p)?,
+            None => KdfParams::default_for(self.kdf_id)
+        };
+
         let salt = match BASE64_STANDARD.decode(&json_struct.salt) {
             Ok(s) => s,
             Err(_) => {
@@ -321,6 +662,9 @@ impl AeadContext {
 
         self.salt = salt;
         self.nonce = nonce;
+        self.version = json_struct.version;
+        self.kdf_params = kdf_params;
+        self.key_file_required = json_struct.key_file;
 
         let data = match BASE64_STANDARD.decode(&json_struct.data) {
             Ok(s) => s,
@@ -336,9 +680,42 @@ impl AeadContext {
         return Ok(data);
     }
 
+    fn kdf_params_str(&self) -> Option<String> {
+        return match self.kdf_params {
+            KdfParams::None => None,
+            p => Some(p.to_string())
+        };
+    }
+
+    // Returns the associated data which has to be used with the header values currently stored in this context.
+    // Files written before FORMAT_VERSION_AUTH_HEADER do not use associated data.
+    pub fn associated_data(&self) -> Vec<u8> {
+        if self.version < FORMAT_VERSION_AUTH_HEADER {
+            return Vec::new();
+        }
+
+        let kdf_id = self.kdf_id.to_string();
+        let header = AuthenticatedHeader {
+            version: self.version,
+            pbkdf: &kdf_id,
+            cipher: self.cipher_id.to_str(),
+            kdf_params: self.kdf_params_str(),
+            key_file: self.key_file_required,
+            salt: BASE64_STANDARD.encode(&self.salt),
+            nonce: BASE64_STANDARD.encode(&self.nonce)
+        };
+
+        // Serializing this struct can not fail
+        return serde_json::to_vec(&header).unwrap();
+    }
+
     pub fn to_writer<T: Write>(&self, writer: T, data: &Vec<u8>) -> std::io::Result<()> {
         let j = CryptedJson {
+            version: FORMAT_VERSION,
             pbkdf: self.kdf_id.to_string(),
+            cipher: Some(self.cipher_id.to_string()),
+            kdf_params: self.kdf_params_str(),
+            key_file: self.key_file_required,
             salt: BASE64_STANDARD.encode(&self.salt),
             nonce: BASE64_STANDARD.encode(&self.nonce),
             data: BASE64_STANDARD.encode(data)
@@ -372,7 +749,24 @@ impl AeadContext {
     }
 
     pub fn regenerate_key(&self, password: &str) -> Vec<u8> {
-        return (self.kdf)(&self.salt, password);
+        let key = (self.kdf)(&self.salt, password, &self.kdf_params);
+
+        return match &self.key_file {
+            Some(k) => derivers::mix_key_file(&key, k),
+            None => key
+        };
+    }
+
+    pub fn check_key_file(&self) -> std::io::Result<()> {
+        if self.key_file_required && self.key_file.is_none() {
+            return Err(Error::new(ErrorKind::Other, "A key file is needed to decrypt this file"));
+        }
+
+        if !self.key_file_required && self.key_file.is_some() {
+            return Err(Error::new(ErrorKind::Other, "This file is not protected by a key file"));
+        }
+
+        return Ok(());
     }
 
     pub fn check_min_size(&self, len: usize) -> std::io::Result<()> {
@@ -385,6 +779,9 @@ impl AeadContext {
 
     pub fn prepare_params_encrypt(&mut self, password: &str) -> (Vec<u8>, Vec<u8>) {
         self.fill_random();
+        // Data is always written in the current format
+        self.version = FORMAT_VERSION;
+        self.key_file_required = self.key_file.is_some();
 
         let raw_32_byte_key = self.regenerate_key(password);
 
@@ -411,12 +808,13 @@ impl AeadContext {
 // implement the corresponding RustCrypto traits. They are therefore helper functions in order to implement the Cryptor trait in this case.
 fn encrypt_aead<T: Aead + AeadInOut + AeadCore<NonceSize = U12, TagSize = U16> + KeyInit>(ctx: &mut AeadContext, password: &str, data: &Vec<u8>, algo_name: &str) -> std::io::Result<Vec<u8>> {
     let (key, nonce) = ctx.prepare_params_encrypt(password);
+    let associated_data = ctx.associated_data();
     let nonce_help = cipher::Array::<u8, <T as AeadCore>::NonceSize>::try_from(nonce.as_slice()).unwrap();
     let key_help = cipher::Array::<u8, <T as KeySizeUser>::KeySize>::try_from(&key[0..T::key_size()]).unwrap();
 
     let cipher = T::new(&key_help);
 
-    return match cipher.encrypt(&nonce_help, data.as_slice()) {
+    return match cipher.encrypt(&nonce_help, Payload { msg: data.as_slice(), aad: associated_data.as_slice() }) {
         Err(_) => return Err(Error::new(ErrorKind::Other, format!("{} {}", algo_name, "Encryption error"))),
         Ok(d) => Ok(d)
     };
@@ -424,7 +822,8 @@ fn encrypt_aead<T: Aead + AeadInOut + AeadCore<NonceSize = U12, TagSize = U16> +
 
 fn decrypt_aead<T: Aead + AeadInOut + AeadCore<NonceSize = U12, TagSize = U16> + KeyInit>(ctx: &mut AeadContext, password: &str, data: &Vec<u8>, algo_name: &str) -> std::io::Result<Vec<u8>> {
     ctx.check_min_size(data.len())?;
-    let associated_data: [u8; 0] = [];
+    ctx.check_key_file()?;
+    let associated_data = ctx.associated_data();
 
     let (key, nonce, tag, mut dec_buffer) = ctx.prepare_params_decrypt(password, data);
 
@@ -436,6 +835,10 @@ fn decrypt_aead<T: Aead + AeadInOut + AeadCore<NonceSize = U12, TagSize = U16> +
     let _ = match cipher.decrypt_inout_detached(&nonce_help, &associated_data, dec_buffer.as_mut_slice().into(), &tag_help) {
         Ok(_) => (),
         Err(_) => {
+            if ctx.version >= FORMAT_VERSION_AUTH_HEADER {
+                return Err(Error::new(ErrorKind::Other, format!("{} {}", algo_name, "Decryption error. Either the password is wrong or the file header has been modified")));
+            }
+
             return Err(Error::new(ErrorKind::Other, format!("{} {}", algo_name, "Decryption error")));
         }
     };
@@ -19,5 +19,5 @@ use chacha20poly1305::ChaCha20Poly1305;
 const ALGO_CHACHA20: &str = "ChaCha20 Poly-1305";
 
 pub struct ChaCha20Poly1305Context(AeadContext);
-crate::make_creator!(ChaCha20Poly1305Context);
+crate::make_creator!(ChaCha20Poly1305Context, crate::fcrypt::CipherId::ChaCha20Poly1305);
 crate::cryptor_impl!(ChaCha20Poly1305Context, ChaCha20Poly1305, ALGO_CHACHA20);
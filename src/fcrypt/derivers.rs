@@ -14,17 +14,23 @@ limitations under the License. */
 
 
 use sha2::{Sha256, Digest};
+use hmac::{Hmac, KeyInit, Mac};
 #[cfg(feature = "withscrypt")]
 use scrypt::scrypt;
 use argon2;
+use super::KdfParams;
 
 
-pub fn argon2id_deriver(salt: &Vec<u8>, password: &str) -> Vec<u8> {
+pub fn argon2id_deriver(salt: &Vec<u8>, password: &str, kdf_params: &KdfParams) -> Vec<u8> {
     let mut aes_key: [u8; 32] = [0; 32];
     //let no_ad: [u8; 0] = [];
 
-    let params = argon2::Params::new(15*1024, 2, 1, Some(32)).unwrap();
-    // 15 MiB, t=2, p=1
+    let (m_cost, t_cost, p_cost) = match *kdf_params {
+        KdfParams::Argon2 { m_cost, t_cost, p_cost } => (m_cost, t_cost, p_cost),
+        _ => panic!("Wrong KDF parameters for argon2 {:?}", kdf_params) // Should not happen
+    };
+
+    let params = argon2::Params::new(m_cost, t_cost, p_cost, Some(32)).unwrap();
     let ctx = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
     ctx.hash_password_into(password.as_bytes(), &salt, &mut aes_key).unwrap();
     let mut res:Vec<u8> = Vec::new();
@@ -34,9 +40,13 @@ pub fn argon2id_deriver(salt: &Vec<u8>, password: &str) -> Vec<u8> {
 }
 
 #[cfg(feature = "withscrypt")]
-pub fn scrypt_deriver(salt: &Vec<u8>, password: &str) -> Vec<u8> {
-    // N = 32768 = 2^15, r=8, p=2
-    let parms = scrypt::Params::new(15, 8, 2).unwrap();
+pub fn scrypt_deriver(salt: &Vec<u8>, password: &str, kdf_params: &KdfParams) -> Vec<u8> {
+    let (log_n, r, p) = match *kdf_params {
+        KdfParams::Scrypt { log_n, r, p } => (log_n, r, p),
+        _ => panic!("Wrong KDF parameters for scrypt {:?}", kdf_params) // Should not happen
+    };
+
+    let parms = scrypt::Params::new(log_n, r, p).unwrap();
     let mut aes_key: [u8; 32] = [0; 32];
 
     scrypt(password.as_bytes(), salt.as_slice(), &parms, &mut aes_key).unwrap();
@@ -46,7 +56,7 @@ pub fn scrypt_deriver(salt: &Vec<u8>, password: &str) -> Vec<u8> {
     return res;
 }
 
-pub fn sha256_deriver(salt: &Vec<u8>, password: &str) -> Vec<u8> {
+pub fn sha256_deriver(salt: &Vec<u8>, password: &str, _kdf_params: &KdfParams) -> Vec<u8> {
     let mut sha_256: Sha256 = Sha256::new();
 
     sha_256.update(password);
@@ -55,4 +65,20 @@ pub fn sha256_deriver(salt: &Vec<u8>, password: &str) -> Vec<u8> {
     let hash_res = sha_256.finalize();
 
     return hash_res.to_vec();
+}
+
+pub fn hash_key_file(data: &Vec<u8>) -> Vec<u8> {
+    let mut sha_256: Sha256 = Sha256::new();
+
+    sha_256.update(data);
+
+    return sha_256.finalize().to_vec();
+}
+
+// Combines the key derived from the password with the hash of a key file. The result has the same length as key.
+pub fn mix_key_file(key: &Vec<u8>, key_file_hash: &Vec<u8>) -> Vec<u8> {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(key_file_hash.as_slice()).expect("This should not happen: HMAC key length mismatch");
+    hmac.update(key.as_slice());
+
+    return hmac.finalize().into_bytes().to_vec();
 }
\ No newline at end of file
@@ -0,0 +1,641 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+// A shared file encrypts its data with a random data key. This data key is wrapped separately for each
+// recipient, either under a key derived from a password or under a key agreed upon via X25519.
+
+use std::io::{Error, ErrorKind, Read, Write};
+
+use serde::{Serialize, Deserialize};
+use base64::prelude::*;
+use rand::Rng;
+use cipher::consts::{U12, U16};
+use aead::{Aead, AeadCore, KeyInit, KeySizeUser, Payload};
+use aes_gcm::AesGcm;
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+use super::{Cryptor, CipherId, KdfId, KdfParams, StrGetter};
+use super::{DEFAULT_NONCE_SIZE, DEFAULT_SALT_SIZE, DEFAULT_TAG_SIZE, FORMAT_VERSION, FORMAT_VERSION_ENVELOPE};
+
+pub const RECIPIENT_PASSWORD: &str = "password";
+pub const RECIPIENT_X25519: &str = "x25519";
+
+const DATA_KEY_SIZE: usize = 32;
+const X25519_KEY_SIZE: usize = 32;
+const X25519_WRAP_INFO: &[u8] = b"rustpwman x25519 key wrap";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Recipient {
+    #[serde(rename(deserialize = "Name"))]
+    #[serde(rename(serialize = "Name"))]
+    name: String,
+    #[serde(rename(deserialize = "Type"))]
+    #[serde(rename(serialize = "Type"))]
+    recipient_type: String,
+    #[serde(rename(deserialize = "PbKdf"))]
+    #[serde(rename(serialize = "PbKdf"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pbkdf: Option<String>,
+    #[serde(rename(deserialize = "KdfParams"))]
+    #[serde(rename(serialize = "KdfParams"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf_params: Option<String>,
+    #[serde(rename(deserialize = "Salt"))]
+    #[serde(rename(serialize = "Salt"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(rename(deserialize = "PublicKey"))]
+    #[serde(rename(serialize = "PublicKey"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
+    #[serde(rename(deserialize = "EphemeralKey"))]
+    #[serde(rename(serialize = "EphemeralKey"))]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ephemeral_key: Option<String>,
+    #[serde(rename(deserialize = "Nonce"))]
+    #[serde(rename(serialize = "Nonce"))]
+    nonce: String,
+    #[serde(rename(deserialize = "Key"))]
+    #[serde(rename(serialize = "Key"))]
+    wrapped_key: String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EnvelopeJson {
+    #[serde(rename(deserialize = "Version"))]
+    #[serde(rename(serialize = "Version"))]
+    version: u32,
+    #[serde(rename(deserialize = "Cipher"))]
+    #[serde(rename(serialize = "Cipher"))]
+    cipher: String,
+    #[serde(rename(deserialize = "Recipients"))]
+    #[serde(rename(serialize = "Recipients"))]
+    recipients: Vec<Recipient>,
+    #[serde(rename(deserialize = "Nonce"))]
+    #[serde(rename(serialize = "Nonce"))]
+    nonce: String,
+    #[serde(rename(deserialize = "Data"))]
+    #[serde(rename(serialize = "Data"))]
+    data: String
+}
+
+// Everything in EnvelopeJson except the ciphertext. Its serialized form is used as associated data.
+#[derive(Serialize, Debug)]
+struct EnvelopeHeader<'a> {
+    #[serde(rename(serialize = "Version"))]
+    version: u32,
+    #[serde(rename(serialize = "Cipher"))]
+    cipher: &'a str,
+    #[serde(rename(serialize = "Recipients"))]
+    recipients: &'a Vec<Recipient>,
+    #[serde(rename(serialize = "Nonce"))]
+    nonce: String
+}
+
+fn decode(data: &str) -> std::io::Result<Vec<u8>> {
+    return match BASE64_STANDARD.decode(data) {
+        Ok(d) => Ok(d),
+        Err(_) => Err(Error::new(ErrorKind::Other, "Base64 decode error"))
+    };
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut res = vec![0; len];
+    rand::rng().fill_bytes(res.as_mut_slice());
+
+    return res;
+}
+
+fn seal_with<T: Aead + AeadCore<NonceSize = U12, TagSize = U16> + KeyInit>(key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    let nonce_help = cipher::Array::<u8, <T as AeadCore>::NonceSize>::try_from(nonce).unwrap();
+    let key_help = cipher::Array::<u8, <T as KeySizeUser>::KeySize>::try_from(&key[0..T::key_size()]).unwrap();
+
+    return match T::new(&key_help).encrypt(&nonce_help, Payload { msg: msg, aad: aad }) {
+        Ok(d) => Ok(d),
+        Err(_) => Err(Error::new(ErrorKind::Other, "Encryption error"))
+    };
+}
+
+fn open_with<T: Aead + AeadCore<NonceSize = U12, TagSize = U16> + KeyInit>(key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    if (nonce.len() != DEFAULT_NONCE_SIZE) || (msg.len() < DEFAULT_TAG_SIZE) {
+        return Err(Error::new(ErrorKind::Other, "Malformed ciphertext"));
+    }
+
+    let nonce_help = cipher::Array::<u8, <T as AeadCore>::NonceSize>::try_from(nonce).unwrap();
+    let key_help = cipher::Array::<u8, <T as KeySizeUser>::KeySize>::try_from(&key[0..T::key_size()]).unwrap();
+
+    return match T::new(&key_help).decrypt(&nonce_help, Payload { msg: msg, aad: aad }) {
+        Ok(d) => Ok(d),
+        Err(_) => Err(Error::new(ErrorKind::Other, "Decryption error"))
+    };
+}
+
+fn seal(cipher_id: CipherId, key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    return match cipher_id {
+        CipherId::Aes256Gcm => seal_with::<AesGcm<aes::Aes256, U12>>(key, nonce, aad, msg),
+        CipherId::Aes192Gcm => seal_with::<AesGcm<aes::Aes192, U12>>(key, nonce, aad, msg),
+        CipherId::ChaCha20Poly1305 => seal_with::<ChaCha20Poly1305>(key, nonce, aad, msg)
+    };
+}
+
+fn open(cipher_id: CipherId, key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    return match cipher_id {
+        CipherId::Aes256Gcm => open_with::<AesGcm<aes::Aes256, U12>>(key, nonce, aad, msg),
+        CipherId::Aes192Gcm => open_with::<AesGcm<aes::Aes192, U12>>(key, nonce, aad, msg),
+        CipherId::ChaCha20Poly1305 => open_with::<ChaCha20Poly1305>(key, nonce, aad, msg)
+    };
+}
+
+fn parse_x25519_key(key: &str) -> std::io::Result<[u8; X25519_KEY_SIZE]> {
+    let raw = decode(key.trim())?;
+
+    return match <[u8; X25519_KEY_SIZE]>::try_from(raw.as_slice()) {
+        Ok(k) => Ok(k),
+        Err(_) => Err(Error::new(ErrorKind::Other, "An X25519 key has to be 32 bytes long"))
+    };
+}
+
+// The key encryption key is derived from the shared secret and both public keys
+fn x25519_kek(shared: &[u8], ephemeral_public: &[u8], recipient_public: &[u8]) -> Vec<u8> {
+    let mut salt = ephemeral_public.to_vec();
+    salt.extend_from_slice(recipient_public);
+
+    let mut kek = vec![0; DATA_KEY_SIZE];
+    // Can not fail as the output length is much smaller than 255 times the hash length
+    Hkdf::<Sha256>::new(Some(salt.as_slice()), shared).expand(X25519_WRAP_INFO, kek.as_mut_slice()).unwrap();
+
+    return kek;
+}
+
+// Creates a new X25519 key pair. The secret key and the public key are returned in base64 encoding.
+pub fn generate_identity() -> (String, String) {
+    let mut raw = random_bytes(X25519_KEY_SIZE);
+    let secret = StaticSecret::from(<[u8; X25519_KEY_SIZE]>::try_from(raw.as_slice()).unwrap());
+    raw.zeroize();
+
+    let public = PublicKey::from(&secret);
+
+    return (BASE64_STANDARD.encode(secret.as_bytes()), BASE64_STANDARD.encode(public.as_bytes()));
+}
+
+// Reads a secret X25519 key as written by generate_identity
+pub fn load_identity(file_name: &str) -> std::io::Result<Vec<u8>> {
+    let mut text = std::fs::read_to_string(file_name)?;
+    let res = parse_x25519_key(&text);
+    text.zeroize();
+
+    return Ok(res?.to_vec());
+}
+
+// Returns the base64 encoded public key which belongs to a secret key returned by load_identity
+pub fn public_key_of(identity: &Vec<u8>) -> std::io::Result<String> {
+    let raw = match <[u8; X25519_KEY_SIZE]>::try_from(identity.as_slice()) {
+        Ok(k) => k,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "An X25519 key has to be 32 bytes long"))
+    };
+
+    return Ok(BASE64_STANDARD.encode(PublicKey::from(&StaticSecret::from(raw)).as_bytes()));
+}
+
+#[derive(Clone)]
+pub struct EnvelopeContext {
+    cipher_id: CipherId,
+    nonce: Vec<u8>,
+    recipients: Vec<Recipient>,
+    // The key which encrypts the data. Empty until the file has been decrypted.
+    data_key: Vec<u8>,
+    // Name of the recipient whose secret has been used to open the file
+    opened_by: Option<String>,
+    // Secret X25519 key. If it is None the file is opened with a password.
+    identity: Option<Vec<u8>>,
+    // PBKDF which is used to wrap the data key for the password of opened_by
+    kdf_id: KdfId,
+    kdf_params: KdfParams,
+    key_file: Option<Vec<u8>>
+}
+
+impl Drop for EnvelopeContext {
+    fn drop(&mut self) {
+        self.data_key.zeroize();
+
+        if let Some(i) = &mut self.identity {
+            i.zeroize();
+        }
+    }
+}
+
+impl EnvelopeContext {
+    // Creates a context which is able to read a shared file
+    pub fn new(identity: Option<Vec<u8>>) -> EnvelopeContext {
+        return EnvelopeContext {
+            cipher_id: CipherId::Aes256Gcm,
+            nonce: vec![0; DEFAULT_NONCE_SIZE],
+            recipients: Vec::new(),
+            data_key: Vec::new(),
+            opened_by: None,
+            identity: identity,
+            kdf_id: super::DEFAULT_KDF_ID,
+            kdf_params: KdfParams::default_for(super::DEFAULT_KDF_ID),
+            key_file: None
+        };
+    }
+
+    // Creates a context for a new shared file. The password used to encrypt it is wrapped for owner.
+    pub fn create(cipher_id: CipherId, kdf_id: KdfId, kdf_params: KdfParams, owner: &str) -> EnvelopeContext {
+        let mut res = EnvelopeContext::new(None);
+
+        res.cipher_id = cipher_id;
+        res.kdf_id = kdf_id;
+        res.kdf_params = kdf_params;
+        res.data_key = random_bytes(DATA_KEY_SIZE);
+        res.opened_by = Some(String::from(owner));
+
+        return res;
+    }
+
+    pub fn set_cipher_id(&mut self, cipher_id: CipherId) {
+        self.cipher_id = cipher_id;
+    }
+
+    pub fn set_kdf(&mut self, kdf_id: KdfId, kdf_params: KdfParams) {
+        self.kdf_id = kdf_id;
+        self.kdf_params = kdf_params;
+    }
+
+    pub fn opened_by(&self) -> Option<String> {
+        return self.opened_by.clone();
+    }
+
+    // Returns the names and types of all recipients
+    pub fn recipients(&self) -> Vec<(String, String)> {
+        return self.recipients.iter().map(|r| (r.name.clone(), r.recipient_type.clone())).collect();
+    }
+
+    fn check_new_name(&self, name: &str) -> std::io::Result<()> {
+        if name.trim().len() == 0 {
+            return Err(Error::new(ErrorKind::Other, "Recipient name is empty"));
+        }
+
+        let exists = self.recipients.iter().any(|r| r.name == name) || (self.opened_by.as_deref() == Some(name));
+        if exists {
+            return Err(Error::new(ErrorKind::Other, format!("Recipient '{}' already exists", name)));
+        }
+
+        if self.data_key.len() == 0 {
+            return Err(Error::new(ErrorKind::Other, "Recipients can only be added after the file has been decrypted"));
+        }
+
+        return Ok(());
+    }
+
+    fn wrap_for_password(&self, name: &str, password: &str, kdf_id: KdfId, kdf_params: KdfParams) -> std::io::Result<Recipient> {
+        let salt = random_bytes(DEFAULT_SALT_SIZE);
+        let nonce = random_bytes(DEFAULT_NONCE_SIZE);
+        let (kdf, _) = kdf_id.to_named_func();
+
+        let mut kek = kdf(&salt, password, &kdf_params);
+        let wrapped = seal(CipherId::Aes256Gcm, &kek, &nonce, name.as_bytes(), &self.data_key);
+        kek.zeroize();
+
+        return Ok(Recipient {
+            name: String::from(name),
+            recipient_type: String::from(RECIPIENT_PASSWORD),
+            pbkdf: Some(kdf_id.to_string()),
+            kdf_params: match kdf_params { KdfParams::None => None, p => Some(p.to_string()) },
+            salt: Some(BASE64_STANDARD.encode(&salt)),
+            public_key: None,
+            ephemeral_key: None,
+            nonce: BASE64_STANDARD.encode(&nonce),
+            wrapped_key: BASE64_STANDARD.encode(wrapped?)
+        });
+    }
+
+    fn wrap_for_public_key(&self, name: &str, public_key: &str) -> std::io::Result<Recipient> {
+        let recipient_public = PublicKey::from(parse_x25519_key(public_key)?);
+        let ephemeral_secret = StaticSecret::from(<[u8; X25519_KEY_SIZE]>::try_from(random_bytes(X25519_KEY_SIZE).as_slice()).unwrap());
+        let ephemeral_public = PublicKey::from(&ephemeral_secret);
+
+        let shared = ephemeral_secret.diffie_hellman(&recipient_public);
+        if !shared.was_contributory() {
+            return Err(Error::new(ErrorKind::Other, "Invalid X25519 public key"));
+        }
+
+        let nonce = random_bytes(DEFAULT_NONCE_SIZE);
+        let mut kek = x25519_kek(shared.as_bytes(), ephemeral_public.as_bytes(), recipient_public.as_bytes());
+        let wrapped = seal(CipherId::Aes256Gcm, &kek, &nonce, name.as_bytes(), &self.data_key);
+        kek.zeroize();
+
+        return Ok(Recipient {
+            name: String::from(name),
+            recipient_type: String::from(RECIPIENT_X25519),
+            pbkdf: None,
+            kdf_params: None,
+            salt: None,
+            public_key: Some(BASE64_STANDARD.encode(recipient_public.as_bytes())),
+            ephemeral_key: Some(BASE64_STANDARD.encode(ephemeral_public.as_bytes())),
+            nonce: BASE64_STANDARD.encode(&nonce),
+            wrapped_key: BASE64_STANDARD.encode(wrapped?)
+        });
+    }
+
+    pub fn add_password_recipient(&mut self, name: &str, password: &str) -> std::io::Result<()> {
+        self.check_new_name(name)?;
+        let r = self.wrap_for_password(name, password, self.kdf_id, self.kdf_params)?;
+        self.recipients.push(r);
+
+        return Ok(());
+    }
+
+    pub fn add_public_key_recipient(&mut self, name: &str, public_key: &str) -> std::io::Result<()> {
+        self.check_new_name(name)?;
+        let r = self.wrap_for_public_key(name, public_key)?;
+        self.recipients.push(r);
+
+        return Ok(());
+    }
+
+    // Returns the recipients who are identified by a password and whose password is needed in order to remove
+    // the recipient name. The password of the recipient who opened the file is already known.
+    pub fn passwords_needed_for_removal(&self, name: &str) -> Vec<String> {
+        return self.recipients.iter()
+            .filter(|r| (r.recipient_type == RECIPIENT_PASSWORD) && (r.name != name) && (self.opened_by.as_deref() != Some(r.name.as_str())))
+            .map(|r| r.name.clone())
+            .collect();
+    }
+
+    // Removes a recipient and replaces the data key. This makes sure that the removed recipient can not decrypt
+    // future versions of the file even if they have kept a copy of the old data key. The new data key is wrapped
+    // for all remaining recipients. passwords has to contain the current password of all recipients returned by
+    // passwords_needed_for_removal. Nothing is changed if one of them is missing or wrong.
+    pub fn remove_recipient(&mut self, name: &str, passwords: &Vec<(String, String)>) -> std::io::Result<()> {
+        if !self.recipients.iter().any(|r| r.name == name) {
+            return Err(Error::new(ErrorKind::Other, format!("Recipient '{}' not found", name)));
+        }
+
+        if self.opened_by.as_deref() == Some(name) {
+            return Err(Error::new(ErrorKind::Other, "The recipient who opened the file can not be removed"));
+        }
+
+        if self.data_key.len() == 0 {
+            return Err(Error::new(ErrorKind::Other, "Recipients can only be removed after the file has been decrypted"));
+        }
+
+        // Check all passwords before anything is changed. The KDF of each recipient is kept.
+        let mut checked: Vec<(&str, &str, KdfId, KdfParams)> = Vec::new();
+
+        for r in self.recipients.iter().filter(|r| self.passwords_needed_for_removal(name).contains(&r.name)) {
+            let password = match passwords.iter().find(|(n, _)| *n == r.name) {
+                Some((_, p)) => p,
+                None => return Err(Error::new(ErrorKind::Other, format!("The password of recipient '{}' is needed to replace the data key", r.name)))
+            };
+
+            match self.unwrap_with_password(r, password) {
+                Ok((mut key, kdf_id, kdf_params)) => {
+                    key.zeroize();
+                    checked.push((&r.name, password, kdf_id, kdf_params));
+                },
+                Err(_) => return Err(Error::new(ErrorKind::Other, format!("Wrong password for recipient '{}'", r.name)))
+            }
+        }
+
+        let mut new_context = self.clone();
+        new_context.data_key.zeroize();
+        new_context.data_key = random_bytes(DATA_KEY_SIZE);
+        new_context.recipients.clear();
+
+        for r in self.recipients.iter().filter(|r| r.name != name) {
+            let new_r = match (&r.public_key, checked.iter().find(|c| c.0 == r.name)) {
+                (Some(k), _) => new_context.wrap_for_public_key(&r.name, k)?,
+                (None, Some((_, password, kdf_id, kdf_params))) => new_context.wrap_for_password(&r.name, password, *kdf_id, *kdf_params)?,
+                // The password of opened_by is wrapped again when the data is encrypted
+                (None, None) => r.clone()
+            };
+
+            new_context.recipients.push(new_r);
+        }
+
+        *self = new_context;
+
+        return Ok(());
+    }
+
+    fn unwrap_with_password(&self, r: &Recipient, password: &str) -> std::io::Result<(Vec<u8>, KdfId, KdfParams)> {
+        let kdf_id = match r.pbkdf.as_deref().and_then(KdfId::from_str) {
+            Some(id) => id,
+            None => return Err(Error::new(ErrorKind::Other, format!("Unknown key derivation function for recipient '{}'", r.name)))
+        };
+
+        let kdf_params = match &r.kdf_params {
+            Some(p) => KdfParams::from_str(kdf_id, p)?,
+            None => KdfParams::default_for(kdf_id)
+        };
+
+        let salt = decode(r.salt.as_deref().unwrap_or(""))?;
+        let (kdf, _) = kdf_id.to_named_func();
+
+        let mut kek = kdf(&salt, password, &kdf_params);
+        let res = open(CipherId::Aes256Gcm, &kek, &decode(&r.nonce)?, r.name.as_bytes(), &decode(&r.wrapped_key)?);
+        kek.zeroize();
+
+        return Ok((res?, kdf_id, kdf_params));
+    }
+
+    fn unwrap_with_identity(&self, r: &Recipient, identity: &Vec<u8>) -> std::io::Result<Vec<u8>> {
+        let secret = StaticSecret::from(<[u8; X25519_KEY_SIZE]>::try_from(identity.as_slice()).unwrap());
+        let ephemeral_public = PublicKey::from(parse_x25519_key(r.ephemeral_key.as_deref().unwrap_or(""))?);
+        let shared = secret.diffie_hellman(&ephemeral_public);
+
+        let mut kek = x25519_kek(shared.as_bytes(), ephemeral_public.as_bytes(), PublicKey::from(&secret).as_bytes());
+        let res = open(CipherId::Aes256Gcm, &kek, &decode(&r.nonce)?, r.name.as_bytes(), &decode(&r.wrapped_key)?);
+        kek.zeroize();
+
+        return res;
+    }
+
+    // Determines the data key by trying the identity or the password against all suitable recipients
+    fn unwrap_data_key(&mut self, password: &str) -> std::io::Result<()> {
+        if let Some(identity) = &self.identity {
+            let public_key = public_key_of(identity)?;
+
+            let r = match self.recipients.iter().find(|r| r.public_key.as_deref() == Some(public_key.as_str())) {
+                Some(r) => r,
+                None => return Err(Error::new(ErrorKind::Other, "This identity is not a recipient of the file"))
+            };
+
+            self.data_key = self.unwrap_with_identity(r, identity)?;
+            self.opened_by = Some(r.name.clone());
+
+            return Ok(());
+        }
+
+        for r in self.recipients.iter().filter(|r| r.recipient_type == RECIPIENT_PASSWORD) {
+            if let Ok((key, kdf_id, kdf_params)) = self.unwrap_with_password(r, password) {
+                self.opened_by = Some(r.name.clone());
+                self.data_key = key;
+                self.kdf_id = kdf_id;
+                self.kdf_params = kdf_params;
+
+                return Ok(());
+            }
+        }
+
+        return Err(Error::new(ErrorKind::Other, "The password does not belong to any recipient of the file"));
+    }
+
+    fn associated_data(&self) -> Vec<u8> {
+        let header = EnvelopeHeader {
+            version: FORMAT_VERSION_ENVELOPE,
+            cipher: self.cipher_id.to_str(),
+            recipients: &self.recipients,
+            nonce: BASE64_STANDARD.encode(&self.nonce)
+        };
+
+        // Serializing this struct can not fail
+        return serde_json::to_vec(&header).unwrap();
+    }
+
+    fn check_key_file(&self) -> std::io::Result<()> {
+        if self.key_file.is_some() {
+            return Err(Error::new(ErrorKind::Other, "Key files can not be used with shared files"));
+        }
+
+        return Ok(());
+    }
+}
+
+impl Cryptor for EnvelopeContext {
+    fn encrypt(&mut self, password: &str, data: &Vec<u8>) -> std::io::Result<Vec<u8>> {
+        self.check_key_file()?;
+
+        if self.data_key.len() == 0 {
+            return Err(Error::new(ErrorKind::Other, "Data key of shared file is unknown"));
+        }
+
+        // Wrap the data key again for the password. This also changes the password of opened_by if necessary.
+        if self.identity.is_none() {
+            if let Some(name) = self.opened_by.clone() {
+                let r = self.wrap_for_password(&name, password, self.kdf_id, self.kdf_params)?;
+
+                match self.recipients.iter().position(|i| i.name == name) {
+                    Some(p) => self.recipients[p] = r,
+                    None => self.recipients.insert(0, r)
+                }
+            }
+        }
+
+        self.nonce = random_bytes(DEFAULT_NONCE_SIZE);
+        let aad = self.associated_data();
+
+        return seal(self.cipher_id, &self.data_key, &self.nonce, &aad, data);
+    }
+
+    fn decrypt(&mut self, password: &str, data: &Vec<u8>) -> std::io::Result<Vec<u8>> {
+        self.check_key_file()?;
+        self.unwrap_data_key(password)?;
+        let aad = self.associated_data();
+
+        return match open(self.cipher_id, &self.data_key, &self.nonce, &aad, data) {
+            Ok(d) => Ok(d),
+            Err(_) => Err(Error::new(ErrorKind::Other, "Decryption error. The file header has been modified"))
+        };
+    }
+
+    fn to_dyn_writer(&self, writer: &mut dyn Write, data: &Vec<u8>) -> std::io::Result<()> {
+        let j = EnvelopeJson {
+            version: FORMAT_VERSION_ENVELOPE,
+            cipher: self.cipher_id.to_string(),
+            recipients: self.recipients.clone(),
+            nonce: BASE64_STANDARD.encode(&self.nonce),
+            data: BASE64_STANDARD.encode(data)
+        };
+
+        serde_json::to_writer_pretty(writer, &j)?;
+
+        return Ok(());
+    }
+
+    fn from_dyn_reader(&mut self, reader: &mut dyn Read) -> std::io::Result<Vec<u8>> {
+        let json_struct: EnvelopeJson = serde_json::from_reader(reader)?;
+
+        if (json_struct.version < FORMAT_VERSION_ENVELOPE) || (json_struct.version > FORMAT_VERSION) {
+            return Err(Error::new(ErrorKind::Other, format!("Unsupported file format version {}", json_struct.version)));
+        }
+
+        self.cipher_id = match CipherId::from_str(&json_struct.cipher) {
+            Some(id) => id,
+            None => return Err(Error::new(ErrorKind::Other, format!("Unknown cipher '{}'", json_struct.cipher)))
+        };
+
+        if json_struct.recipients.len() == 0 {
+            return Err(Error::new(ErrorKind::Other, "Shared file has no recipients"));
+        }
+
+        let nonce = decode(&json_struct.nonce)?;
+        if nonce.len() != DEFAULT_NONCE_SIZE {
+            return Err(Error::new(ErrorKind::Other, "Unsupported nonce size"));
+        }
+
+        let data = decode(&json_struct.data)?;
+        if data.len() < DEFAULT_TAG_SIZE {
+            return Err(Error::new(ErrorKind::Other, "Ciphertext too short"));
+        }
+
+        self.nonce = nonce;
+        self.recipients = json_struct.recipients;
+
+        return Ok(data);
+    }
+
+    fn algo_name(&self) -> &'static str {
+        let (d, i) = KdfId::Sha256.to_named_func();
+        return self.cipher_id.make(d, i).algo_name();
+    }
+
+    fn cipher_id(&self) -> CipherId {
+        return self.cipher_id;
+    }
+
+    fn kdf_id(&self) -> KdfId {
+        return self.kdf_id;
+    }
+
+    fn kdf_params(&self) -> KdfParams {
+        return self.kdf_params;
+    }
+
+    fn set_kdf_params(&mut self, params: KdfParams) {
+        self.kdf_params = params;
+    }
+
+    fn set_key_file(&mut self, key_file: Option<Vec<u8>>) {
+        self.key_file = key_file;
+    }
+
+    fn key_file_required(&self) -> bool {
+        return false;
+    }
+
+    fn envelope(&self) -> Option<EnvelopeContext> {
+        return Some(self.clone());
+    }
+}
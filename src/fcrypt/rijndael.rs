@@ -22,9 +22,9 @@ const ALGO_AES192: &str = "AES-192 GCM";
 
 
 pub struct Gcm256Context(AeadContext);
-crate::make_creator!(Gcm256Context);
+crate::make_creator!(Gcm256Context, crate::fcrypt::CipherId::Aes256Gcm);
 crate::cryptor_impl!(Gcm256Context, AesGcm::<aes::Aes256, U12>, ALGO_AES256);
 
 pub struct Gcm192Context(AeadContext);
-crate::make_creator!(Gcm192Context);
+crate::make_creator!(Gcm192Context, crate::fcrypt::CipherId::Aes192Gcm);
 crate::cryptor_impl!(Gcm192Context, AesGcm::<aes::Aes192, U12>, ALGO_AES192);
@@ -62,6 +62,20 @@ impl TotpParams {
         return format!("{:0>width$}", totp_int % mod_val, width = self.digits);
     }    
 
+    // Uses the default parameters with a Base32 encoded secret as it is shown by most services
+    pub fn from_secret(secret: &str) -> Option<TotpParams> {
+        let cleaned: String = secret.chars().filter(|c| !c.is_whitespace()).collect();
+
+        if cleaned.len() == 0 {
+            return None;
+        }
+
+        let mut params = TotpParams::new();
+        params.secret = base32::decode(base32::Alphabet::Rfc4648 { padding: false }, cleaned.trim_end_matches('=').to_uppercase().as_str())?;
+
+        return Some(params);
+    }
+
     pub fn from_totp_params(entry_content: String) -> Option<TotpParams> {
         let v: Vec<_> = entry_content.match_indices("otpauth://totp/").collect();
 
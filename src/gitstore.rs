@@ -0,0 +1,210 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use crate::persist::{Persister, SendSyncPersister, FilePersister, HistoryEntry};
+
+pub const GIT_PREFIX: &str = "git:";
+const GIT_COMMAND: &str = "git";
+// Used if the user has not configured an identity for git
+const FALLBACK_NAME: &str = "rustpwman";
+const FALLBACK_EMAIL: &str = "rustpwman@localhost";
+
+// Stores the password file in a git repository and commits every saved version. The directory of the file
+// has to be the top level directory of an existing repository. Repositories which merely contain the file
+// somewhere below their top level, e.g. a home directory which is managed by git, are never used. Only the
+// encrypted data is ever committed.
+pub struct GitPersister {
+    file: SendSyncPersister,
+    dir: PathBuf,
+    name: String,
+    // Name of the remote to which every commit is pushed
+    remote: Option<String>
+}
+
+impl GitPersister {
+    pub fn new(file_name: &String, remote: &Option<String>) -> SendSyncPersister {
+        let path = PathBuf::from(file_name);
+
+        let dir = match path.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(n) => String::from(n),
+            None => file_name.clone()
+        };
+
+        let res = GitPersister {
+            file: FilePersister::new(file_name),
+            dir: dir,
+            name: name,
+            remote: remote.clone()
+        };
+
+        return Box::new(res);
+    }
+
+    fn git(&self, args: &[&str]) -> std::io::Result<Output> {
+        return match Command::new(GIT_COMMAND).arg("-C").arg(&self.dir).args(args).output() {
+            Ok(o) => Ok(o),
+            Err(e) => Err(Error::new(ErrorKind::Other, format!("Unable to run git: {}", e)))
+        };
+    }
+
+    // Runs git and turns a failure into an error which contains the output of git
+    fn git_checked(&self, args: &[&str]) -> std::io::Result<Output> {
+        let output = self.git(args)?;
+
+        if !output.status.success() {
+            let msg = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::new(ErrorKind::Other, format!("git {} failed: {}", args[0], msg.trim())));
+        }
+
+        return Ok(output);
+    }
+
+    // true if the directory of the file is the top level directory of a repository
+    fn is_repository(&self) -> std::io::Result<bool> {
+        let output = self.git(&["rev-parse", "--show-toplevel"])?;
+
+        if !output.status.success() {
+            return Ok(false);
+        }
+
+        let top_level = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+        return match (fs::canonicalize(&top_level), fs::canonicalize(&self.dir)) {
+            (Ok(t), Ok(d)) => Ok(t == d),
+            _ => Ok(false)
+        };
+    }
+
+    fn check_repository(&self) -> std::io::Result<()> {
+        if !self.is_repository()? {
+            let msg = format!("'{}' is not the top level directory of a git repository. Run 'git init' there first.", self.dir.display());
+            return Err(Error::new(ErrorKind::Other, msg));
+        }
+
+        return Ok(());
+    }
+
+    // Returns the configured value or the fallback
+    fn config_value(&self, key: &str, fallback: &str) -> std::io::Result<String> {
+        let output = self.git(&["config", key])?;
+
+        if !output.status.success() {
+            return Ok(String::from(fallback));
+        }
+
+        return Ok(String::from(String::from_utf8_lossy(&output.stdout).trim()));
+    }
+
+    fn commit(&self) -> std::io::Result<()> {
+        self.git_checked(&["add", "--", &self.name])?;
+
+        // Saving unchanged data does not create a commit
+        if self.git(&["diff", "--cached", "--quiet", "--", &self.name])?.status.success() {
+            return Ok(());
+        }
+
+        // git refuses to commit if either the name or the email address is missing
+        let message = format!("Update {}", &self.name);
+        let name_cfg = format!("user.name={}", self.config_value("user.name", FALLBACK_NAME)?);
+        let email_cfg = format!("user.email={}", self.config_value("user.email", FALLBACK_EMAIL)?);
+
+        let args = ["-c", name_cfg.as_str(), "-c", email_cfg.as_str(), "commit", "-q", "-m", message.as_str(), "--", self.name.as_str()];
+        self.git_checked(&args)?;
+
+        return Ok(());
+    }
+}
+
+impl Persister for GitPersister {
+    fn does_exist(&self) -> std::io::Result<bool> {
+        return self.file.does_exist();
+    }
+
+    fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
+        // Nothing is written if the data could not be committed
+        self.check_repository()?;
+        self.file.persist(data)?;
+
+        if let Err(e) = self.commit() {
+            return Err(Error::new(ErrorKind::Other, format!("The file was saved but not committed. {}", e)));
+        }
+
+        if let Some(r) = &self.remote {
+            if let Err(e) = self.git_checked(&["push", "-q", r.as_str(), "HEAD"]) {
+                return Err(Error::new(ErrorKind::Other, format!("The file was saved and committed but not pushed. {}", e)));
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return self.file.retrieve();
+    }
+
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return self.file.peek();
+    }
+
+    fn get_canonical_path(&self) -> std::io::Result<String> {
+        return self.file.get_canonical_path();
+    }
+
+    fn get_type(&self) -> String {
+        return String::from("Git");
+    }
+
+    fn history(&self) -> std::io::Result<Vec<HistoryEntry>> {
+        if !self.is_repository()? {
+            return Ok(vec![]);
+        }
+
+        let output = self.git(&["log", "--format=%H%x09%ci%x09%s", "--", &self.name])?;
+
+        // A repository without commits has no history
+        if !output.status.success() {
+            return Ok(vec![]);
+        }
+
+        let mut res: Vec<HistoryEntry> = Vec::new();
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let parts: Vec<&str> = line.splitn(3, '\t').collect();
+
+            if parts.len() == 3 {
+                res.push(HistoryEntry { id: String::from(parts[0]), time_stamp: String::from(parts[1]), description: String::from(parts[2]) });
+            }
+        }
+
+        return Ok(res);
+    }
+
+    fn read_historic(&self, id: &str) -> std::io::Result<Vec<u8>> {
+        self.check_repository()?;
+        let object = format!("{}:./{}", id, &self.name);
+
+        return Ok(self.git_checked(&["show", &object])?.stdout);
+    }
+}
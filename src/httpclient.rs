@@ -0,0 +1,81 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use reqwest::Proxy;
+use reqwest::blocking::Client;
+use reqwest::tls::{Certificate, Identity};
+
+use crate::tomlconfig::WebDavClientParams;
+
+// Requests fail after this number of seconds if webdav_timeout is not set
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+fn read_pem(file_name: &str, what: &str) -> std::io::Result<Vec<u8>> {
+    return match fs::read(file_name) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(Error::new(ErrorKind::Other, format!("Unable to read {} '{}': {}", what, file_name, e)))
+    };
+}
+
+// Creates the HTTP client which is shared by all requests to a WebDAV server or an S3 compatible object store
+pub fn make_client(params: &WebDavClientParams) -> std::io::Result<Client> {
+    let mut builder = Client::builder();
+
+    // Certificates of internal CAs are trusted in addition to the ones known to the system
+    if let Some(f) = &params.webdav_ca_file {
+        let certs = match Certificate::from_pem_bundle(&read_pem(f, "CA file")?) {
+            Ok(c) => c,
+            Err(e) => return Err(Error::new(ErrorKind::Other, format!("Unable to parse CA file '{}': {}", f, e)))
+        };
+
+        builder = builder.tls_certs_merge(certs);
+    }
+
+    if let Some(f) = &params.webdav_client_cert {
+        let mut pem = read_pem(f, "client certificate")?;
+
+        if let Some(k) = &params.webdav_client_key {
+            pem.push(b'\n');
+            pem.extend(read_pem(k, "client key")?);
+        }
+
+        let identity = match Identity::from_pem(&pem) {
+            Ok(i) => i,
+            Err(e) => return Err(Error::new(ErrorKind::Other, format!("Unable to parse client certificate '{}': {}", f, e)))
+        };
+
+        builder = builder.identity(identity);
+    }
+
+    if let Some(p) = &params.webdav_proxy {
+        let proxy = match Proxy::all(p.as_str()) {
+            Ok(p) => p,
+            Err(e) => return Err(Error::new(ErrorKind::Other, format!("Not a valid proxy '{}': {}", p, e)))
+        };
+
+        builder = builder.proxy(proxy);
+    }
+
+    builder = builder.timeout(Duration::from_secs(params.webdav_timeout.unwrap_or(DEFAULT_TIMEOUT)));
+
+    return match builder.build() {
+        Ok(c) => Ok(c),
+        Err(e) => Err(Error::new(ErrorKind::Other, format!("Unable to create HTTP client: {}", e)))
+    };
+}
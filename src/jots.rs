@@ -21,16 +21,28 @@ use std::io::BufWriter;
 use std::io::{Error, ErrorKind};
 use crate::fcrypt;
 use crate::persist::SendSyncPersister;
+use crate::merge;
 use crate::undo::UndoRepo;
 use crate::obfuscate::Cfb8;
+use crate::timeutil::{now_millis, SECONDS_PER_DAY};
 use fcrypt::KeyDeriver;
 use fcrypt::KdfId;
 use fcrypt::Cryptor;
+use fcrypt::CipherId;
+use fcrypt::KdfParams;
+use fcrypt::envelope::EnvelopeContext;
 use rand::Rng;
+use base64::prelude::*;
 use sha2::{Sha256, Digest};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
 
+// Number of previous values which are kept for each entry
+pub const MAX_PREVIOUS_VALUES: usize = 10;
+// Size limits in bytes for a single attachment and for all attachments of an entry
+pub const MAX_ATTACHMENT_SIZE: usize = 1024 * 1024;
+pub const MAX_ATTACHMENTS_PER_ENTRY: usize = 4 * 1024 * 1024;
+
 pub type CryptorGen = Box<dyn Fn(KeyDeriver, KdfId) -> Box<dyn Cryptor>  + Send + Sync>;
 pub type BackupCallback = Box<dyn Fn(&Vec<u8>) -> std::io::Result<()> + Send + Sync> ;
 
@@ -51,26 +63,53 @@ impl MapObfuscator {
         return MapObfuscator { session_key: key.clone() };
     }
 
-    fn derive_iv(map_key: &str) -> Vec<u8> {
+    // Every obfuscated value gets its own nonce. Otherwise equal values, e.g. a value and one of its
+    // previous values, would have the same obfuscated representation.
+    fn new_nonce() -> Vec<u8> {
+        let mut nonce = vec![0u8; 16];
+        rand::rng().fill_bytes(&mut nonce);
+
+        return nonce;
+    }
+
+    fn derive_iv(nonce: &[u8], map_key: &str) -> Vec<u8> {
         let mut sha = Sha256::new();
+        sha.update(nonce);
         sha.update(map_key.as_bytes());
         return sha.finalize().into_iter().take(16).collect();
     }
 
-    fn encrypt_for_memory(&self, value: &str, map_key: &str) -> Vec<u8> {
+    fn encrypt_for_memory(&self, value: &str, nonce: &[u8], map_key: &str) -> Vec<u8> {
         let mut data = value.as_bytes().to_vec();
-        Cfb8::new_aes_128_cfb((&self.session_key).to_vec(), MapObfuscator::derive_iv(map_key)).encrypt(&mut data);
+        Cfb8::new_aes_128_cfb((&self.session_key).to_vec(), MapObfuscator::derive_iv(nonce, map_key)).encrypt(&mut data);
         return data;
     }
 
-    fn decrypt_from_memory(&self, ciphertext: &[u8], map_key: &str) -> String {
+    fn decrypt_from_memory(&self, ciphertext: &[u8], nonce: &[u8], map_key: &str) -> String {
         let mut data = ciphertext.to_vec();
-        Cfb8::new_aes_128_cfb((&self.session_key).to_vec(), MapObfuscator::derive_iv(map_key)).decrypt(&mut data);
+        Cfb8::new_aes_128_cfb((&self.session_key).to_vec(), MapObfuscator::derive_iv(nonce, map_key)).decrypt(&mut data);
         return String::from_utf8(data).expect("decrypted value is not valid UTF-8");
     }
 }
 
 
+// A value which an entry had before it was changed. The time stamp is the point in time at which
+// the value was replaced.
+#[derive(Serialize, Deserialize, Debug, Clone, Zeroize, ZeroizeOnDrop)]
+pub struct PreviousValue {
+    #[serde(rename(deserialize = "Replaced"))]
+    #[serde(rename(serialize = "Replaced"))]
+    pub replaced: u64,
+    #[serde(rename(deserialize = "Text"))]
+    #[serde(rename(serialize = "Text"))]
+    pub value: String,
+    #[serde(rename(deserialize = "Fields"))]
+    #[serde(rename(serialize = "Fields"))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EntryField>
+}
+
+// Time stamps are seconds since 1970-01-01 UTC. They are missing in entries which were created by older versions.
 #[derive(Serialize, Deserialize, Debug, Zeroize, ZeroizeOnDrop)]
 pub struct KvEntry {
     #[serde(rename(deserialize = "Key"))]
@@ -78,16 +117,325 @@ pub struct KvEntry {
     pub key: String,
     #[serde(rename(deserialize = "Text"))]
     #[serde(rename(serialize = "Text"))]
-    pub value: String 
+    pub value: String,
+    #[serde(rename(deserialize = "Fields"))]
+    #[serde(rename(serialize = "Fields"))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EntryField>,
+    #[serde(rename(deserialize = "Attachments"))]
+    #[serde(rename(serialize = "Attachments"))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    #[serde(rename(deserialize = "Created"))]
+    #[serde(rename(serialize = "Created"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    #[serde(rename(deserialize = "Modified"))]
+    #[serde(rename(serialize = "Modified"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(rename(deserialize = "History"))]
+    #[serde(rename(serialize = "History"))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<PreviousValue>,
+    #[serde(rename(deserialize = "Tags"))]
+    #[serde(rename(serialize = "Tags"))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(rename(deserialize = "Expires"))]
+    #[serde(rename(serialize = "Expires"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    #[serde(rename(deserialize = "RotationDays"))]
+    #[serde(rename(serialize = "RotationDays"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation_days: Option<u32>
 }
 
-impl KvEntry {
-    pub fn new(k: &String, v: &String) -> KvEntry {
-        return KvEntry {
-            key: k.clone(),
-            value: v.clone()
+// Creation and modification time of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryTimes {
+    pub created: Option<u64>,
+    pub modified: Option<u64>
+}
+
+// An entry is due for rotation when its expiry date has been reached or when it has not been changed
+// for the given number of days
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EntryExpiry {
+    pub expires: Option<u64>,
+    pub rotation_days: Option<u32>
+}
+
+impl EntryExpiry {
+    // Returns the point in time at which the entry is due. The last change is used as the start of the
+    // rotation interval. Entries which have never been changed are due immediately.
+    pub fn due_date(&self, times: &EntryTimes) -> Option<u64> {
+        let last_change = times.modified.or(times.created).unwrap_or(0);
+        let rotation = self.rotation_days.map(|d| last_change + (d as u64) * SECONDS_PER_DAY);
+
+        return match (self.expires, rotation) {
+            (Some(e), Some(r)) => Some(e.min(r)),
+            (e, r) => e.or(r)
+        };
+    }
+}
+
+// Obfuscated text, fields and attachments of an entry. The names of fields and attachments are kept in plaintext.
+#[derive(Clone)]
+struct StoredValue {
+    nonce: Vec<u8>,
+    text: Vec<u8>,
+    fields: Vec<(String, Vec<u8>)>,
+    attachments: Vec<(String, Vec<u8>)>
+}
+
+// An entry as it is held in memory. The value and the previous values are obfuscated.
+#[derive(Clone)]
+pub struct StoredEntry {
+    value: StoredValue,
+    times: EntryTimes,
+    // Oldest value first
+    history: Vec<(u64, StoredValue)>,
+    tags: Vec<String>,
+    expiry: EntryExpiry
+}
+
+// Fields which have a special meaning. All other fields are custom fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Username,
+    Password,
+    Url,
+    Totp,
+    Notes,
+    Custom
+}
+
+pub const STANDARD_FIELDS: [FieldKind; 5] = [FieldKind::Username, FieldKind::Password, FieldKind::Url, FieldKind::Totp, FieldKind::Notes];
+
+impl FieldKind {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            FieldKind::Username => "Username",
+            FieldKind::Password => "Password",
+            FieldKind::Url => "URL",
+            FieldKind::Totp => "TOTP",
+            FieldKind::Notes => "Notes",
+            FieldKind::Custom => "Custom"
+        };
+    }
+
+    pub fn from_name(name: &str) -> FieldKind {
+        return STANDARD_FIELDS.iter().find(|k| k.name().eq_ignore_ascii_case(name.trim())).copied().unwrap_or(FieldKind::Custom);
+    }
+
+    // The values of these fields are not displayed unless this is explicitly requested
+    pub fn is_hidden(&self) -> bool {
+        return matches!(self, FieldKind::Password | FieldKind::Totp);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct EntryField {
+    #[serde(rename(deserialize = "Name"))]
+    #[serde(rename(serialize = "Name"))]
+    pub name: String,
+    #[serde(rename(deserialize = "Value"))]
+    #[serde(rename(serialize = "Value"))]
+    pub value: String
+}
+
+impl EntryField {
+    pub fn new(name: &str, value: &str) -> EntryField {
+        return EntryField { name: String::from(name), value: String::from(value) };
+    }
+
+    pub fn kind(&self) -> FieldKind {
+        return FieldKind::from_name(&self.name);
+    }
+}
+
+// Removes fields without a name or value. Only the first field with a given name is kept and the names
+// of standard fields are spelled in the same way everywhere.
+pub fn normalize_fields(fields: &Vec<EntryField>) -> Vec<EntryField> {
+    let mut res: Vec<EntryField> = Vec::new();
+
+    for f in fields {
+        let name = match f.kind() {
+            FieldKind::Custom => String::from(f.name.trim()),
+            k => String::from(k.name())
+        };
+
+        if name.is_empty() || f.value.trim().is_empty() || res.iter().any(|i| i.name.eq_ignore_ascii_case(&name)) {
+            continue;
         }
+
+        res.push(EntryField::new(&name, &f.value));
+    }
+
+    return res;
+}
+
+// A small file which is stored inside an entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct Attachment {
+    #[serde(rename(deserialize = "Name"))]
+    #[serde(rename(serialize = "Name"))]
+    pub name: String,
+    // Base64 encoded contents of the file
+    #[serde(rename(deserialize = "Data"))]
+    #[serde(rename(serialize = "Data"))]
+    pub data: String
+}
+
+impl Attachment {
+    pub fn new(name: &str, data: &[u8]) -> Attachment {
+        return Attachment { name: String::from(name), data: BASE64_STANDARD.encode(data) };
+    }
+
+    pub fn decode(&self) -> std::io::Result<Vec<u8>> {
+        return match BASE64_STANDARD.decode(&self.data) {
+            Ok(d) => Ok(d),
+            Err(_) => Err(Error::new(ErrorKind::Other, format!("Attachment '{}' is not correctly encoded", &self.name)))
+        };
     }
+
+    // Size of the decoded data
+    pub fn size(&self) -> usize {
+        let padding = self.data.bytes().rev().take_while(|b| *b == b'=').count();
+
+        return ((self.data.len() / 4) * 3).saturating_sub(padding);
+    }
+}
+
+// Attachments are extracted to files of the same name. Therefore the name must not contain a path.
+pub fn check_attachment_name(name: &str) -> std::io::Result<()> {
+    if name.trim().is_empty() || (name.trim() != name) || name.contains(['/', '\\']) || (name == ".") || (name == "..") {
+        return Err(Error::new(ErrorKind::Other, format!("'{}' is not a valid attachment name", name)));
+    }
+
+    return Ok(());
+}
+
+// The text, the structured fields and the attachments of an entry. Entries created by older versions only have a text.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Zeroize, ZeroizeOnDrop)]
+pub struct EntryValue {
+    pub text: String,
+    pub fields: Vec<EntryField>,
+    pub attachments: Vec<Attachment>
+}
+
+impl EntryValue {
+    pub fn from_text(text: &str) -> EntryValue {
+        return EntryValue { text: String::from(text), fields: Vec::new(), attachments: Vec::new() };
+    }
+
+    fn from_kv_entry(entry: &KvEntry) -> EntryValue {
+        return EntryValue { text: entry.value.clone(), fields: normalize_fields(&entry.fields), attachments: entry.attachments.clone() };
+    }
+
+    // Field names are case insensitive
+    pub fn field(&self, name: &str) -> Option<&EntryField> {
+        return self.fields.iter().find(|f| f.name.eq_ignore_ascii_case(name.trim()));
+    }
+
+    // Returns a copy in which the password has been replaced. The password is either stored in the Password
+    // field or in a single line of the text which starts with "Password:". Entries which have fields but no
+    // password get a new Password field. None means the password can not be located.
+    pub fn with_password(&self, new_pw: &str) -> Option<EntryValue> {
+        let mut res = self.clone();
+        let name = FieldKind::Password.name();
+
+        if let Some(f) = res.fields.iter_mut().find(|f| f.kind() == FieldKind::Password) {
+            f.value = String::from(new_pw);
+            return Some(res);
+        }
+
+        let prefix = format!("{}:", name);
+        let lines: Vec<&str> = self.text.split('\n').collect();
+        let pw_lines: Vec<usize> = lines.iter().enumerate()
+            .filter(|(_, l)| l.trim_start().get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(&prefix)))
+            .map(|(i, _)| i)
+            .collect();
+
+        if pw_lines.len() == 1 {
+            let line = lines[pw_lines[0]];
+            let start = line.len() - line.trim_start().len() + prefix.len();
+            let new_line = format!("{} {}", &line[..start], new_pw);
+
+            res.text = lines.iter().enumerate()
+                .map(|(i, l)| if i == pw_lines[0] { new_line.as_str() } else { l })
+                .collect::<Vec<&str>>()
+                .join("\n");
+
+            return Some(res);
+        }
+
+        if !self.fields.is_empty() && pw_lines.is_empty() {
+            res.fields.push(EntryField::new(name, new_pw));
+            return Some(res);
+        }
+
+        return None;
+    }
+
+    // Shows the fields as lines of the form "Name: value" in front of the text. The names and sizes of the
+    // attachments follow the text.
+    pub fn render(&self, hide_secrets: bool) -> String {
+        if self.fields.is_empty() && self.attachments.is_empty() {
+            return self.text.clone();
+        }
+
+        let mut res = String::new();
+
+        for f in &self.fields {
+            let value = if hide_secrets && f.kind().is_hidden() { "********" } else { f.value.as_str() };
+            res.push_str(&format!("{}: {}\n", &f.name, value));
+        }
+
+        if !self.text.trim().is_empty() {
+            if !res.is_empty() {
+                res.push_str("\n");
+            }
+
+            res.push_str(&self.text);
+        }
+
+        if !self.attachments.is_empty() {
+            if !res.is_empty() {
+                res.push_str(if res.ends_with('\n') { "\n" } else { "\n\n" });
+            }
+
+            for a in &self.attachments {
+                res.push_str(&format!("Attachment: {} ({} bytes)\n", &a.name, a.size()));
+            }
+        }
+
+        return res;
+    }
+
+    fn total_attachment_size(&self) -> usize {
+        return self.attachments.iter().map(|a| a.size()).sum();
+    }
+}
+
+// A slash separates the levels of a tag, i.e. work/servers is contained in work
+fn tag_matches(tag: &str, filter: &str) -> bool {
+    return (tag == filter) || tag.starts_with(&format!("{}/", filter.trim_end_matches('/')));
+}
+
+// Removes surrounding white space, empty tags and duplicates
+pub fn normalize_tags(tags: &Vec<String>) -> Vec<String> {
+    let mut res: Vec<String> = tags.iter().map(|t| String::from(t.trim().trim_matches('/'))).filter(|t| !t.is_empty()).collect();
+    res.sort();
+    res.dedup();
+
+    return res;
+}
+
+pub fn now_secs() -> u64 {
+    return (now_millis() / 1000) as u64;
 }
 
 pub struct JotsIter<'a> {
@@ -122,12 +470,25 @@ impl<'a> Iterator for JotsIter<'a> {
 }
 
 pub struct Jots {
-    contents: HashMap<String, Vec<u8>>,
+    contents: HashMap<String, StoredEntry>,
+    // The entries as they were last read from or written to storage. Used as the common ancestor when merging.
+    base: HashMap<String, StoredEntry>,
     obf: MapObfuscator,
+    // The PBKDF of the file which was read last or the one used for new files
     kdf: KeyDeriver,
     kdf_id: fcrypt::KdfId,
+    // The cipher of the file which was read last. If it is None cr_gen decides which cipher is used.
+    cipher_id: Option<CipherId>,
+    // The PBKDF parameters of the file which was read last. If it is None cr_gen decides which parameters are used.
+    kdf_params: Option<KdfParams>,
+    // Hash of the key file which is used in addition to the password
+    key_file: Option<Vec<u8>>,
+    // Secret X25519 key which is used instead of the password to open a shared file
+    identity: Option<Vec<u8>>,
+    // Recipients and data key of a shared file. None if the file is protected by a single password.
+    envelope: Option<EnvelopeContext>,
     dirty: bool,
-    pub undoer: UndoRepo<String, Vec<u8>>,
+    pub undoer: UndoRepo<String, StoredEntry>,
     pub cr_gen: CryptorGen,
     pub backup_cb: Option<BackupCallback>
 }
@@ -137,11 +498,17 @@ impl Jots {
     pub fn new(d: KeyDeriver, kdf_id: fcrypt::KdfId, g: CryptorGen) -> Jots {
         return Jots {
             contents: HashMap::new(),
+            base: HashMap::new(),
             obf: MapObfuscator::new(),
             kdf: d,
             kdf_id: kdf_id,
+            cipher_id: None,
+            kdf_params: None,
+            key_file: None,
+            identity: None,
+            envelope: None,
             dirty: false,
-            undoer: UndoRepo::<String, Vec<u8>>::new(),
+            undoer: UndoRepo::<String, StoredEntry>::new(),
             cr_gen: g,
             backup_cb: None
         };
@@ -154,6 +521,7 @@ impl Jots {
     pub fn mark_as_clean(&mut self) {
         self.dirty = false;
         self.undoer.clear();
+        self.base = self.contents.clone();
     }
 
     pub fn len(&self) -> usize {
@@ -164,26 +532,94 @@ impl Jots {
         let reader = BufReader::new(r);
         let raw_struct: Vec<KvEntry> = serde_json::from_reader(reader)?;
 
-        self.contents.clear();
-    
-        for i in raw_struct {
-            let enc = self.obf.encrypt_for_memory(&i.value, &i.key);
-            self.contents.insert(i.key.clone(), enc);
-        }
+        self.contents = self.obfuscate_entries(&raw_struct);
 
         return Ok(());
     }
 
+    // Each field and attachment is obfuscated with its own IV
+    fn field_map_key(k: &str, field_name: &str) -> String {
+        return format!("{}\n{}", k, field_name);
+    }
+
+    fn attachment_map_key(k: &str, attachment_name: &str) -> String {
+        return format!("{}\n\n{}", k, attachment_name);
+    }
+
+    fn obfuscate_value(&self, v: &EntryValue, k: &str) -> StoredValue {
+        let nonce = MapObfuscator::new_nonce();
+
+        return StoredValue {
+            text: self.obf.encrypt_for_memory(&v.text, &nonce, k),
+            fields: v.fields.iter().map(|f| (f.name.clone(), self.obf.encrypt_for_memory(&f.value, &nonce, &Jots::field_map_key(k, &f.name)))).collect(),
+            attachments: v.attachments.iter().map(|a| (a.name.clone(), self.obf.encrypt_for_memory(&a.data, &nonce, &Jots::attachment_map_key(k, &a.name)))).collect(),
+            nonce: nonce
+        };
+    }
+
+    fn deobfuscate_value(&self, v: &StoredValue, k: &str) -> EntryValue {
+        return EntryValue {
+            text: self.obf.decrypt_from_memory(&v.text, &v.nonce, k),
+            fields: v.fields.iter().map(|(n, f)| EntryField { name: n.clone(), value: self.obf.decrypt_from_memory(f, &v.nonce, &Jots::field_map_key(k, n)) }).collect(),
+            attachments: v.attachments.iter().map(|(n, a)| Attachment { name: n.clone(), data: self.obf.decrypt_from_memory(a, &v.nonce, &Jots::attachment_map_key(k, n)) }).collect()
+        };
+    }
+
+    fn obfuscate_entry(&self, entry: &KvEntry) -> StoredEntry {
+        // Previous values do not contain attachments
+        let to_value = |text: &String, fields: &Vec<EntryField>| EntryValue { text: text.clone(), fields: normalize_fields(fields), attachments: Vec::new() };
+
+        return StoredEntry {
+            value: self.obfuscate_value(&EntryValue::from_kv_entry(entry), &entry.key),
+            times: EntryTimes { created: entry.created, modified: entry.modified },
+            history: entry.history.iter().map(|h| (h.replaced, self.obfuscate_value(&to_value(&h.value, &h.fields), &entry.key))).collect(),
+            tags: normalize_tags(&entry.tags),
+            expiry: EntryExpiry { expires: entry.expires, rotation_days: entry.rotation_days }
+        };
+    }
+
+    fn obfuscate_entries(&self, entries: &Vec<KvEntry>) -> HashMap<String, StoredEntry> {
+        let mut res: HashMap<String, StoredEntry> = HashMap::new();
+
+        for i in entries {
+            res.insert(i.key.clone(), self.obfuscate_entry(i));
+        }
+
+        return res;
+    }
+
+    fn to_previous_value(&self, k: &String, replaced: u64, v: &StoredValue) -> PreviousValue {
+        let value = self.deobfuscate_value(v, k);
+
+        return PreviousValue { replaced: replaced, value: value.text.clone(), fields: value.fields.clone() };
+    }
+
+    fn deobfuscate_entry(&self, k: &String, entry: &StoredEntry) -> KvEntry {
+        let value = self.deobfuscate_value(&entry.value, k);
+
+        return KvEntry {
+            key: k.clone(),
+            value: value.text.clone(),
+            fields: value.fields.clone(),
+            attachments: value.attachments.clone(),
+            created: entry.times.created,
+            modified: entry.times.modified,
+            history: entry.history.iter().map(|(t, v)| self.to_previous_value(k, *t, v)).collect(),
+            tags: entry.tags.clone(),
+            expires: entry.expiry.expires,
+            rotation_days: entry.expiry.rotation_days
+        };
+    }
+
     pub fn to_writer<T: Write>(&self, w: T) -> std::io::Result<()> {
         let mut raw_data: Vec<KvEntry> = Vec::new();
         let writer = BufWriter::new(w);
 
         for i in &self.contents {
-            let mut plaintext = self.obf.decrypt_from_memory(i.1, i.0);
-            raw_data.push(KvEntry::new(i.0, &plaintext));
-            plaintext.zeroize();
+            raw_data.push(self.deobfuscate_entry(i.0, i.1));
         }
 
+        // KvEntry zeroizes its contents when it is dropped
         serde_json::to_writer_pretty(writer, &raw_data)?;
 
         return Ok(());
@@ -191,14 +627,14 @@ impl Jots {
 
     pub fn print(&self) {
         (&self.contents).iter().for_each(|i| {
-            let mut plaintext = self.obf.decrypt_from_memory(i.1, i.0);
+            let mut plaintext = self.deobfuscate_value(&i.1.value, i.0).render(false);
             println!("{}: {}", i.0, plaintext);
             plaintext.zeroize();
         });
     }
 
-    fn insert_int(&mut self, k: &String, v: &String) {
-        self.contents.insert(k.clone(), self.obf.encrypt_for_memory(v, k));
+    fn insert_int(&mut self, k: &String, entry: StoredEntry) {
+        self.contents.insert(k.clone(), entry);
         self.dirty = true;
     }
 
@@ -207,22 +643,143 @@ impl Jots {
         self.dirty = true;
     }
 
-    pub fn modify(&mut self, k: &String, v: &String) {
-        if self.get(k).is_none() {
-            return;
+    // false means the entry does not exist
+    fn change(&mut self, k: &String, v: &EntryValue, msg: String) -> bool {
+        let old_encrypted = match self.contents.get(k).cloned() {
+            Some(e) => e,
+            None => return false
+        };
+
+        let mut new_entry = old_encrypted.clone();
+        let old_value = self.deobfuscate_value(&old_encrypted.value, k);
+
+        if old_value != *v {
+            // The old value is only kept if its text or fields have actually been changed. Previous values do not
+            // contain attachments as they could take up a lot of space.
+            if (old_value.text != v.text) || (old_value.fields != v.fields) {
+                let mut previous = old_encrypted.value.clone();
+                previous.attachments.clear();
+
+                new_entry.history.push((now_secs(), previous));
+                if new_entry.history.len() > MAX_PREVIOUS_VALUES {
+                    new_entry.history.remove(0);
+                }
+            }
+
+            new_entry.value = self.obfuscate_value(v, k);
+            new_entry.times.modified = Some(now_secs());
         }
 
-        let old_encrypted = self.contents.get(k).cloned().unwrap();
-        self.insert_int(k, v);
+        self.insert_int(k, new_entry);
 
-        let msg = format!("Modify entry '{}'", k);
         let old_key = k.clone();
 
-        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, Vec<u8>>| -> bool {
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
             s.insert(old_key.clone(), old_encrypted.clone());
 
             return true;
         }));
+
+        return true;
+    }
+
+    // Changes the text of an entry. Its fields and attachments are kept.
+    pub fn modify(&mut self, k: &String, v: &String) {
+        let mut new_value = match self.value(k) {
+            Some(old) => old,
+            None => return
+        };
+
+        new_value.text = v.clone();
+
+        let _ = self.change(k, &new_value, format!("Modify entry '{}'", k));
+    }
+
+    // Changes the text, the fields and the attachments of an entry. false means the entry does not exist.
+    pub fn set_value(&mut self, k: &String, v: &EntryValue) -> bool {
+        let new_value = EntryValue { text: v.text.clone(), fields: normalize_fields(&v.fields), attachments: v.attachments.clone() };
+
+        return self.change(k, &new_value, format!("Modify entry '{}'", k));
+    }
+
+    // false means the entry does not exist
+    pub fn set_fields(&mut self, k: &String, fields: &Vec<EntryField>) -> bool {
+        let mut new_value = match self.value(k) {
+            Some(old) => old,
+            None => return false
+        };
+
+        let fields = normalize_fields(fields);
+        if new_value.fields == fields {
+            return true;
+        }
+
+        new_value.fields = fields;
+
+        return self.change(k, &new_value, format!("Change fields of entry '{}'", k));
+    }
+
+    // Names and sizes of the attachments of an entry
+    pub fn attachments(&self, k: &String) -> Vec<(String, usize)> {
+        return match self.value(k) {
+            Some(v) => v.attachments.iter().map(|a| (a.name.clone(), a.size())).collect(),
+            None => Vec::new()
+        };
+    }
+
+    pub fn attachment(&self, k: &String, name: &str) -> std::io::Result<Vec<u8>> {
+        let value = match self.value(k) {
+            Some(v) => v,
+            None => return Err(Error::new(ErrorKind::Other, format!("Entry '{}' not found", k)))
+        };
+
+        return match value.attachments.iter().find(|a| a.name == name) {
+            Some(a) => a.decode(),
+            None => Err(Error::new(ErrorKind::Other, format!("Entry '{}' has no attachment '{}'", k, name)))
+        };
+    }
+
+    pub fn add_attachment(&mut self, k: &String, name: &str, data: &[u8]) -> std::io::Result<()> {
+        let mut new_value = match self.value(k) {
+            Some(v) => v,
+            None => return Err(Error::new(ErrorKind::Other, format!("Entry '{}' not found", k)))
+        };
+
+        check_attachment_name(name)?;
+
+        if new_value.attachments.iter().any(|a| a.name == name) {
+            return Err(Error::new(ErrorKind::Other, format!("Entry '{}' already has an attachment '{}'", k, name)));
+        }
+
+        if data.len() > MAX_ATTACHMENT_SIZE {
+            return Err(Error::new(ErrorKind::Other, format!("Attachments must not be larger than {} bytes", MAX_ATTACHMENT_SIZE)));
+        }
+
+        if new_value.total_attachment_size() + data.len() > MAX_ATTACHMENTS_PER_ENTRY {
+            return Err(Error::new(ErrorKind::Other, format!("All attachments of an entry must not be larger than {} bytes", MAX_ATTACHMENTS_PER_ENTRY)));
+        }
+
+        new_value.attachments.push(Attachment::new(name, data));
+        let _ = self.change(k, &new_value, format!("Add attachment '{}' to entry '{}'", name, k));
+
+        return Ok(());
+    }
+
+    // false means the entry or the attachment does not exist
+    pub fn remove_attachment(&mut self, k: &String, name: &str) -> bool {
+        let mut new_value = match self.value(k) {
+            Some(v) => v,
+            None => return false
+        };
+
+        let len_before = new_value.attachments.len();
+        new_value.attachments.retain(|a| a.name != name);
+
+        if new_value.attachments.len() == len_before {
+            return false;
+        }
+
+        return self.change(k, &new_value, format!("Remove attachment '{}' from entry '{}'", name, k));
     }
 
     pub fn delete(&mut self, k: &String) {
@@ -236,7 +793,7 @@ impl Jots {
         let msg = format!("Delete entry '{}'", k);
         let old_key = k.clone();
 
-        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, Vec<u8>>| -> bool {
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
             s.insert(old_key.clone(), old_encrypted.clone());
 
             return true;
@@ -249,15 +806,155 @@ impl Jots {
             Some(val) => val
         };
 
-        return Some(self.obf.decrypt_from_memory(v, k));
+        return Some(self.obf.decrypt_from_memory(&v.value.text, &v.value.nonce, k));
+    }
+
+    pub fn value(&self, k: &String) -> Option<EntryValue> {
+        return self.contents.get(k).map(|e| self.deobfuscate_value(&e.value, k));
+    }
+
+    pub fn fields(&self, k: &String) -> Vec<EntryField> {
+        return match self.value(k) {
+            Some(v) => v.fields.clone(),
+            None => Vec::new()
+        };
+    }
+
+    // Fields and text of an entry as they are displayed
+    pub fn render(&self, k: &String, hide_secrets: bool) -> Option<String> {
+        return self.value(k).map(|v| v.render(hide_secrets));
+    }
+
+    pub fn times(&self, k: &String) -> Option<EntryTimes> {
+        return self.contents.get(k).map(|e| e.times);
+    }
+
+    pub fn tags(&self, k: &String) -> Vec<String> {
+        return match self.contents.get(k) {
+            Some(e) => e.tags.clone(),
+            None => Vec::new()
+        };
+    }
+
+    // false means the entry does not exist
+    pub fn set_tags(&mut self, k: &String, tags: &Vec<String>) -> bool {
+        let old_entry = match self.contents.get(k).cloned() {
+            Some(e) => e,
+            None => return false
+        };
+
+        let tags = normalize_tags(tags);
+        if tags == old_entry.tags {
+            return true;
+        }
+
+        let mut new_entry = old_entry.clone();
+        new_entry.tags = tags;
+        self.insert_int(k, new_entry);
+
+        let msg = format!("Change tags of entry '{}'", k);
+        let old_key = k.clone();
+
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
+            s.insert(old_key.clone(), old_entry.clone());
+
+            return true;
+        }));
+
+        return true;
+    }
+
+    pub fn expiry(&self, k: &String) -> Option<EntryExpiry> {
+        return self.contents.get(k).map(|e| e.expiry);
+    }
+
+    // false means the entry does not exist
+    pub fn set_expiry(&mut self, k: &String, expiry: EntryExpiry) -> bool {
+        let old_entry = match self.contents.get(k).cloned() {
+            Some(e) => e,
+            None => return false
+        };
+
+        if expiry == old_entry.expiry {
+            return true;
+        }
+
+        let mut new_entry = old_entry.clone();
+        new_entry.expiry = expiry;
+        self.insert_int(k, new_entry);
+
+        let msg = format!("Change expiry of entry '{}'", k);
+        let old_key = k.clone();
+
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
+            s.insert(old_key.clone(), old_entry.clone());
+
+            return true;
+        }));
+
+        return true;
+    }
+
+    pub fn due_date(&self, k: &String) -> Option<u64> {
+        return self.contents.get(k).and_then(|e| e.expiry.due_date(&e.times));
+    }
+
+    // Names and due dates of all entries which are due at the given point in time, the longest overdue first
+    pub fn due(&self, now: u64) -> Vec<(String, u64)> {
+        let mut res: Vec<(String, u64)> = self.into_iter()
+            .filter_map(|k| self.due_date(k).filter(|d| *d <= now).map(|d| (k.clone(), d)))
+            .collect();
+        res.sort_by_key(|(_, d)| *d);
+
+        return res;
+    }
+
+    // All tags which are in use including the upper levels of tags like work/servers
+    pub fn all_tags(&self) -> Vec<String> {
+        let mut res: Vec<String> = Vec::new();
+
+        for e in self.contents.values() {
+            for t in &e.tags {
+                let parts: Vec<&str> = t.split('/').collect();
+                for i in 1..=parts.len() {
+                    res.push(parts[..i].join("/"));
+                }
+            }
+        }
+
+        res.sort();
+        res.dedup();
+
+        return res;
+    }
+
+    // Names of all entries which have the tag or one of its sub tags, sorted by name
+    pub fn tagged(&self, tag: &str) -> Vec<String> {
+        return self.into_iter().filter(|k| self.contents[*k].tags.iter().any(|t| tag_matches(t, tag))).cloned().collect();
+    }
+
+    // Returns the values which the entry had before, the oldest first
+    pub fn previous_values(&self, k: &String) -> Vec<PreviousValue> {
+        return match self.contents.get(k) {
+            Some(e) => e.history.iter().map(|(t, v)| self.to_previous_value(k, *t, v)).collect(),
+            None => Vec::new()
+        };
     }
 
     // false means add has failed
     pub fn add(&mut self, k: &String, v: &String) -> bool {
+        return self.add_value(k, &EntryValue::from_text(v));
+    }
+
+    // false means add has failed
+    pub fn add_value(&mut self, k: &String, v: &EntryValue) -> bool {
         // Check for entry with the given name. It must not exist.
         let res = match self.get(k) {
             None => {
-                self.insert_int(k, v);
+                let now = Some(now_secs());
+                let value = EntryValue { text: v.text.clone(), fields: normalize_fields(&v.fields), attachments: v.attachments.clone() };
+                let entry = StoredEntry { value: self.obfuscate_value(&value, k), times: EntryTimes { created: now, modified: now }, history: Vec::new(), tags: Vec::new(), expiry: EntryExpiry::default() };
+                self.insert_int(k, entry);
                 true
             },
             _ => return false // Entry already exists
@@ -266,7 +963,7 @@ impl Jots {
         let msg = format!("Add entry '{}'", k);
         let old_key = k.clone();
 
-        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, Vec<u8>>| -> bool {
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
             s.remove(&old_key);
 
             return true;
@@ -303,10 +1000,14 @@ impl Jots {
         // Check if entry k_new exists. It must not exist.
         let res = match self.get(k_new) {
             None => {
-                let mut decrypted = self.obf.decrypt_from_memory(&old_encrypted, k_old);
+                // Renaming counts as a modification. The values have to be obfuscated again as the
+                // obfuscation depends on the name of the entry.
+                let mut plain = self.deobfuscate_entry(k_old, &old_encrypted);
+                plain.key = k_new.clone();
+                plain.modified = Some(now_secs());
+                let new_entry = self.obfuscate_entry(&plain);
                 self.remove_int(k_old);
-                self.insert_int(k_new, &decrypted);
-                decrypted.zeroize();
+                self.insert_int(k_new, new_entry);
                 true
             },
             _ => return false
@@ -316,7 +1017,7 @@ impl Jots {
         let old_key = k_old.clone();
         let new_key = k_new.clone();
 
-        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, Vec<u8>>| -> bool {
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
             s.remove(&new_key);
             s.insert(old_key.clone(), old_encrypted.clone());
 
@@ -326,46 +1027,370 @@ impl Jots {
         return res;
     }
 
-    pub fn from_enc_file(&mut self, file_name: &str, password: &str) -> std::io::Result<()> {
-        let mut ctx = (self.cr_gen)(self.kdf, self.kdf_id);
+    // Files keep the cipher and PBKDF parameters they were encrypted with. Only new files use the
+    // values selected by cr_gen.
+    fn make_cryptor(&self) -> Box<dyn Cryptor> {
+        if let Some(e) = &self.envelope {
+            let mut res = e.clone();
 
-        let data = ctx.from_file(file_name)?;
-        let mut plain_data = match ctx.decrypt(password, &data) {
-            Err(e) => { return Err(Error::new(ErrorKind::Other, format!("{}", e))); },
-            Ok(d) => d
+            if let Some(id) = self.cipher_id {
+                res.set_cipher_id(id);
+            }
+
+            res.set_kdf(self.kdf_id, self.kdf_params.unwrap_or(KdfParams::default_for(self.kdf_id)));
+            res.set_key_file(self.key_file.clone());
+
+            return Box::new(res);
+        }
+
+        let mut res = match self.cipher_id {
+            Some(id) => id.make(self.kdf, self.kdf_id),
+            None => (self.cr_gen)(self.kdf, self.kdf_id)
         };
 
-        self.from_reader(plain_data.as_slice())?;
-        self.mark_as_clean();
-        plain_data.zeroize();
+        if let Some(p) = self.kdf_params {
+            res.set_kdf_params(p);
+        }
+
+        res.set_key_file(self.key_file.clone());
+
+        return res;
+    }
+
+    // Selects the PBKDF and cipher which are recorded in the header of raw_data. Old files do not
+    // contain the cipher. In this case the cipher selected by cr_gen is used.
+    fn make_cryptor_for(&self, raw_data: &Vec<u8>) -> std::io::Result<Box<dyn Cryptor>> {
+        let info = fcrypt::peek_container_info(raw_data.as_slice())?;
+        let (kdf, kdf_id) = info.kdf_id.to_named_func();
+
+        if info.shared {
+            let mut res = EnvelopeContext::new(self.identity.clone());
+            res.set_key_file(self.key_file.clone());
+
+            return Ok(Box::new(res));
+        }
+
+        let mut res = match info.cipher_id {
+            Some(id) => id.make(kdf, kdf_id),
+            None => (self.cr_gen)(kdf, kdf_id)
+        };
+
+        res.set_key_file(self.key_file.clone());
+
+        return Ok(res);
+    }
+
+    // Sets the hash of the key file which is used to read and write the data. None means that only the password is used.
+    pub fn set_key_file(&mut self, key_file: Option<Vec<u8>>) {
+        if let Some(k) = &mut self.key_file {
+            k.zeroize();
+        }
+
+        self.key_file = key_file;
+    }
+
+    pub fn has_key_file(&self) -> bool {
+        return self.key_file.is_some();
+    }
+
+    // Sets the secret X25519 key which is used to open a shared file. None means that the password is used.
+    pub fn set_identity(&mut self, identity: Option<Vec<u8>>) {
+        if let Some(i) = &mut self.identity {
+            i.zeroize();
+        }
+
+        self.identity = identity;
+    }
+
+    pub fn is_shared(&self) -> bool {
+        return self.envelope.is_some();
+    }
+
+    // Turns the data into a shared file. The password which is used the next time the data is encrypted
+    // becomes the secret of the recipient owner.
+    pub fn share(&mut self, owner: &str) -> std::io::Result<()> {
+        if self.envelope.is_some() {
+            return Err(Error::new(ErrorKind::Other, "File is already shared"));
+        }
+
+        if self.key_file.is_some() {
+            return Err(Error::new(ErrorKind::Other, "Key files can not be used with shared files"));
+        }
+
+        let ctx = self.make_cryptor();
+        self.cipher_id = Some(ctx.cipher_id());
+        self.kdf_params = Some(ctx.kdf_params());
+        self.envelope = Some(EnvelopeContext::create(ctx.cipher_id(), ctx.kdf_id(), ctx.kdf_params(), owner));
+        self.dirty = true;
 
         return Ok(());
     }
 
-    pub fn retrieve(&mut self, p: &mut SendSyncPersister, password: &str) -> std::io::Result<()> {
-        let mut ctx = (self.cr_gen)(self.kdf, self.kdf_id);
+    fn envelope_mut(&mut self) -> std::io::Result<&mut EnvelopeContext> {
+        return match &mut self.envelope {
+            Some(e) => Ok(e),
+            None => Err(Error::new(ErrorKind::Other, "File is not shared"))
+        };
+    }
 
-        let (data, raw_data) = ctx.retrieve(p)?;
+    // Returns the names and types of all recipients of a shared file
+    pub fn recipients(&self) -> Vec<(String, String)> {
+        return match &self.envelope {
+            Some(e) => e.recipients(),
+            None => Vec::new()
+        };
+    }
 
-        if let Some(cb) = &self.backup_cb {
-            // ignore result
-            _ = cb(&raw_data);
+    // Name of the recipient whose secret was used to open a shared file
+    pub fn opened_by(&self) -> Option<String> {
+        return self.envelope.as_ref().and_then(|e| e.opened_by());
+    }
+
+    pub fn add_password_recipient(&mut self, name: &str, password: &str) -> std::io::Result<()> {
+        let (kdf_id, kdf_params) = (self.kdf_id, self.kdf_params());
+        let e = self.envelope_mut()?;
+
+        e.set_kdf(kdf_id, kdf_params);
+        e.add_password_recipient(name, password)?;
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    pub fn add_public_key_recipient(&mut self, name: &str, public_key: &str) -> std::io::Result<()> {
+        self.envelope_mut()?.add_public_key_recipient(name, public_key)?;
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    // Recipients whose password has to be passed to remove_recipient
+    pub fn passwords_needed_for_removal(&self, name: &str) -> Vec<String> {
+        return match &self.envelope {
+            Some(e) => e.passwords_needed_for_removal(name),
+            None => Vec::new()
+        };
+    }
+
+    // The data key is replaced, i.e. the removed recipient will not be able to decrypt future versions of the
+    // file even if they have kept a copy of the old data key
+    pub fn remove_recipient(&mut self, name: &str, passwords: &Vec<(String, String)>) -> std::io::Result<()> {
+        self.envelope_mut()?.remove_recipient(name, passwords)?;
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    // Changes the algorithms and parameters which are used the next time the data is encrypted
+    pub fn rekey(&mut self, cipher_id: CipherId, kdf_id: fcrypt::KdfId, kdf_params: KdfParams) -> std::io::Result<()> {
+        if !kdf_params.is_for(kdf_id) {
+            return Err(Error::new(ErrorKind::Other, format!("Parameters '{}' can not be used with {}", kdf_params.to_string(), kdf_id.to_string())));
         }
 
+        let (kdf, kdf_id) = kdf_id.to_named_func();
+
+        self.kdf = kdf;
+        self.kdf_id = kdf_id;
+        self.cipher_id = Some(cipher_id);
+        self.kdf_params = Some(kdf_params);
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    pub fn kdf_id(&self) -> fcrypt::KdfId {
+        return self.kdf_id;
+    }
+
+    pub fn cipher_id(&self) -> CipherId {
+        return self.make_cryptor().cipher_id();
+    }
+
+    pub fn algo_name(&self) -> &'static str {
+        return self.make_cryptor().algo_name();
+    }
+
+    pub fn kdf_params(&self) -> KdfParams {
+        return self.make_cryptor().kdf_params();
+    }
+
+    fn decrypt_entries(&self, raw_data: &Vec<u8>, password: &str) -> std::io::Result<(Box<dyn Cryptor>, Vec<KvEntry>)> {
+        let mut ctx = self.make_cryptor_for(raw_data)?;
+        let data = ctx.from_dyn_reader(&mut raw_data.as_slice())?;
+
         let mut plain_data = match ctx.decrypt(password, &data) {
             Err(e) => { return Err(Error::new(ErrorKind::Other, format!("{}", e))); },
             Ok(d) => d
         };
 
-        self.from_reader(plain_data.as_slice())?;
-        self.mark_as_clean();
+        let entries: serde_json::Result<Vec<KvEntry>> = serde_json::from_slice(plain_data.as_slice());
         plain_data.zeroize();
 
+        return Ok((ctx, entries?));
+    }
+
+    // Use the cipher, PBKDF and recipients of the file which was read last when writing data
+    fn adopt_cryptor(&mut self, ctx: &dyn Cryptor) {
+        (self.kdf, self.kdf_id) = ctx.kdf_id().to_named_func();
+        self.cipher_id = Some(ctx.cipher_id());
+        self.kdf_params = Some(ctx.kdf_params());
+        self.envelope = ctx.envelope();
+    }
+
+    fn decrypt_raw(&mut self, raw_data: &Vec<u8>, password: &str) -> std::io::Result<()> {
+        let (ctx, entries) = self.decrypt_entries(raw_data, password)?;
+
+        self.contents = self.obfuscate_entries(&entries);
+        self.adopt_cryptor(ctx.as_ref());
+        self.mark_as_clean();
+
         return Ok(());
     }
 
+    fn to_merge_entry(entry: &KvEntry) -> merge::MergeEntry {
+        return merge::MergeEntry {
+            value: EntryValue::from_kv_entry(entry),
+            tags: normalize_tags(&entry.tags),
+            times: EntryTimes { created: entry.created, modified: entry.modified },
+            history: entry.history.clone(),
+            expiry: EntryExpiry { expires: entry.expires, rotation_days: entry.rotation_days }
+        };
+    }
+
+    fn from_merge_entry(k: &String, entry: &merge::MergeEntry) -> KvEntry {
+        return KvEntry {
+            key: k.clone(),
+            value: entry.value.text.clone(),
+            fields: entry.value.fields.clone(),
+            attachments: entry.value.attachments.clone(),
+            created: entry.times.created,
+            modified: entry.times.modified,
+            history: entry.history.clone(),
+            tags: entry.tags.clone(),
+            expires: entry.expiry.expires,
+            rotation_days: entry.expiry.rotation_days
+        };
+    }
+
+    fn decrypt_map(&self, map: &HashMap<String, StoredEntry>) -> merge::Entries {
+        return map.iter().map(|(k, v)| (k.clone(), Jots::to_merge_entry(&self.deobfuscate_entry(k, v)))).collect();
+    }
+
+    pub fn entries(&self) -> merge::Entries {
+        return self.decrypt_map(&self.contents);
+    }
+
+    // The entries as they were last read from or written to storage
+    pub fn base_entries(&self) -> merge::Entries {
+        return self.decrypt_map(&self.base);
+    }
+
+    // Decrypts another password file using the key file and identity of this one
+    pub fn decrypt_other(&self, raw_data: &Vec<u8>, password: &str) -> std::io::Result<merge::Entries> {
+        let (_, entries) = self.decrypt_entries(raw_data, password)?;
+
+        return Ok(entries.iter().map(|e| (e.key.clone(), Jots::to_merge_entry(e))).collect());
+    }
+
+    // Determines how the data which is currently stored can be merged into the data held in memory. The state
+    // of the data when it was last read or written is used as the common ancestor. The stored data is returned
+    // in order to pass it to adopt_stored later.
+    pub fn plan_merge_stored(&self, p: &mut SendSyncPersister, password: &str) -> std::io::Result<(merge::MergePlan, Vec<u8>)> {
+        let raw_data = *p.peek()?;
+        let theirs = self.decrypt_other(&raw_data, password)?;
+
+        return Ok((merge::plan(&self.entries(), &theirs, Some(&self.base_entries())), raw_data));
+    }
+
+    // Makes raw_data the version which is overwritten by the next call to persist. Fails with a conflict error if the
+    // stored data has been changed again. The cipher, PBKDF and recipients of the stored data are used from now on.
+    pub fn adopt_stored(&mut self, p: &mut SendSyncPersister, password: &str, raw_data: &Vec<u8>) -> std::io::Result<()> {
+        if *p.retrieve()? != *raw_data {
+            return Err(crate::persist::conflict_error());
+        }
+
+        let (ctx, entries) = self.decrypt_entries(raw_data, password)?;
+        self.adopt_cryptor(ctx.as_ref());
+        self.base = self.obfuscate_entries(&entries);
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    // Applies the changes of a merge plan. Each change can be undone separately. Returns the number of
+    // added, modified and deleted entries.
+    pub fn apply_merge(&mut self, plan: &merge::MergePlan) -> (usize, usize, usize) {
+        let (mut added, mut modified, mut deleted) = (0, 0, 0);
+
+        for c in plan.resolved_changes() {
+            match (&c.value, self.entry_exists(&c.key)) {
+                (Some(v), false) => { self.merge_entry(&c.key, v); added += 1; },
+                (Some(v), true) => { self.merge_entry(&c.key, v); modified += 1; },
+                (None, true) => { self.delete(&c.key); deleted += 1; },
+                (None, false) => ()
+            }
+        }
+
+        return (added, modified, deleted);
+    }
+
+    // Replaces an entry including its tags, time stamps, previous values and expiry by the version from another file.
+    // If the value of the replaced entry is not one of the previous values of the new version it is added to them.
+    fn merge_entry(&mut self, k: &String, entry: &merge::MergeEntry) {
+        let old_entry = self.contents.get(k).cloned();
+        let mut new_entry = Jots::from_merge_entry(k, entry);
+
+        if let Some(old) = &old_entry {
+            let mine = self.deobfuscate_value(&old.value, k);
+            let same_value = |text: &String, fields: &Vec<EntryField>| (*text == mine.text) && (normalize_fields(fields) == mine.fields);
+            let known = same_value(&new_entry.value, &new_entry.fields) || new_entry.history.iter().any(|h| same_value(&h.value, &h.fields));
+
+            if !known {
+                new_entry.history.push(PreviousValue { replaced: now_secs(), value: mine.text.clone(), fields: mine.fields.clone() });
+                if new_entry.history.len() > MAX_PREVIOUS_VALUES {
+                    new_entry.history.remove(0);
+                }
+            }
+        }
+
+        let stored = self.obfuscate_entry(&new_entry);
+        self.insert_int(k, stored);
+
+        let msg = format!("Merge entry '{}'", k);
+        let old_key = k.clone();
+
+        self.undoer.push(&msg, Box::new(move |s: &mut HashMap<String, StoredEntry>| -> bool {
+            match &old_entry {
+                Some(e) => { s.insert(old_key.clone(), e.clone()); },
+                None => { s.remove(&old_key); }
+            }
+
+            return true;
+        }));
+    }
+
+    pub fn from_enc_file(&mut self, file_name: &str, password: &str) -> std::io::Result<()> {
+        let raw_data = std::fs::read(file_name)?;
+
+        return self.decrypt_raw(&raw_data, password);
+    }
+
+    pub fn retrieve(&mut self, p: &mut SendSyncPersister, password: &str) -> std::io::Result<()> {
+        let raw_data = *p.retrieve()?;
+
+        // Make sure the data is at least syntactically correct before a backup is written
+        let _ = fcrypt::peek_container_info(raw_data.as_slice())?;
+
+        if let Some(cb) = &self.backup_cb {
+            // ignore result
+            _ = cb(&raw_data);
+        }
+
+        return self.decrypt_raw(&raw_data, password);
+    }
+
     pub fn to_enc_file(&mut self, file_name: &str, password: &str) -> std::io::Result<()> {
-        let mut ctx = (self.cr_gen)(self.kdf, self.kdf_id);
+        let mut ctx = self.make_cryptor();
         let mut serialized: Vec<u8> = Vec::new();
 
         self.to_writer(&mut serialized)?;
@@ -375,6 +1400,7 @@ impl Jots {
         };
 
         ctx.to_file(&enc_data, file_name)?;
+        self.envelope = ctx.envelope();
         self.mark_as_clean();
         serialized.zeroize();
 
@@ -382,7 +1408,7 @@ impl Jots {
     }
 
     pub fn persist(&mut self, p: &mut SendSyncPersister, password: &str) -> std::io::Result<()> {
-        let mut ctx = (self.cr_gen)(self.kdf, self.kdf_id);
+        let mut ctx = self.make_cryptor();
         let mut serialized: Vec<u8> = Vec::new();
 
         self.to_writer(&mut serialized)?;
@@ -391,18 +1417,35 @@ impl Jots {
             Ok(d) => d
         };
 
+        // Keep a copy of the data which is about to be overwritten
+        if let Some(cb) = &self.backup_cb {
+            if p.does_exist()? {
+                let old_data = *p.peek()?;
+                if let Err(e) = cb(&old_data) {
+                    return Err(Error::new(ErrorKind::Other, format!("Unable to write backup: {}", e)));
+                }
+            }
+        }
+
         ctx.persist(&enc_data, p)?;
+        self.envelope = ctx.envelope();
         self.mark_as_clean();
         serialized.zeroize();
 
         return Ok(());
     }
 
-    pub fn search(&self, search_term: &String) -> Vec<String> {
+    // If a tag is given only entries with this tag are searched
+    pub fn search(&self, search_term: &String, tag: Option<&str>) -> Vec<String> {
         let mut res = vec![];
         let search_lower = search_term.to_lowercase();
 
-        let search_res: Vec<&String> = self.into_iter().filter(|&x| x.to_lowercase().contains(&search_lower)).collect();
+        let has_tag = |x: &String| match tag {
+            Some(t) => self.contents[x].tags.iter().any(|i| tag_matches(i, t)),
+            None => true
+        };
+
+        let search_res: Vec<&String> = self.into_iter().filter(|&x| x.to_lowercase().contains(&search_lower) && has_tag(x)).collect();
 
         if search_res.len() != 0 {
             for i in search_res {
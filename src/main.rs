@@ -27,9 +27,23 @@ mod persist;
 mod obfuscate;
 mod pwstore;
 mod strat_helper;
+mod merge;
+mod timeutil;
 
+#[cfg(any(feature = "webdav", feature = "s3"))]
+mod httpclient;
 #[cfg(feature = "webdav")]
 mod webdav;
+#[cfg(feature = "ssh")]
+mod ssh;
+#[cfg(feature = "ssh")]
+mod sftp;
+#[cfg(feature = "git")]
+mod gitstore;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "writebackup")]
+mod backup;
 #[cfg(feature = "pwmanclient")]
 mod pwman_client;
 #[cfg(feature = "pwmanclientux")]
@@ -39,6 +53,10 @@ mod pwman_client_win;
 
 const OBFUSCATION_ENV_VAR: &str = "RUSTPWMAN_OBFUSCATION";
 const RUSTPWMAN_VIEWER: &str = "RUSTPWMAN_VIEWER";
+#[cfg(feature = "ssh")]
+const RUSTPWMAN_SSH: &str = "RUSTPWMAN_SSH";
+#[cfg(feature = "ssh")]
+const RUSTPWMAN_SFTP: &str = "RUSTPWMAN_SFTP";
 const PWMAN_CONFIG: &str = "PWMAN_CONFIG";
 
 use std::env;
@@ -56,9 +74,12 @@ use std::fs::File;
 use std::io::BufReader;
 use std::io::BufWriter;
 use std::io::{Error, ErrorKind};
+use std::io::Read;
+use std::io::Write;
 use pwgen::GenerationStrategy;
 use obfuscate::de_obfuscate;
 use obfuscate::obfuscate;
+use zeroize::Zeroize;
 
 pub const VERSION_STRING: &'static str = env!("CARGO_PKG_VERSION");
 const COMMAND_ENCRYPT: &str = "enc";
@@ -67,6 +88,20 @@ const COMMAND_GUI: &str = "gui";
 const COMMAND_CONFIG: &str = "cfg";
 const COMMAND_GENERATE: &str = "gen";
 const COMMAND_OBFUSCATE: &str = "obf";
+const COMMAND_GET: &str = "get";
+const COMMAND_ADD: &str = "add";
+const COMMAND_SET: &str = "set";
+const COMMAND_REMOVE: &str = "rm";
+const COMMAND_RENAME: &str = "mv";
+const COMMAND_LIST: &str = "list";
+const COMMAND_SEARCH: &str = "search";
+const COMMAND_REKEY: &str = "rekey";
+const COMMAND_RECIPIENTS: &str = "recipients";
+const COMMAND_KEYGEN: &str = "keygen";
+const COMMAND_MERGE: &str = "merge";
+const COMMAND_ATTACHMENTS: &str = "attachments";
+#[cfg(feature = "writebackup")]
+const COMMAND_BACKUPS: &str = "backups";
 const ARG_INPUT_FILE: &str = "inputfile";
 const ARG_OUTPUT_FILE: &str = "outputfile";
 const ARG_CONFIG_FILE: &str = "cfgfile";
@@ -74,6 +109,37 @@ const ARG_KDF: &str = "kdf";
 const ARG_CIPHER: &str = "cipher";
 const ARG_EXPORT: &str = "backup";
 const ARG_NUM_PASSWORDS: &str = "num-passwords";
+const ARG_ENTRY: &str = "entry";
+const ARG_FIELD: &str = "field";
+const ARG_NEW_NAME: &str = "newname";
+const ARG_FORMAT: &str = "format";
+const ARG_SEARCH_TERM: &str = "term";
+const ARG_TAG: &str = "tag";
+const ARG_DUE: &str = "due";
+const ARG_KDF_PARAMS: &str = "kdfparams";
+const ARG_NEW_PASSWORD: &str = "newpassword";
+const ARG_KEY_FILE: &str = "keyfile";
+const ARG_NEW_KEY_FILE: &str = "newkeyfile";
+const ARG_NO_KEY_FILE: &str = "nokeyfile";
+const ARG_IDENTITY: &str = "identity";
+const ARG_ADD_RECIPIENT: &str = "add";
+const ARG_REMOVE_RECIPIENT: &str = "remove";
+const ARG_PUBLIC_KEY: &str = "pubkey";
+const ARG_OWNER: &str = "owner";
+#[cfg(feature = "writebackup")]
+const ARG_RESTORE: &str = "restore";
+const DEFAULT_OWNER: &str = "owner";
+const ARG_ADD_ATTACHMENT: &str = "add";
+const ARG_ATTACHMENT_NAME: &str = "name";
+const ARG_EXTRACT_ATTACHMENT: &str = "extract";
+const ARG_REMOVE_ATTACHMENT: &str = "remove";
+const ARG_OTHER_FILE: &str = "other";
+const ARG_BASE_FILE: &str = "base";
+const ARG_PREFER: &str = "prefer";
+const PREFER_MINE: &str = "mine";
+const PREFER_THEIRS: &str = "theirs";
+const FORMAT_TEXT: &str = "text";
+const FORMAT_JSON: &str = "json";
 #[cfg(not(feature = "chacha20"))]
 const SINGLE_CIPHER_DEFAULT: CipherId = CipherId::Aes256Gcm;
 #[cfg(feature = "chacha20")]
@@ -105,12 +171,33 @@ struct RustPwMan {
     copy_command: String,
     viewer_command: Option<String>,
     bkp_file_name: Option<String>,
+    bkp_count: Option<usize>,
+    bkp_max_age: Option<u64>,
     cipher: Option<String>,
     webdav_user: String,
     webdav_pw: String,
     webdav_server: String,
+    webdav_offline: Option<String>,
+    webdav_client: tomlconfig::WebDavClientParams,
+    git_remote: Option<String>,
+    s3: tomlconfig::S3Params,
     info: Option<InfoParams>,
-    template_strings: Option<Vec<String>>
+    template_strings: Option<Vec<String>>,
+    argon2_params: Option<String>,
+    scrypt_params: Option<String>
+}
+
+// Everything a non-interactive command needs to read and write an already existing password file
+struct CliStore {
+    jots: jots::Jots,
+    persister: SendSyncPersister,
+    password: String
+}
+
+impl Drop for CliStore {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
 }
 
 enum CfgFailReaction {
@@ -159,8 +246,14 @@ pub fn determine_cipher_id(id: &str) -> fcrypt::CipherId {
 }
 
 
-pub fn make_cryptor(id: &str, d: fcrypt::KeyDeriver, i: fcrypt::KdfId) -> Box<dyn fcrypt::Cryptor> {
-    return determine_cipher_id(id).make(d, i);
+pub fn make_cryptor(id: &str, d: fcrypt::KeyDeriver, i: fcrypt::KdfId, p: fcrypt::KdfParams) -> Box<dyn fcrypt::Cryptor> {
+    let mut res = determine_cipher_id(id).make(d, i);
+
+    if p.is_for(i) {
+        res.set_kdf_params(p);
+    }
+
+    return res;
 }
 
 impl RustPwMan {
@@ -175,13 +268,21 @@ impl RustPwMan {
             paste_command: String::from(DEFAULT_PASTE_CMD),
             copy_command: String::from(DEFAULT_COPY_CMD),
             bkp_file_name: RustPwMan::get_bkp_file_name_from_env(),
+            bkp_count: None,
+            bkp_max_age: None,
             viewer_command: RustPwMan::get_viewer_from_env(),
             webdav_user: String::new(),
             webdav_pw: String::new(),
             webdav_server: String::new(),
+            webdav_offline: None,
+            webdav_client: tomlconfig::WebDavClientParams::default(),
+            git_remote: None,
+            s3: tomlconfig::S3Params::default(),
             info: None,
             cipher: None,
-            template_strings: None
+            template_strings: None,
+            argon2_params: None,
+            scrypt_params: None
         };
 
         res.reset_config();
@@ -199,12 +300,20 @@ impl RustPwMan {
         self.copy_command = String::from(DEFAULT_COPY_CMD);
         self.viewer_command = RustPwMan::get_viewer_from_env();
         self.bkp_file_name = RustPwMan::get_bkp_file_name_from_env();
+        self.bkp_count = None;
+        self.bkp_max_age = None;
         self.webdav_user = String::from("");
         self.webdav_pw = String::from("");
         self.webdav_server = String::from("");
+        self.webdav_offline = None;
+        self.webdav_client = tomlconfig::WebDavClientParams::default();
+        self.git_remote = None;
+        self.s3 = tomlconfig::S3Params::default();
         self.info = None;
         self.cipher = None;
         self.template_strings = None;
+        self.argon2_params = None;
+        self.scrypt_params = None;
     }
 
     fn is_option_present(matches: &clap::ArgMatches, id: &str) -> bool {
@@ -234,6 +343,22 @@ impl RustPwMan {
         return viewer;
     }
     
+    #[cfg(feature = "ssh")]
+    fn get_ssh_command_from_env() -> String {
+        return match env::var(RUSTPWMAN_SSH) {
+            Ok(s) => s,
+            Err(_) => String::from(ssh::DEFAULT_SSH_COMMAND)
+        };
+    }
+
+    #[cfg(feature = "ssh")]
+    fn get_sftp_command_from_env() -> String {
+        return match env::var(RUSTPWMAN_SFTP) {
+            Ok(s) => s,
+            Err(_) => String::from(sftp::DEFAULT_SFTP_COMMAND)
+        };
+    }
+
     fn get_bkp_file_name_from_env() -> Option<String> {
         #[cfg(not(feature = "writebackup"))]
         return None;
@@ -249,6 +374,30 @@ impl RustPwMan {
         return self.template_strings.clone();
     }
 
+    pub fn get_argon2_params(&self) -> Option<String> {
+        return self.argon2_params.clone();
+    }
+
+    pub fn get_scrypt_params(&self) -> Option<String> {
+        return self.scrypt_params.clone();
+    }
+
+    // Returns the PBKDF parameters which are used for new files. The values from the config file
+    // have already been checked when it was loaded.
+    fn get_kdf_params(&self) -> fcrypt::KdfParams {
+        let configured = match self.default_deriver_id {
+            fcrypt::KdfId::Argon2 => self.argon2_params.clone(),
+            #[cfg(feature = "withscrypt")]
+            fcrypt::KdfId::Scrypt => self.scrypt_params.clone(),
+            _ => None
+        };
+
+        return match configured {
+            Some(p) => fcrypt::KdfParams::from_str(self.default_deriver_id, &p).unwrap_or(fcrypt::KdfParams::default_for(self.default_deriver_id)),
+            None => fcrypt::KdfParams::default_for(self.default_deriver_id)
+        };
+    }
+
     pub fn get_backup_file_name_str(&self) -> Option<String> {
         return self.bkp_file_name.clone();
     }
@@ -261,6 +410,38 @@ impl RustPwMan {
         return path;
     }
 
+    pub fn get_webdav_offline(&self) -> Option<String> {
+        return self.webdav_offline.clone();
+    }
+
+    pub fn get_webdav_client_params(&self) -> tomlconfig::WebDavClientParams {
+        return self.webdav_client.clone();
+    }
+
+    pub fn get_git_remote(&self) -> Option<String> {
+        return self.git_remote.clone();
+    }
+
+    pub fn get_s3_params(&self) -> tomlconfig::S3Params {
+        return self.s3.clone();
+    }
+
+    pub fn get_backup_count(&self) -> Option<usize> {
+        return self.bkp_count;
+    }
+
+    pub fn get_backup_max_age(&self) -> Option<u64> {
+        return self.bkp_max_age;
+    }
+
+    #[cfg(feature = "writebackup")]
+    pub fn make_backup_manager(&self, store_id: &str) -> backup::BackupManager {
+        let count = self.bkp_count.unwrap_or(backup::DEFAULT_BACKUP_COUNT);
+        let max_age = self.bkp_max_age.unwrap_or(backup::DEFAULT_BACKUP_MAX_AGE);
+
+        return backup::BackupManager::new(&self.get_backup_file_name(), store_id, count, max_age);
+    }
+
     fn get_cipher_id(&self, matches: &clap::ArgMatches) -> String {
         let mut algo_id = String::from("");
 
@@ -301,6 +482,9 @@ impl RustPwMan {
                 self.bkp_file_name = loaded_config.bkp_file_name;
             }
 
+            self.bkp_count = loaded_config.bkp_count;
+            self.bkp_max_age = loaded_config.bkp_max_age;
+
             // If the config contains a cipher name use this instead of the value
             // read from the environment.
             if loaded_config.cipher != None {
@@ -310,8 +494,36 @@ impl RustPwMan {
             self.webdav_user = loaded_config.webdav_user;
             self.webdav_pw = loaded_config.webdav_pw;
             self.webdav_server = loaded_config.webdav_server;
+
+            #[cfg(feature = "webdav")]
+            if let Some(m) = &loaded_config.webdav_offline {
+                if webdav::OfflineMode::from_str(m).is_none() {
+                    return Some(format!("The WebDAV offline mode '{}' in the config file is invalid", m));
+                }
+            }
+
+            self.webdav_offline = loaded_config.webdav_offline;
+            self.webdav_client = loaded_config.webdav_client;
+            self.git_remote = loaded_config.git_remote;
+            self.s3 = loaded_config.s3;
             self.template_strings = loaded_config.template_strings;
 
+            if let Some(p) = &loaded_config.argon2_params {
+                if let Err(e) = fcrypt::KdfParams::from_str(fcrypt::KdfId::Argon2, p) {
+                    return Some(format!("The argon2 parameters in the config file are invalid: {}", e));
+                }
+            }
+
+            #[cfg(feature = "withscrypt")]
+            if let Some(p) = &loaded_config.scrypt_params {
+                if let Err(e) = fcrypt::KdfParams::from_str(fcrypt::KdfId::Scrypt, p) {
+                    return Some(format!("The scrypt parameters in the config file are invalid: {}", e));
+                }
+            }
+
+            self.argon2_params = loaded_config.argon2_params;
+            self.scrypt_params = loaded_config.scrypt_params;
+
             return None;
         } else {
             if file_was_read {
@@ -442,6 +654,22 @@ impl RustPwMan {
         return (file_name_in, file_name_out);
     }
 
+    // Returns the hash of the key file specified via the argument id or None if it is not present
+    fn get_key_file(matches: &clap::ArgMatches, id: &str) -> std::io::Result<Option<Vec<u8>>> {
+        return match matches.get_one::<String>(id) {
+            Some(f) => Ok(Some(fcrypt::load_key_file(f)?)),
+            None => Ok(None)
+        };
+    }
+
+    // Returns the secret X25519 key stored in the file specified via the argument id or None if it is not present
+    fn get_identity(matches: &clap::ArgMatches, id: &str) -> std::io::Result<Option<Vec<u8>>> {
+        return match matches.get_one::<String>(id) {
+            Some(f) => Ok(Some(fcrypt::envelope::load_identity(f)?)),
+            None => Ok(None)
+        };
+    }
+
     fn enter_password_verified() -> std::io::Result<String> {
         let pw1 = rpassword::prompt_password("Password: ")?;
         let pw2 = rpassword::prompt_password("Verfication: ")?;
@@ -476,13 +704,22 @@ impl RustPwMan {
         };
 
         let algo_id = self.get_cipher_id(encrypt_matches);
+        let kdf_params = self.get_kdf_params();
 
         let cr_gen = Box::new(move |k: fcrypt::KeyDeriver, i: fcrypt::KdfId| -> Box<dyn fcrypt::Cryptor>  {
-            return make_cryptor(algo_id.as_str(), k, i);
+            return make_cryptor(algo_id.as_str(), k, i, kdf_params);
         });
 
         let mut jots_file = jots::Jots::new(self.default_deriver, self.default_deriver_id, cr_gen);
 
+        match RustPwMan::get_key_file(encrypt_matches, ARG_KEY_FILE) {
+            Ok(k) => jots_file.set_key_file(k),
+            Err(e) => {
+                eprintln!("Error reading key file. {}", e);
+                return;
+            }
+        }
+
         let file = match File::open(&file_in) {
             Ok(f) => f,
             Err(e) => {
@@ -520,19 +757,42 @@ impl RustPwMan {
         let (file_in, file_out) = RustPwMan::determine_in_out_files(decrypt_matches);
 
         let algo_id = self.get_cipher_id(decrypt_matches);
+        let kdf_params = self.get_kdf_params();
 
         let cr_gen = Box::new(move |k: fcrypt::KeyDeriver, i: fcrypt::KdfId| -> Box<dyn fcrypt::Cryptor>  {
-            return make_cryptor(algo_id.as_str(), k, i);
+            return make_cryptor(algo_id.as_str(), k, i, kdf_params);
         });
 
         let mut jots_file = jots::Jots::new(self.default_deriver, self.default_deriver_id, cr_gen);
 
-        let pw = match rpassword::prompt_password("Password: ") {
-            Err(_) => {
-                eprintln!("Error reading password");
+        match RustPwMan::get_key_file(decrypt_matches, ARG_KEY_FILE) {
+            Ok(k) => jots_file.set_key_file(k),
+            Err(e) => {
+                eprintln!("Error reading key file. {}", e);
                 return;
-            },
-            Ok(p) => p
+            }
+        }
+
+        match RustPwMan::get_identity(decrypt_matches, ARG_IDENTITY) {
+            Ok(i) => jots_file.set_identity(i),
+            Err(e) => {
+                eprintln!("Error reading identity. {}", e);
+                return;
+            }
+        }
+
+        // A shared file which is opened with an identity does not need a password
+        let pw = match decrypt_matches.contains_id(ARG_IDENTITY) {
+            true => String::new(),
+            false => {
+                match rpassword::prompt_password("Password: ") {
+                    Err(_) => {
+                        eprintln!("Error reading password");
+                        return;
+                    },
+                    Ok(p) => p
+                }
+            }
         };
 
         match fcrypt::check_password(&pw) {
@@ -572,6 +832,34 @@ impl RustPwMan {
         };
     }
 
+    fn get_webdav_password(&self) -> Option<String> {
+        if obfuscate::is_obfuscation_possible(OBFUSCATION_ENV_VAR) {
+            return de_obfuscate(&self.webdav_pw, OBFUSCATION_ENV_VAR);
+        }
+
+        return Some(self.webdav_pw.clone());
+    }
+
+    // The token can be obfuscated in the same way as the password
+    #[cfg(feature = "webdav")]
+    fn get_webdav_token(&self) -> Option<Option<String>> {
+        return match &self.webdav_client.webdav_token {
+            Some(t) if obfuscate::is_obfuscation_possible(OBFUSCATION_ENV_VAR) => de_obfuscate(t, OBFUSCATION_ENV_VAR).map(Some),
+            Some(t) => Some(Some(t.clone())),
+            None => Some(None)
+        };
+    }
+
+    // The secret can also be obfuscated
+    #[cfg(feature = "s3")]
+    fn get_s3_secret(&self) -> Option<String> {
+        return match &self.s3.s3_secret {
+            Some(t) if obfuscate::is_obfuscation_possible(OBFUSCATION_ENV_VAR) => de_obfuscate(t, OBFUSCATION_ENV_VAR),
+            Some(t) => Some(t.clone()),
+            None => None
+        };
+    }
+
     #[allow(unused_variables)]
     fn make_persist_creator(&self, u: &String, p: &String, s: &String, s_id: &String) -> PersistCreator {
         let persist_closure : PersistCreator;
@@ -580,12 +868,79 @@ impl RustPwMan {
         let p = p.clone();
         let s = s.clone();
 
+        #[cfg(feature = "git")]
+        if s_id.starts_with(gitstore::GIT_PREFIX) {
+            let remote = self.git_remote.clone();
+
+            return Box::new(move |store_id: &String| -> SendSyncPersister {
+                let file_name = match store_id.strip_prefix(gitstore::GIT_PREFIX) {
+                    Some(f) => String::from(f),
+                    None => store_id.clone()
+                };
+
+                return gitstore::GitPersister::new(&file_name, &remote);
+            });
+        }
+
+        #[cfg(feature = "s3")]
+        if s_id.starts_with(s3::S3_PREFIX) {
+            let bucket = match (&self.s3.s3_secret, self.get_s3_secret()) {
+                (Some(_), None) => Err(String::from("Unable to de-obfuscate S3 secret")),
+                (_, secret) => s3::S3Bucket::from_params(&self.s3, secret, &self.webdav_client)
+            };
+
+            return Box::new(move |store_id: &String| -> SendSyncPersister {
+                let key = match store_id.strip_prefix(s3::S3_PREFIX) {
+                    Some(k) => String::from(k),
+                    None => store_id.clone()
+                };
+
+                return s3::S3Persister::new(&bucket, &key);
+            });
+        }
+
+        // SSH and SFTP URLs are not combined with the WebDAV server
+        #[cfg(feature = "ssh")]
+        if s_id.to_lowercase().starts_with(ssh::SSH_PREFIX) {
+            let ssh_command = RustPwMan::get_ssh_command_from_env();
+
+            return Box::new(move |store_id: &String| -> SendSyncPersister {
+                return ssh::SshPersister::new(store_id, &ssh_command);
+            });
+        }
+
+        #[cfg(feature = "ssh")]
+        if s_id.to_lowercase().starts_with(ssh::SFTP_PREFIX) {
+            let sftp_command = RustPwMan::get_sftp_command_from_env();
+
+            return Box::new(move |store_id: &String| -> SendSyncPersister {
+                return sftp::SftpPersister::new(store_id, &sftp_command);
+            });
+        }
+
         #[cfg(feature = "webdav")]
         {
             let test_str = format!("{}{}", s, s_id).to_lowercase();
             if test_str.starts_with("http") {
+                let mode = self.webdav_offline.clone().unwrap_or(String::from(webdav::OFFLINE_QUEUE));
+                let mirror_dir = webdav::Mirror::default_dir();
+
+                // All persisters share the same client. Errors are reported when it is used.
+                let token = self.get_webdav_token();
+                let client = match token {
+                    Some(_) => httpclient::make_client(&self.webdav_client).map_err(|e| e.to_string()),
+                    None => Err(String::from("Unable to de-obfuscate WebDAV token"))
+                };
+                let token = token.unwrap_or_default();
+
                 persist_closure = Box::new(move |store_id: &String| -> SendSyncPersister {
-                    return webdav::WebDavPersister::new(&u, &p, &s, store_id);
+                    // Without a cache directory no mirror can be kept
+                    let mirror = match (webdav::OfflineMode::from_str(&mode), &mirror_dir) {
+                        (Some(Some(m)), Some(d)) => Some(webdav::Mirror::new(d, m)),
+                        _ => None
+                    };
+
+                    return webdav::WebDavPersister::new(&u, &p, &token, &client, &s, store_id, mirror);
                 });
             } else {
                 persist_closure = Box::new(move |store_id: &String| -> SendSyncPersister {
@@ -625,15 +980,15 @@ impl RustPwMan {
 
         let a:Option<&String> = gui_matches.get_one(ARG_INPUT_FILE);
         let u = self.webdav_user.clone();
-        let mut p = self.webdav_pw.clone();
         let s = self.webdav_server.clone();
 
         let algo_id = self.get_cipher_id(gui_matches);
+        let kdf_params = self.get_kdf_params();
 
         let cr_gen_gen = Box::new(move || -> jots::CryptorGen {
             let h = algo_id.clone();
             return Box::new(move |k: fcrypt::KeyDeriver, i: fcrypt::KdfId| -> Box<dyn fcrypt::Cryptor>  {
-                return make_cryptor(h.as_str(), k, i);
+                return make_cryptor(h.as_str(), k, i, kdf_params);
             });
         });
 
@@ -641,20 +996,19 @@ impl RustPwMan {
             Some(v) => {
                 let data_file_name : String = v.clone();
 
-                if obfuscate::is_obfuscation_possible(OBFUSCATION_ENV_VAR) {
-                    p = match de_obfuscate(&p, OBFUSCATION_ENV_VAR) {
-                        Some(s) => s,
-                        None => {
-                            eprintln!("Unable to de obfuscate password from config");
-                            return;
-                        }
-                    };
-                }
+                let p = match self.get_webdav_password() {
+                    Some(s) => s,
+                    None => {
+                        eprintln!("Unable to de obfuscate password from config");
+                        return;
+                    }
+                };
 
                 let persist_closure = self.make_persist_creator(&u, &p, &s, &data_file_name);
 
                 modtui::tuimain::main(self, data_file_name, self.default_sec_level, self.default_deriver, self.default_deriver_id,
-                                      self.default_pw_gen, self.paste_command.clone(), self.copy_command.clone(), persist_closure, cr_gen_gen, gui_matches.get_flag(ARG_EXPORT), self.viewer_command.clone());
+                                      self.default_pw_gen, self.paste_command.clone(), self.copy_command.clone(), persist_closure, cr_gen_gen, gui_matches.get_flag(ARG_EXPORT), self.viewer_command.clone(),
+                                      gui_matches.get_one::<String>(ARG_KEY_FILE).cloned(), gui_matches.get_one::<String>(ARG_IDENTITY).cloned());
             },
             None => {
                 eprintln!("Password file name missing");
@@ -663,103 +1017,1078 @@ impl RustPwMan {
         }
     }
 
-    fn perform_obfuscate_command(&mut self) {
-        let pw1 = rpassword::prompt_password("WebDAV password       : ").unwrap();
-        let pw2 = rpassword::prompt_password("Again for verification: ").unwrap();
+    #[allow(unused_variables)]
+    fn read_password_for_store(p: &SendSyncPersister) -> std::io::Result<String> {
+        // Try the password cache first so that scripts do not have to prompt each time they run
+        #[cfg(feature = "pwmanclient")]
+        {
+            if let Ok(store_id) = p.get_canonical_path() {
+                if let Ok(client) = modtui::cache::make_pwman_client(store_id) {
+                    if let Ok(password) = client.get_password() {
+                        return Ok(password);
+                    }
+                }
+            }
+        }
 
-        if pw1 != pw2 {
-            eprintln!("Passwords differ");
-            return;
+        let pw = rpassword::prompt_password("Password: ")?;
+
+        match fcrypt::check_password(&pw) {
+            Some(e) => return Err(e),
+            None => ()
         }
 
-        println!("{}", obfuscate(&pw1, OBFUSCATION_ENV_VAR));
+        return Ok(pw);
     }
 
-    fn perform_config_command(&mut self, config_matches: &clap::ArgMatches) {
-        let (_, config_file_name, err_msg) = self.load_config(config_matches, CfgFailReaction::Reset);
+    fn open_store(&mut self, matches: &clap::ArgMatches) -> Option<CliStore> {
+        return self.open_store_with_legacy_cipher(matches, true);
+    }
 
-        if let Some(error_message) = err_msg  {
+    // Files written by old versions do not record their cipher. They are read using the cipher from the config
+    // or the environment and, if cli_cipher_for_legacy is true, the one from the command line.
+    fn open_store_with_legacy_cipher(&mut self, matches: &clap::ArgMatches, cli_cipher_for_legacy: bool) -> Option<CliStore> {
+        if let (_, _, Some(error_message)) = self.load_config(matches, CfgFailReaction::Abort)  {
             eprintln!("{}", error_message.as_str());
-            return;
+            return None;
         }
 
-        let mut viewer_cmd = RustPwMan::get_viewer_from_env();
-        if self.viewer_command != None {
-            viewer_cmd = self.viewer_command.clone();
+        self.set_pbkdf_from_command_line(matches);
+
+        let data_file_name = match matches.get_one::<String>(ARG_INPUT_FILE) {
+            Some(v) => v.clone(),
+            None => {
+                eprintln!("Password file name missing");
+                return None;
+            }
+        };
+
+        let webdav_pw = match self.get_webdav_password() {
+            Some(p) => p,
+            None => {
+                eprintln!("Unable to de obfuscate password from config");
+                return None;
+            }
+        };
+
+        let persist_closure = self.make_persist_creator(&self.webdav_user, &webdav_pw, &self.webdav_server, &data_file_name);
+        let mut persister = persist_closure(&data_file_name);
+
+        match persister.does_exist() {
+            Ok(true) => (),
+            Ok(false) => {
+                eprintln!("Password file '{}' does not exist", &data_file_name);
+                return None;
+            },
+            Err(e) => {
+                eprintln!("Unable to determine if password file exists. {}", e);
+                return None;
+            }
         }
 
-        let mut cipher_id = determine_cipher_id("");
+        let key_file = match RustPwMan::get_key_file(matches, ARG_KEY_FILE) {
+            Ok(k) => k,
+            Err(e) => {
+                eprintln!("Error reading key file. {}", e);
+                return None;
+            }
+        };
 
-        if let Some(id) = self.cipher.clone() {
-            cipher_id = determine_cipher_id(id.as_str());
+        let identity = match RustPwMan::get_identity(matches, ARG_IDENTITY) {
+            Ok(i) => i,
+            Err(e) => {
+                eprintln!("Error reading identity. {}", e);
+                return None;
+            }
+        };
+
+        // A shared file which is opened with an identity does not need a password
+        let password = match identity.is_some() {
+            true => String::new(),
+            false => {
+                match RustPwMan::read_password_for_store(&persister) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        eprintln!("Error reading password: {}", e);
+                        return None;
+                    }
+                }
+            }
+        };
+
+        let algo_id = match cli_cipher_for_legacy {
+            true => self.get_cipher_id(matches),
+            false => self.cipher.clone().unwrap_or(String::from(""))
+        };
+        let kdf_params = self.get_kdf_params();
+
+        let cr_gen = Box::new(move |k: fcrypt::KeyDeriver, i: fcrypt::KdfId| -> Box<dyn fcrypt::Cryptor>  {
+            return make_cryptor(algo_id.as_str(), k, i, kdf_params);
+        });
+
+        let mut jots_file = jots::Jots::new(self.default_deriver, self.default_deriver_id, cr_gen);
+        jots_file.set_key_file(key_file);
+        jots_file.set_identity(identity);
+
+        #[cfg(feature = "writebackup")]
+        {
+            let backups = self.make_backup_manager(&backup::store_id(&persister, &data_file_name));
+            jots_file.backup_cb = Some(Box::new(move |data: &Vec<u8>| { return backups.write(data); }));
         }
 
-        tuiconfig::config_main(self, config_file_name, self.default_sec_level, self.default_pw_gen, self.default_deriver_id, &self.paste_command, &self.copy_command, &self.webdav_user, &self.webdav_pw, &self.webdav_server, &viewer_cmd, cipher_id);
+        if let Err(e) = jots_file.retrieve(&mut persister, &password) {
+            eprintln!("Error reading password file. {}", e);
+            return None;
+        }
+
+        match persister.sync_state() {
+            Some(persist::SyncState::Conflict) => eprintln!("Warning: Changes made while the server was not reachable have not been uploaded. Use the GUI to merge them."),
+            Some(persist::SyncState::Offline) | Some(persist::SyncState::OfflinePending) => eprintln!("Warning: The server is not reachable. Using the local copy."),
+            _ => ()
+        }
+
+        return Some(CliStore {
+            jots: jots_file,
+            persister: persister,
+            password: password
+        });
     }
 
-    fn perform_generate_command(&mut self, generate_matches: &clap::ArgMatches) {
-        if let (_, _, Some(error_message)) = self.load_config(generate_matches, CfgFailReaction::Abort)  {
-            eprintln!("{}", error_message.as_str());
-            return;
+    fn save_store(store: &mut CliStore) -> bool {
+        if let Err(e) = store.jots.persist(&mut store.persister, &store.password) {
+            eprintln!("Error saving password file. {}", e);
+            return false;
         }
 
-        let default_num_passwords = match generate_matches.get_one::<u16>(ARG_NUM_PASSWORDS) {
-            Some(v) => *v as usize,
-            None => 1
+        return true;
+    }
+
+    fn get_entry_name(matches: &clap::ArgMatches, id: &str) -> Option<String> {
+        let entry_name = match matches.get_one::<String>(id) {
+            Some(e) => e.clone(),
+            None => {
+                eprintln!("Entry name missing");
+                return None;
+            }
         };
 
-        tuigen::generate_main(self.default_sec_level, self.default_pw_gen, default_num_passwords);
+        if entry_name.len() == 0 {
+            eprintln!("Entry name is empty");
+            return None;
+        }
+
+        return Some(entry_name);
     }
-}
 
-pub fn add_kdf_param() -> clap::Arg {
-    let mut arg = Arg::new(ARG_KDF);
+    fn read_entry_text_from_stdin() -> Option<String> {
+        let mut text = String::new();
 
-    arg = arg.long(ARG_KDF);
-    arg = arg.num_args(1);
-    arg = arg.help("Use specific PBKDF");
-    let ids: Vec<fcrypt::KdfId> = fcrypt::KdfId::get_known_ids();
-    let mut possible_values: Vec<&str> = Vec::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut text) {
+            eprintln!("Error reading entry text from stdin. {}", e);
+            return None;
+        }
 
-    for i in ids {
-        possible_values.push(i.to_str());
+        if text.len() == 0 {
+            eprintln!("Entry text is empty");
+            return None;
+        }
+
+        return Some(text);
     }
 
-    return arg.value_parser(possible_values);
-}
+    fn perform_add_command(&mut self, add_matches: &clap::ArgMatches) -> bool {
+        let entry_name = match RustPwMan::get_entry_name(add_matches, ARG_ENTRY) {
+            Some(e) => e,
+            None => return false
+        };
 
-pub fn add_cipher_param() -> clap::Arg {
-    let arg = Arg::new(ARG_CIPHER)
-        .long(ARG_CIPHER)
-        .short('c')
-        .required(false)
-        .num_args(1)
-        .help("Use specific cipher");
+        let mut store = match self.open_store(add_matches) {
+            Some(s) => s,
+            None => return false
+        };
 
-    let ids: Vec<fcrypt::CipherId> = fcrypt::CipherId::get_known_ids();
-    let mut possible_values: Vec<&str> = Vec::new();
+        if store.jots.entry_exists(&entry_name) {
+            eprintln!("An entry with the name '{}' already exists", &entry_name);
+            return false;
+        }
 
-    for i in ids {
-        possible_values.push(i.to_str());
+        let mut text = match RustPwMan::read_entry_text_from_stdin() {
+            Some(t) => t,
+            None => return false
+        };
+
+        let added = store.jots.add(&entry_name, &text);
+        text.zeroize();
+
+        if !added {
+            eprintln!("Adding entry '{}' failed", &entry_name);
+            return false;
+        }
+
+        return RustPwMan::save_store(&mut store);
     }
 
-    return arg.value_parser(possible_values);
-}
+    fn perform_set_command(&mut self, set_matches: &clap::ArgMatches) -> bool {
+        let entry_name = match RustPwMan::get_entry_name(set_matches, ARG_ENTRY) {
+            Some(e) => e,
+            None => return false
+        };
 
-fn main() {
-    let mut app = Command::new("rustpwman")
-        .version(VERSION_STRING)
-        .author("Martin Grap <rmsk2@gmx.de>")
-        .about("A password manager for the cursive TUI in Rust")
-        .subcommand(
-            Command::new(COMMAND_ENCRYPT)
-                .about("Encrypt file")
-                .arg(Arg::new(ARG_INPUT_FILE)
-                    .short('i')
-                    .long(ARG_INPUT_FILE)
-                    .num_args(1)
-                    .required(true)
-                    .help("Name of plaintext file to encrypt"))
+        let mut store = match self.open_store(set_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        if !store.jots.entry_exists(&entry_name) {
+            eprintln!("Entry '{}' not found", &entry_name);
+            return false;
+        }
+
+        let mut text = match RustPwMan::read_entry_text_from_stdin() {
+            Some(t) => t,
+            None => return false
+        };
+
+        store.jots.modify(&entry_name, &text);
+        text.zeroize();
+
+        return RustPwMan::save_store(&mut store);
+    }
+
+    fn perform_remove_command(&mut self, remove_matches: &clap::ArgMatches) -> bool {
+        let entry_name = match RustPwMan::get_entry_name(remove_matches, ARG_ENTRY) {
+            Some(e) => e,
+            None => return false
+        };
+
+        let mut store = match self.open_store(remove_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        if !store.jots.entry_exists(&entry_name) {
+            eprintln!("Entry '{}' not found", &entry_name);
+            return false;
+        }
+
+        store.jots.delete(&entry_name);
+
+        return RustPwMan::save_store(&mut store);
+    }
+
+    fn perform_attachments_command(&mut self, attachments_matches: &clap::ArgMatches) -> bool {
+        let entry_name = match RustPwMan::get_entry_name(attachments_matches, ARG_ENTRY) {
+            Some(e) => e,
+            None => return false
+        };
+
+        let mut store = match self.open_store(attachments_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        if !store.jots.entry_exists(&entry_name) {
+            eprintln!("Entry '{}' not found", &entry_name);
+            return false;
+        }
+
+        if let Some(file_name) = attachments_matches.get_one::<String>(ARG_ADD_ATTACHMENT) {
+            let mut data = match std::fs::read(file_name) {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("Unable to read file '{}'. {}", file_name, e);
+                    return false;
+                }
+            };
+
+            let name = match attachments_matches.get_one::<String>(ARG_ATTACHMENT_NAME) {
+                Some(n) => n.clone(),
+                None => modtui::attachments::name_from_path(file_name)
+            };
+
+            let res = store.jots.add_attachment(&entry_name, &name, &data);
+            data.zeroize();
+
+            if let Err(e) = res {
+                eprintln!("Unable to add attachment. {}", e);
+                return false;
+            }
+
+            return RustPwMan::save_store(&mut store);
+        }
+
+        if let Some(name) = attachments_matches.get_one::<String>(ARG_EXTRACT_ATTACHMENT) {
+            let file_name = match attachments_matches.get_one::<String>(ARG_OUTPUT_FILE) {
+                Some(f) => f.clone(),
+                None => name.clone()
+            };
+
+            let res = store.jots.attachment(&entry_name, name).and_then(|mut data| {
+                let res = modtui::attachments::save_to_file(&file_name, &data);
+                data.zeroize();
+                res
+            });
+
+            if let Err(e) = res {
+                eprintln!("Unable to extract attachment. {}", e);
+                return false;
+            }
+
+            return true;
+        }
+
+        if let Some(name) = attachments_matches.get_one::<String>(ARG_REMOVE_ATTACHMENT) {
+            if !store.jots.remove_attachment(&entry_name, name) {
+                eprintln!("Entry '{}' has no attachment '{}'", &entry_name, name);
+                return false;
+            }
+
+            return RustPwMan::save_store(&mut store);
+        }
+
+        for (name, size) in store.jots.attachments(&entry_name) {
+            println!("{} ({} bytes)", name, size);
+        }
+
+        return true;
+    }
+
+    // Reads another password file. The secret of the store is tried first. If it does not fit the user is asked for a password.
+    fn read_other_file(&self, store: &CliStore, file_name: &String) -> Option<merge::Entries> {
+        let webdav_pw = match self.get_webdav_password() {
+            Some(p) => p,
+            None => {
+                eprintln!("Unable to de obfuscate password from config");
+                return None;
+            }
+        };
+
+        let persist_closure = self.make_persist_creator(&self.webdav_user, &webdav_pw, &self.webdav_server, file_name);
+        let mut persister = persist_closure(file_name);
+
+        let raw_data = match persister.retrieve() {
+            Ok(d) => *d,
+            Err(e) => {
+                eprintln!("Unable to read '{}'. {}", file_name, e);
+                return None;
+            }
+        };
+
+        if let Ok(entries) = store.jots.decrypt_other(&raw_data, &store.password) {
+            return Some(entries);
+        }
+
+        let mut password = match rpassword::prompt_password(format!("Password for '{}': ", file_name)) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("Error reading password: {}", e);
+                return None;
+            }
+        };
+
+        let res = store.jots.decrypt_other(&raw_data, &password);
+        password.zeroize();
+
+        return match res {
+            Ok(entries) => Some(entries),
+            Err(e) => {
+                eprintln!("Unable to decrypt '{}'. {}", file_name, e);
+                None
+            }
+        };
+    }
+
+    fn ask_for_resolution(conflict: &merge::Conflict) -> Option<bool> {
+        let deleted = String::from("<entry does not exist>");
+
+        println!("Entry '{}' has been changed in both versions", conflict.key);
+        println!("----- mine -----\n{}", conflict.mine.as_ref().map(|v| v.render()).unwrap_or(deleted.clone()));
+        println!("----- theirs -----\n{}", conflict.theirs.as_ref().map(|v| v.render()).unwrap_or(deleted.clone()));
+
+        loop {
+            print!("Use (m)ine or (t)heirs? ");
+            let _ = std::io::stdout().flush();
+
+            let mut answer = String::new();
+            match std::io::stdin().read_line(&mut answer) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => ()
+            }
+
+            match answer.trim() {
+                "m" => return Some(false),
+                "t" => return Some(true),
+                _ => ()
+            }
+        }
+    }
+
+    fn perform_merge_command(&mut self, merge_matches: &clap::ArgMatches) -> bool {
+        let other_file = match merge_matches.get_one::<String>(ARG_OTHER_FILE) {
+            Some(f) => f.clone(),
+            None => {
+                eprintln!("Name of other file missing");
+                return false;
+            }
+        };
+
+        let mut store = match self.open_store(merge_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        let theirs = match self.read_other_file(&store, &other_file) {
+            Some(e) => e,
+            None => return false
+        };
+
+        let base = match merge_matches.get_one::<String>(ARG_BASE_FILE) {
+            Some(f) => match self.read_other_file(&store, f) {
+                Some(e) => Some(e),
+                None => return false
+            },
+            None => None
+        };
+
+        let mut plan = merge::plan(&store.jots.entries(), &theirs, base.as_ref());
+
+        if plan.is_empty() {
+            println!("There is nothing to merge");
+            return true;
+        }
+
+        match merge_matches.get_one::<String>(ARG_PREFER) {
+            Some(p) => plan.resolve_all(p == PREFER_THEIRS),
+            None => {
+                for c in plan.conflicts.iter_mut() {
+                    c.take_theirs = match RustPwMan::ask_for_resolution(c) {
+                        Some(t) => t,
+                        None => {
+                            eprintln!("Merge aborted");
+                            return false;
+                        }
+                    };
+                }
+            }
+        }
+
+        let (added, modified, deleted) = store.jots.apply_merge(&plan);
+        println!("Added {}, changed {} and deleted {} entries", added, modified, deleted);
+
+        return RustPwMan::save_store(&mut store);
+    }
+
+    // Determines the algorithms which are used to write the file. Everything which is not specified on the command
+    // line is taken from the file. Only if the PBKDF is changed its parameters are taken from the config.
+    fn rekey_algorithms(&self, rekey_matches: &clap::ArgMatches, jots: &jots::Jots) -> Result<(fcrypt::CipherId, fcrypt::KdfId, fcrypt::KdfParams), String> {
+        let cipher_id = match rekey_matches.get_one::<String>(ARG_CIPHER) {
+            Some(c) => determine_cipher_id(c),
+            None => jots.cipher_id()
+        };
+
+        // set_pbkdf_from_command_line has already stored the PBKDF from the command line in default_deriver_id
+        let kdf_id = if RustPwMan::is_option_present(rekey_matches, ARG_KDF) { self.default_deriver_id } else { jots.kdf_id() };
+
+        let kdf_params = match rekey_matches.get_one::<String>(ARG_KDF_PARAMS) {
+            Some(p) => fcrypt::KdfParams::from_str(kdf_id, p).map_err(|e| format!("Invalid KDF parameters: {}", e))?,
+            None if kdf_id == jots.kdf_id() => jots.kdf_params(),
+            None => self.get_kdf_params()
+        };
+
+        return Ok((cipher_id, kdf_id, kdf_params));
+    }
+
+    fn perform_rekey_command(&mut self, rekey_matches: &clap::ArgMatches) -> bool {
+        // The cipher given on the command line is the one which is used to write the file
+        let mut store = match self.open_store_with_legacy_cipher(rekey_matches, false) {
+            Some(s) => s,
+            None => return false
+        };
+
+        let (cipher_id, kdf_id, kdf_params) = match self.rekey_algorithms(rekey_matches, &store.jots) {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("{}", e);
+                return false;
+            }
+        };
+
+        if rekey_matches.get_flag(ARG_NO_KEY_FILE) {
+            store.jots.set_key_file(None);
+        }
+
+        match RustPwMan::get_key_file(rekey_matches, ARG_NEW_KEY_FILE) {
+            Ok(Some(k)) => store.jots.set_key_file(Some(k)),
+            Ok(None) => (),
+            Err(e) => {
+                eprintln!("Error reading key file. {}", e);
+                return false;
+            }
+        }
+
+        if rekey_matches.get_flag(ARG_NEW_PASSWORD) {
+            println!("Enter new password");
+
+            let new_password = match RustPwMan::enter_password_verified() {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("Error reading password: {}", e);
+                    return false;
+                }
+            };
+
+            store.password.zeroize();
+            store.password = new_password;
+        }
+
+        if let Err(e) = store.jots.rekey(cipher_id, kdf_id, kdf_params) {
+            eprintln!("{}", e);
+            return false;
+        }
+
+        if !RustPwMan::save_store(&mut store) {
+            return false;
+        }
+
+        // A password which may have been cached for this file is no longer valid
+        #[cfg(feature = "pwmanclient")]
+        if rekey_matches.get_flag(ARG_NEW_PASSWORD) {
+            if let Ok(store_id) = store.persister.get_canonical_path() {
+                if let Ok(client) = modtui::cache::make_pwman_client(store_id) {
+                    let _ = client.reset_password();
+                }
+            }
+        }
+
+        let mut msg = format!("File encrypted with {} using {}", store.jots.algo_name(), kdf_id.to_str());
+        if kdf_params != fcrypt::KdfParams::None {
+            msg.push_str(format!(" ({})", kdf_params.to_string()).as_str());
+        }
+
+        if store.jots.has_key_file() {
+            msg.push_str(" and a key file");
+        }
+
+        println!("{}", msg);
+
+        return true;
+    }
+
+    fn perform_recipients_command(&mut self, recipients_matches: &clap::ArgMatches) -> bool {
+        let mut store = match self.open_store(recipients_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        if let Some(name) = recipients_matches.get_one::<String>(ARG_ADD_RECIPIENT) {
+            if !store.jots.is_shared() {
+                let owner = recipients_matches.get_one::<String>(ARG_OWNER).cloned().unwrap_or(String::from(DEFAULT_OWNER));
+
+                if let Err(e) = store.jots.share(&owner) {
+                    eprintln!("{}", e);
+                    return false;
+                }
+            }
+
+            let add_res = match recipients_matches.get_one::<String>(ARG_PUBLIC_KEY) {
+                Some(k) => store.jots.add_public_key_recipient(name, k),
+                None => {
+                    println!("Enter password of new recipient");
+
+                    let mut password = match RustPwMan::enter_password_verified() {
+                        Ok(p) => p,
+                        Err(e) => {
+                            eprintln!("Error reading password: {}", e);
+                            return false;
+                        }
+                    };
+
+                    let res = store.jots.add_password_recipient(name, &password);
+                    password.zeroize();
+                    res
+                }
+            };
+
+            if let Err(e) = add_res {
+                eprintln!("Unable to add recipient. {}", e);
+                return false;
+            }
+
+            return RustPwMan::save_store(&mut store);
+        }
+
+        if let Some(name) = recipients_matches.get_one::<String>(ARG_REMOVE_RECIPIENT) {
+            // The data key is replaced. It has to be wrapped again for all other recipients who use a password.
+            let mut passwords: Vec<(String, String)> = Vec::new();
+
+            for needed in store.jots.passwords_needed_for_removal(name) {
+                match rpassword::prompt_password(format!("Password of recipient '{}': ", &needed)) {
+                    Ok(p) => passwords.push((needed, p)),
+                    Err(e) => {
+                        eprintln!("Error reading password: {}", e);
+                        passwords.zeroize();
+                        return false;
+                    }
+                }
+            }
+
+            let res = store.jots.remove_recipient(name, &passwords);
+            passwords.zeroize();
+
+            if let Err(e) = res {
+                eprintln!("Unable to remove recipient. {}", e);
+                return false;
+            }
+
+            return RustPwMan::save_store(&mut store);
+        }
+
+        let opened_by = store.jots.opened_by();
+
+        for (name, recipient_type) in store.jots.recipients() {
+            let marker = if opened_by.as_deref() == Some(name.as_str()) { " (you)" } else { "" };
+            println!("{} [{}]{}", name, recipient_type, marker);
+        }
+
+        return true;
+    }
+
+    fn perform_keygen_command(&mut self, keygen_matches: &clap::ArgMatches) -> bool {
+        let file_name = match keygen_matches.get_one::<String>(ARG_OUTPUT_FILE) {
+            Some(f) => f.clone(),
+            None => {
+                eprintln!("Output file name missing");
+                return false;
+            }
+        };
+
+        if std::path::Path::new(&file_name).exists() {
+            eprintln!("File '{}' already exists", &file_name);
+            return false;
+        }
+
+        let (mut secret, public) = fcrypt::envelope::generate_identity();
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        // Only the owner is allowed to read the secret key
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let res = match options.open(&file_name) {
+            Ok(mut f) => f.write_all(format!("{}\n", secret).as_bytes()),
+            Err(e) => Err(e)
+        };
+
+        secret.zeroize();
+
+        if let Err(e) = res {
+            eprintln!("Unable to write identity. {}", e);
+            return false;
+        }
+
+        println!("{}", public);
+
+        return true;
+    }
+
+    #[cfg(feature = "writebackup")]
+    fn perform_backups_command(&mut self, backups_matches: &clap::ArgMatches) -> bool {
+        if let (_, _, Some(error_message)) = self.load_config(backups_matches, CfgFailReaction::Abort)  {
+            eprintln!("{}", error_message.as_str());
+            return false;
+        }
+
+        let data_file_name = match backups_matches.get_one::<String>(ARG_INPUT_FILE) {
+            Some(v) => v.clone(),
+            None => {
+                eprintln!("Password file name missing");
+                return false;
+            }
+        };
+
+        let webdav_pw = match self.get_webdav_password() {
+            Some(p) => p,
+            None => {
+                eprintln!("Unable to de obfuscate password from config");
+                return false;
+            }
+        };
+
+        let persist_closure = self.make_persist_creator(&self.webdav_user, &webdav_pw, &self.webdav_server, &data_file_name);
+        let mut persister = persist_closure(&data_file_name);
+
+        // Only the backups of the given password file are listed and can be restored
+        let backups = self.make_backup_manager(&backup::store_id(&persister, &data_file_name));
+
+        let entries = match backups.list() {
+            Ok(e) => e,
+            Err(e) => {
+                eprintln!("Unable to list backups. {}", e);
+                return false;
+            }
+        };
+
+        let number = match backups_matches.get_one::<usize>(ARG_RESTORE) {
+            Some(n) => *n,
+            None => {
+                for (i, entry) in entries.iter().enumerate() {
+                    println!("{:3}  {}  {:8} bytes  {}", i + 1, entry.display_time(), entry.size, entry.path.display());
+                }
+
+                return true;
+            }
+        };
+
+        if (number == 0) || (number > entries.len()) {
+            eprintln!("There is no backup with number {}", number);
+            return false;
+        }
+
+        let backup_data = match std::fs::read(&entries[number - 1].path) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Unable to read backup. {}", e);
+                return false;
+            }
+        };
+
+        if let Err(e) = fcrypt::peek_container_info(backup_data.as_slice()) {
+            eprintln!("Backup is not a valid password file. {}", e);
+            return false;
+        }
+
+        // The current state of the password file becomes a backup itself and can therefore be restored later
+        let current = match persister.does_exist() {
+            Ok(true) => persister.retrieve().map(|d| Some(*d)),
+            Ok(false) => Ok(None),
+            Err(e) => Err(e)
+        };
+
+        let res = match current {
+            Ok(Some(d)) => backups.write(&d),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e)
+        };
+
+        if let Err(e) = res {
+            eprintln!("Unable to back up current password file. {}", e);
+            return false;
+        }
+
+        if let Err(e) = persister.persist(&backup_data) {
+            eprintln!("Unable to restore backup. {}", e);
+            return false;
+        }
+
+        println!("Restored backup from {}", entries[number - 1].display_time());
+
+        return true;
+    }
+
+    fn perform_rename_command(&mut self, rename_matches: &clap::ArgMatches) -> bool {
+        let old_name = match RustPwMan::get_entry_name(rename_matches, ARG_ENTRY) {
+            Some(e) => e,
+            None => return false
+        };
+
+        let new_name = match RustPwMan::get_entry_name(rename_matches, ARG_NEW_NAME) {
+            Some(e) => e,
+            None => return false
+        };
+
+        let mut store = match self.open_store(rename_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        if !store.jots.entry_exists(&old_name) {
+            eprintln!("Entry '{}' not found", &old_name);
+            return false;
+        }
+
+        if store.jots.entry_exists(&new_name) {
+            eprintln!("An entry with the name '{}' already exists", &new_name);
+            return false;
+        }
+
+        if !store.jots.rename(&old_name, &new_name) {
+            eprintln!("Renaming entry '{}' failed", &old_name);
+            return false;
+        }
+
+        return RustPwMan::save_store(&mut store);
+    }
+
+    fn print_entry_names(names: &Vec<String>, matches: &clap::ArgMatches) -> bool {
+        let format = match matches.get_one::<String>(ARG_FORMAT) {
+            Some(f) => f.clone(),
+            None => String::from(FORMAT_TEXT)
+        };
+
+        if format == FORMAT_JSON {
+            match serde_json::to_string(names) {
+                Ok(s) => println!("{}", s),
+                Err(e) => {
+                    eprintln!("Error creating JSON output. {}", e);
+                    return false;
+                }
+            }
+        } else {
+            for i in names {
+                println!("{}", i);
+            }
+        }
+
+        return true;
+    }
+
+    fn perform_list_command(&mut self, list_matches: &clap::ArgMatches) -> bool {
+        let store = match self.open_store(list_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        let mut names: Vec<String> = match list_matches.get_one::<String>(ARG_TAG) {
+            Some(t) => store.jots.tagged(t),
+            None => (&store.jots).into_iter().cloned().collect()
+        };
+
+        if list_matches.get_flag(ARG_DUE) {
+            let due: Vec<String> = store.jots.due(jots::now_secs()).into_iter().map(|(n, _)| n).collect();
+            names = due.into_iter().filter(|n| names.contains(n)).collect();
+        }
+
+        return RustPwMan::print_entry_names(&names, list_matches);
+    }
+
+    fn perform_search_command(&mut self, search_matches: &clap::ArgMatches) -> bool {
+        let search_term = match search_matches.get_one::<String>(ARG_SEARCH_TERM) {
+            Some(t) => t.clone(),
+            None => {
+                eprintln!("Search term missing");
+                return false;
+            }
+        };
+
+        let store = match self.open_store(search_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        let names = store.jots.search(&search_term, search_matches.get_one::<String>(ARG_TAG).map(|t| t.as_str()));
+
+        return RustPwMan::print_entry_names(&names, search_matches);
+    }
+
+    fn perform_get_command(&mut self, get_matches: &clap::ArgMatches) -> bool {
+        let store = match self.open_store(get_matches) {
+            Some(s) => s,
+            None => return false
+        };
+
+        let entry_name = match RustPwMan::get_entry_name(get_matches, ARG_ENTRY) {
+            Some(e) => e,
+            None => return false
+        };
+
+        let value = match store.jots.value(&entry_name) {
+            Some(c) => c,
+            None => {
+                eprintln!("Entry '{}' not found", &entry_name);
+                return false;
+            }
+        };
+
+        let mut content = value.render(false);
+
+        let res = match get_matches.get_one::<String>(ARG_FIELD) {
+            Some(field) => {
+                match modtui::template::get_field_value(&value, &vec![field.clone()], field) {
+                    Ok(mut v) => {
+                        println!("{}", v);
+                        v.zeroize();
+                        true
+                    },
+                    Err(m) => {
+                        eprintln!("{}", m);
+                        false
+                    }
+                }
+            },
+            None => {
+                print!("{}", content);
+                true
+            }
+        };
+
+        content.zeroize();
+
+        return res;
+    }
+
+    fn perform_obfuscate_command(&mut self) {
+        let pw1 = rpassword::prompt_password("WebDAV password       : ").unwrap();
+        let pw2 = rpassword::prompt_password("Again for verification: ").unwrap();
+
+        if pw1 != pw2 {
+            eprintln!("Passwords differ");
+            return;
+        }
+
+        println!("{}", obfuscate(&pw1, OBFUSCATION_ENV_VAR));
+    }
+
+    fn perform_config_command(&mut self, config_matches: &clap::ArgMatches) {
+        let (_, config_file_name, err_msg) = self.load_config(config_matches, CfgFailReaction::Reset);
+
+        if let Some(error_message) = err_msg  {
+            eprintln!("{}", error_message.as_str());
+            return;
+        }
+
+        let mut viewer_cmd = RustPwMan::get_viewer_from_env();
+        if self.viewer_command != None {
+            viewer_cmd = self.viewer_command.clone();
+        }
+
+        let mut cipher_id = determine_cipher_id("");
+
+        if let Some(id) = self.cipher.clone() {
+            cipher_id = determine_cipher_id(id.as_str());
+        }
+
+        tuiconfig::config_main(self, config_file_name, self.default_sec_level, self.default_pw_gen, self.default_deriver_id, &self.paste_command, &self.copy_command, &self.webdav_user, &self.webdav_pw, &self.webdav_server, &viewer_cmd, cipher_id);
+    }
+
+    fn perform_generate_command(&mut self, generate_matches: &clap::ArgMatches) {
+        if let (_, _, Some(error_message)) = self.load_config(generate_matches, CfgFailReaction::Abort)  {
+            eprintln!("{}", error_message.as_str());
+            return;
+        }
+
+        let default_num_passwords = match generate_matches.get_one::<u16>(ARG_NUM_PASSWORDS) {
+            Some(v) => *v as usize,
+            None => 1
+        };
+
+        tuigen::generate_main(self.default_sec_level, self.default_pw_gen, default_num_passwords);
+    }
+}
+
+pub fn add_kdf_param() -> clap::Arg {
+    let mut arg = Arg::new(ARG_KDF);
+
+    arg = arg.long(ARG_KDF);
+    arg = arg.num_args(1);
+    arg = arg.help("Use specific PBKDF");
+    let ids: Vec<fcrypt::KdfId> = fcrypt::KdfId::get_known_ids();
+    let mut possible_values: Vec<&str> = Vec::new();
+
+    for i in ids {
+        possible_values.push(i.to_str());
+    }
+
+    return arg.value_parser(possible_values);
+}
+
+// Adds the parameters which are needed by all commands that access an existing password file
+// without opening the TUI
+pub fn add_store_access_params(cmd: Command) -> Command {
+    return cmd
+        .arg(Arg::new(ARG_INPUT_FILE)
+            .short('i')
+            .long(ARG_INPUT_FILE)
+            .required(true)
+            .num_args(1)
+            .help("Name of encrypted data file"))
+        .arg(Arg::new(ARG_CONFIG_FILE)
+            .long(ARG_CONFIG_FILE)
+            .num_args(1)
+            .help("Name of config file. Default is .rustpwman"))
+        .arg(add_kdf_param())
+        .arg(add_cipher_param())
+        .arg(add_key_file_param())
+        .arg(add_identity_param());
+}
+
+pub fn add_identity_param() -> clap::Arg {
+    return Arg::new(ARG_IDENTITY)
+        .long(ARG_IDENTITY)
+        .required(false)
+        .num_args(1)
+        .help("Name of file containing a secret X25519 key which is used instead of the password to open a shared file");
+}
+
+pub fn add_key_file_param() -> clap::Arg {
+    return Arg::new(ARG_KEY_FILE)
+        .short('k')
+        .long(ARG_KEY_FILE)
+        .required(false)
+        .num_args(1)
+        .help("Name of key file which is needed in addition to the password");
+}
+
+pub fn add_entry_param() -> clap::Arg {
+    return Arg::new(ARG_ENTRY)
+        .short('e')
+        .long(ARG_ENTRY)
+        .required(true)
+        .num_args(1)
+        .help("Name of entry");
+}
+
+pub fn add_format_param() -> clap::Arg {
+    return Arg::new(ARG_FORMAT)
+        .long(ARG_FORMAT)
+        .required(false)
+        .num_args(1)
+        .default_value(FORMAT_TEXT)
+        .value_parser([FORMAT_TEXT, FORMAT_JSON])
+        .help("Output format");
+}
+
+pub fn add_tag_param() -> clap::Arg {
+    return Arg::new(ARG_TAG)
+        .long(ARG_TAG)
+        .required(false)
+        .num_args(1)
+        .help("Only consider entries with this tag. Entries with sub tags like tag/xyz are included");
+}
+
+pub fn add_cipher_param() -> clap::Arg {
+    let arg = Arg::new(ARG_CIPHER)
+        .long(ARG_CIPHER)
+        .short('c')
+        .required(false)
+        .num_args(1)
+        .help("Use specific cipher");
+
+    let ids: Vec<fcrypt::CipherId> = fcrypt::CipherId::get_known_ids();
+    let mut possible_values: Vec<&str> = Vec::new();
+
+    for i in ids {
+        possible_values.push(i.to_str());
+    }
+
+    return arg.value_parser(possible_values);
+}
+
+fn main() {
+    let mut app = Command::new("rustpwman")
+        .version(VERSION_STRING)
+        .author("Martin Grap <rmsk2@gmx.de>")
+        .about("A password manager for the cursive TUI in Rust")
+        .subcommand(
+            Command::new(COMMAND_ENCRYPT)
+                .about("Encrypt file")
+                .arg(Arg::new(ARG_INPUT_FILE)
+                    .short('i')
+                    .long(ARG_INPUT_FILE)
+                    .num_args(1)
+                    .required(true)
+                    .help("Name of plaintext file to encrypt"))
                 .arg(Arg::new(ARG_OUTPUT_FILE)
                     .short('o')
                     .long(ARG_OUTPUT_FILE)
@@ -771,7 +2100,8 @@ fn main() {
                     .num_args(1)
                     .help("Name of config file. Default is .rustpwman"))
                 .arg(add_kdf_param())
-                .arg(add_cipher_param()))
+                .arg(add_cipher_param())
+                .arg(add_key_file_param()))
         .subcommand(
             Command::new(COMMAND_DECRYPT)
                 .about("Decrypt file")
@@ -792,7 +2122,9 @@ fn main() {
                     .num_args(1)
                     .help("Name of config file. Default is .rustpwman"))
                 .arg(add_kdf_param())
-                .arg(add_cipher_param()))
+                .arg(add_cipher_param())
+                .arg(add_key_file_param())
+                .arg(add_identity_param()))
         .subcommand(
             Command::new(COMMAND_GUI)
                 .about("Open file in TUI")
@@ -804,6 +2136,8 @@ fn main() {
                     .help("Name of encrypted data file"))
                 .arg(add_kdf_param())
                 .arg(add_cipher_param())
+                .arg(add_key_file_param())
+                .arg(add_identity_param())
                 .arg(Arg::new(ARG_EXPORT)
                     .long(ARG_EXPORT)
                     .required(false)
@@ -838,8 +2172,194 @@ fn main() {
         .subcommand(
             Command::new(COMMAND_OBFUSCATE)
                 .about("Obfuscate WebDAV password")
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_GET)
+                .about("Print an entry or one of its template fields")
+                .arg(add_entry_param())
+                .arg(Arg::new(ARG_FIELD)
+                    .short('f')
+                    .long(ARG_FIELD)
+                    .required(false)
+                    .num_args(1)
+                    .help("Only print the value of this field or template string, e.g. Password")))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_ADD)
+                .about("Add an entry. Its text is read from stdin")
+                .arg(add_entry_param()))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_SET)
+                .about("Replace the text of an entry by data read from stdin")
+                .arg(add_entry_param()))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_REMOVE)
+                .about("Delete an entry")
+                .arg(add_entry_param()))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_RENAME)
+                .about("Rename an entry")
+                .arg(add_entry_param())
+                .arg(Arg::new(ARG_NEW_NAME)
+                    .short('n')
+                    .long(ARG_NEW_NAME)
+                    .required(true)
+                    .num_args(1)
+                    .help("New name of entry")))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_LIST)
+                .about("Print the names of all entries")
+                .arg(add_tag_param())
+                .arg(Arg::new(ARG_DUE)
+                    .long(ARG_DUE)
+                    .action(ArgAction::SetTrue)
+                    .help("Only print entries which are due for rotation. The longest overdue entry comes first"))
+                .arg(add_format_param()))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_SEARCH)
+                .about("Print the names of all entries which contain a search term")
+                .arg(Arg::new(ARG_SEARCH_TERM)
+                    .short('t')
+                    .long(ARG_SEARCH_TERM)
+                    .required(true)
+                    .num_args(1)
+                    .help("Search term. Case is ignored"))
+                .arg(add_tag_param())
+                .arg(add_format_param()))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_REKEY)
+                .about("Reencrypt a file using a different cipher, PBKDF or PBKDF parameters. Unspecified algorithms are kept")
+                .arg(Arg::new(ARG_KDF_PARAMS)
+                    .long(ARG_KDF_PARAMS)
+                    .num_args(1)
+                    .help("PBKDF parameters, e.g. m=65536,t=3,p=4 for argon2 or ln=16,r=8,p=2 for scrypt"))
+                .arg(Arg::new(ARG_NEW_PASSWORD)
+                    .short('p')
+                    .long(ARG_NEW_PASSWORD)
+                    .action(ArgAction::SetTrue)
+                    .help("Also change the password"))
+                .arg(Arg::new(ARG_NEW_KEY_FILE)
+                    .long(ARG_NEW_KEY_FILE)
+                    .num_args(1)
+                    .conflicts_with(ARG_NO_KEY_FILE)
+                    .help("Protect the file with this key file from now on"))
+                .arg(Arg::new(ARG_NO_KEY_FILE)
+                    .long(ARG_NO_KEY_FILE)
+                    .action(ArgAction::SetTrue)
+                    .help("Do not use a key file from now on")))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_RECIPIENTS)
+                .about("List, add or remove the recipients of a shared file")
+                .arg(Arg::new(ARG_ADD_RECIPIENT)
+                    .long(ARG_ADD_RECIPIENT)
+                    .num_args(1)
+                    .conflicts_with(ARG_REMOVE_RECIPIENT)
+                    .help("Name of recipient to add. Asks for the password of the recipient if --pubkey is not used"))
+                .arg(Arg::new(ARG_PUBLIC_KEY)
+                    .long(ARG_PUBLIC_KEY)
+                    .num_args(1)
+                    .requires(ARG_ADD_RECIPIENT)
+                    .help("X25519 public key of the recipient to add as printed by the keygen command"))
+                .arg(Arg::new(ARG_OWNER)
+                    .long(ARG_OWNER)
+                    .num_args(1)
+                    .requires(ARG_ADD_RECIPIENT)
+                    .help("Your name in the list of recipients if the file is not shared yet. Default is owner"))
+                .arg(Arg::new(ARG_REMOVE_RECIPIENT)
+                    .long(ARG_REMOVE_RECIPIENT)
+                    .num_args(1)
+                    .help("Name of recipient to remove")))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_ATTACHMENTS)
+                .about("List, add, extract or remove the attachments of an entry")
+                .arg(add_entry_param())
+                .arg(Arg::new(ARG_ADD_ATTACHMENT)
+                    .long(ARG_ADD_ATTACHMENT)
+                    .num_args(1)
+                    .conflicts_with_all([ARG_EXTRACT_ATTACHMENT, ARG_REMOVE_ATTACHMENT])
+                    .help("Name of a file to store in the entry"))
+                .arg(Arg::new(ARG_ATTACHMENT_NAME)
+                    .long(ARG_ATTACHMENT_NAME)
+                    .num_args(1)
+                    .requires(ARG_ADD_ATTACHMENT)
+                    .help("Name of the new attachment. Default is the name of the file without its path"))
+                .arg(Arg::new(ARG_EXTRACT_ATTACHMENT)
+                    .long(ARG_EXTRACT_ATTACHMENT)
+                    .num_args(1)
+                    .conflicts_with(ARG_REMOVE_ATTACHMENT)
+                    .help("Name of the attachment to write to a file"))
+                .arg(Arg::new(ARG_OUTPUT_FILE)
+                    .short('o')
+                    .long(ARG_OUTPUT_FILE)
+                    .num_args(1)
+                    .requires(ARG_EXTRACT_ATTACHMENT)
+                    .help("File to which the attachment is written. It must not exist. Default is the name of the attachment"))
+                .arg(Arg::new(ARG_REMOVE_ATTACHMENT)
+                    .long(ARG_REMOVE_ATTACHMENT)
+                    .num_args(1)
+                    .help("Name of the attachment to remove")))
+        )
+        .subcommand(
+            add_store_access_params(Command::new(COMMAND_MERGE)
+                .about("Merge the changes made in another version of a password file")
+                .arg(Arg::new(ARG_OTHER_FILE)
+                    .long(ARG_OTHER_FILE)
+                    .required(true)
+                    .num_args(1)
+                    .help("Name of the other version"))
+                .arg(Arg::new(ARG_BASE_FILE)
+                    .long(ARG_BASE_FILE)
+                    .num_args(1)
+                    .help("Name of the common ancestor of both versions, e.g. a backup"))
+                .arg(Arg::new(ARG_PREFER)
+                    .long(ARG_PREFER)
+                    .num_args(1)
+                    .value_parser([PREFER_MINE, PREFER_THEIRS])
+                    .help("Resolve all conflicts in favour of this version instead of asking")))
+        )
+        .subcommand(
+            Command::new(COMMAND_KEYGEN)
+                .about("Create an X25519 key pair for opening shared files. The public key is printed")
+                .arg(Arg::new(ARG_OUTPUT_FILE)
+                    .short('o')
+                    .long(ARG_OUTPUT_FILE)
+                    .required(true)
+                    .num_args(1)
+                    .help("File to which the secret key is written"))
         );
 
+        #[cfg(feature = "writebackup")]
+        {
+            app = app.subcommand(
+                Command::new(COMMAND_BACKUPS)
+                    .about("List the backups of the password file or restore one of them")
+                    .arg(Arg::new(ARG_INPUT_FILE)
+                        .short('i')
+                        .long(ARG_INPUT_FILE)
+                        .required(true)
+                        .num_args(1)
+                        .help("Name of encrypted data file. Only its backups are listed and it is replaced when a backup is restored"))
+                    .arg(Arg::new(ARG_RESTORE)
+                        .short('r')
+                        .long(ARG_RESTORE)
+                        .num_args(1)
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Number of the backup to restore as shown in the list. 1 is the newest backup"))
+                    .arg(Arg::new(ARG_CONFIG_FILE)
+                        .long(ARG_CONFIG_FILE)
+                        .num_args(1)
+                        .help("Name of config file. Default is .rustpwman"))
+            );
+        }
+
     let mut rustpwman = RustPwMan::new();
 
     let matches = app.clone().get_matches();
@@ -866,6 +2386,72 @@ fn main() {
                 (COMMAND_OBFUSCATE, _) => {
                     rustpwman.perform_obfuscate_command();
                 },
+                (COMMAND_GET, get_matches) => {
+                    if !rustpwman.perform_get_command(get_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_ADD, add_matches) => {
+                    if !rustpwman.perform_add_command(add_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_SET, set_matches) => {
+                    if !rustpwman.perform_set_command(set_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_REMOVE, remove_matches) => {
+                    if !rustpwman.perform_remove_command(remove_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_RENAME, rename_matches) => {
+                    if !rustpwman.perform_rename_command(rename_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_LIST, list_matches) => {
+                    if !rustpwman.perform_list_command(list_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_SEARCH, search_matches) => {
+                    if !rustpwman.perform_search_command(search_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_REKEY, rekey_matches) => {
+                    if !rustpwman.perform_rekey_command(rekey_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_RECIPIENTS, recipients_matches) => {
+                    if !rustpwman.perform_recipients_command(recipients_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_ATTACHMENTS, attachments_matches) => {
+                    if !rustpwman.perform_attachments_command(attachments_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_MERGE, merge_matches) => {
+                    if !rustpwman.perform_merge_command(merge_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                (COMMAND_KEYGEN, keygen_matches) => {
+                    if !rustpwman.perform_keygen_command(keygen_matches) {
+                        std::process::exit(1);
+                    }
+                },
+                #[cfg(feature = "writebackup")]
+                (COMMAND_BACKUPS, backups_matches) => {
+                    if !rustpwman.perform_backups_command(backups_matches) {
+                        std::process::exit(1);
+                    }
+                },
                 (&_, _) => panic!("Can not happen")
             }
         },
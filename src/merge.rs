@@ -0,0 +1,168 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::collections::HashMap;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+use crate::jots::{EntryValue, EntryTimes, EntryExpiry, PreviousValue};
+use crate::timeutil::format_date;
+
+// An entry together with the data which is stored alongside its value. Only the value, the tags and the expiry
+// are compared. Time stamps and previous values differ whenever an entry has been saved in both versions even
+// if the resulting contents are the same.
+#[derive(Debug, Clone, Zeroize, ZeroizeOnDrop)]
+pub struct MergeEntry {
+    pub value: EntryValue,
+    pub tags: Vec<String>,
+    #[zeroize(skip)]
+    pub times: EntryTimes,
+    pub history: Vec<PreviousValue>,
+    #[zeroize(skip)]
+    pub expiry: EntryExpiry
+}
+
+impl MergeEntry {
+    pub fn new(value: EntryValue) -> MergeEntry {
+        return MergeEntry { value: value, tags: Vec::new(), times: EntryTimes { created: None, modified: None }, history: Vec::new(), expiry: EntryExpiry::default() };
+    }
+
+    // The text which is shown when a conflict has to be resolved
+    pub fn render(&self) -> String {
+        let mut res = self.value.render(false);
+        let mut details: Vec<String> = Vec::new();
+
+        if !self.tags.is_empty() {
+            details.push(format!("Tags: {}", self.tags.join(", ")));
+        }
+
+        if let Some(d) = self.expiry.expires {
+            details.push(format!("Expires: {}", format_date(d)));
+        }
+
+        if let Some(d) = self.expiry.rotation_days {
+            details.push(format!("Rotation: every {} days", d));
+        }
+
+        if !details.is_empty() {
+            res.push_str("\n\n");
+            res.push_str(&details.join("\n"));
+        }
+
+        return res;
+    }
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        return (self.value == other.value) && (self.tags == other.tags) && (self.expiry == other.expiry);
+    }
+}
+
+pub type Entries = HashMap<String, MergeEntry>;
+
+// A change which is applied to the local data. A value of None means that the entry is deleted.
+#[derive(Debug, Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
+pub struct Change {
+    pub key: String,
+    pub value: Option<MergeEntry>
+}
+
+// An entry which has been changed in both versions. None means that the entry does not exist in this version.
+#[derive(Debug, Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
+pub struct Conflict {
+    pub key: String,
+    pub mine: Option<MergeEntry>,
+    pub theirs: Option<MergeEntry>,
+    // Is set when the conflict is resolved in favour of the other version
+    pub take_theirs: bool
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergePlan {
+    pub changes: Vec<Change>,
+    pub conflicts: Vec<Conflict>
+}
+
+impl MergePlan {
+    // Returns all changes which result from the plan including the resolved conflicts
+    pub fn resolved_changes(&self) -> Vec<Change> {
+        let mut res = self.changes.clone();
+
+        for c in self.conflicts.iter().filter(|c| c.take_theirs) {
+            res.push(Change { key: c.key.clone(), value: c.theirs.clone() });
+        }
+
+        return res;
+    }
+
+    pub fn resolve_all(&mut self, take_theirs: bool) {
+        for c in self.conflicts.iter_mut() {
+            c.take_theirs = take_theirs;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return (self.changes.len() == 0) && (self.conflicts.len() == 0);
+    }
+}
+
+// Determines which changes have to be made to mine in order to incorporate theirs. If the common ancestor
+// base is known an entry which has only been changed on one side is taken from that side. Without an
+// ancestor entries which only exist in theirs are added, entries which only exist in mine are kept and
+// entries with differing contents are reported as conflicts.
+pub fn plan(mine: &Entries, theirs: &Entries, base: Option<&Entries>) -> MergePlan {
+    let mut res = MergePlan::default();
+
+    let mut all_keys: Vec<&String> = mine.keys().chain(theirs.keys()).chain(base.into_iter().flat_map(|b| b.keys())).collect();
+    all_keys.sort();
+    all_keys.dedup();
+
+    for k in all_keys {
+        let my_val = mine.get(k);
+        let their_val = theirs.get(k);
+
+        if my_val == their_val {
+            continue;
+        }
+
+        let only_changed_by_them = match base {
+            Some(b) => {
+                let base_val = b.get(k);
+
+                if their_val == base_val {
+                    // Only changed by me
+                    continue;
+                }
+
+                my_val == base_val
+            },
+            None => {
+                if their_val.is_none() {
+                    // Either added by me or deleted by them. Without an ancestor it is assumed that it was added.
+                    continue;
+                }
+
+                my_val.is_none()
+            }
+        };
+
+        if only_changed_by_them {
+            res.changes.push(Change { key: k.clone(), value: their_val.cloned() });
+        } else {
+            res.conflicts.push(Conflict { key: k.clone(), mine: my_val.cloned(), theirs: their_val.cloned(), take_theirs: false });
+        }
+    }
+
+    return res;
+}
@@ -13,7 +13,7 @@ See the License for the specific language governing permissions and
 limitations under the License. */
 
 #[cfg(feature = "pwmanclient")]
-mod cache;
+pub mod cache;
 pub mod pwgenerate;
 mod load;
 mod rename;
@@ -23,9 +23,12 @@ mod add;
 mod clear;
 mod edit;
 mod pw;
+mod rekey;
+mod recipients;
 mod pwentry;
 mod init;
 mod tuiundo;
+mod merge;
 mod copy;
 mod open;
 mod info;
@@ -33,8 +36,15 @@ mod export;
 mod queue;
 #[cfg(feature = "qrcode")]
 mod qrcode;
+#[cfg(feature = "git")]
+mod history;
 mod search;
 mod totp;
+mod previous;
+mod tags;
+mod fields;
+mod rotation;
+pub mod attachments;
 pub mod template;
 pub mod tuimain;
 pub mod tuitheme;
@@ -47,6 +57,7 @@ const SELECT_VIEW: &str = "entrylist";
 const PANEL_AREA_MAIN: &str = "entrytitle";
 const TEXT_AREA_TITLE: &str = "texttitle";
 const TEXT_AREA_MAIN: &str = "entrytext";
+const ENTRY_SELECT_PANEL: &str = "EntrySelectPanel";
 const PW_WIDTH: usize = 35;
 
 pub const DEFAULT_PASTE_CMD: &str = "xsel -ob";
@@ -102,6 +113,8 @@ pub struct AppState {
     kdf_id: KdfId,
     current_totp_producer: Option<Sender<()>>,
     template_strings: Vec<String>,
+    // If set only entries with this tag are shown
+    tag_filter: Option<String>,
 }
 
 impl AppState {
@@ -125,7 +138,8 @@ impl AppState {
             cfg_name: cfg_name.clone(),
             kdf_id: kdf_id,
             current_totp_producer: None,
-            template_strings: templ.iter().cloned().collect()
+            template_strings: templ.iter().cloned().collect(),
+            tag_filter: None
         }
     }
 
@@ -271,19 +285,34 @@ fn display_entry(siv: &mut Cursive, state: Arc<Mutex<AppState>>, entry_name: &St
     }
 
     let entry_text: String;
-    let pos: usize;
+    let find_res: Option<usize>;
+    let is_filtered: bool;
 
     {
         let h = state.lock().unwrap();
         let store = &(*h).store;
 
-        let positions = 0..store.into_iter().count();
-        let find_res = store.into_iter().zip(positions).find(|i| entry_name == (*i).0 );
-
-        pos = find_res.unwrap().1;
-        entry_text = store.get(entry_name).unwrap();
+        find_res = visible_entries(&h).iter().position(|i| entry_name == i);
+        is_filtered = h.tag_filter.is_some();
+        // Hidden fields like passwords are not shown in the main window
+        entry_text = store.render(entry_name, true).unwrap();
     }
 
+    let pos = match find_res {
+        Some(p) => p,
+        None if is_filtered => {
+            // The entry is hidden by the tag filter. Show all entries instead.
+            state.lock().unwrap().tag_filter = None;
+            redraw_tui(siv, state.clone());
+            display_entry(siv, state, entry_name, do_select);
+            return;
+        },
+        None => {
+            show_message(siv, "Unable to set selection");
+            return;
+        }
+    };
+
     if do_select {
         match siv.call_on_name(SELECT_VIEW, |view: &mut SelectView| { view.set_selection(pos) }) {
             Some(cb) => cb(siv),
@@ -299,6 +328,14 @@ fn display_entry(siv: &mut Cursive, state: Arc<Mutex<AppState>>, entry_name: &St
     }
 }
 
+// The names of the entries which are shown in the main window
+fn visible_entries(state: &AppState) -> Vec<String> {
+    return match &state.tag_filter {
+        Some(t) => state.store.tagged(t),
+        None => (&state.store).into_iter().cloned().collect()
+    };
+}
+
 fn redraw_tui(siv: &mut Cursive, state: Arc<Mutex<AppState>>) {
     let mut count = 0;
     let mut initial_entry = String::from("");
@@ -309,9 +346,14 @@ fn redraw_tui(siv: &mut Cursive, state: Arc<Mutex<AppState>>) {
 
     {
         let h = state.lock().unwrap();
-        let store = &(*h).store;
 
-        for i in store {
+        let title = match &h.tag_filter {
+            Some(t) => format!("Entries tagged {}", t),
+            None => String::from("Entries")
+        };
+        siv.call_on_name(ENTRY_SELECT_PANEL, |view: &mut Panel<NamedView<ScrollView<ResizedView<OnEventView<NamedView<SelectView>>>>>>| { view.set_title(title); });
+
+        for i in visible_entries(&h).iter() {
             if count == 0 {
                  initial_entry = i.clone();
             }
@@ -392,7 +434,7 @@ fn quit_and_print(s: &mut Cursive, state: Arc<Mutex<AppState>>, sndr: Arc<Sender
         let h = state.lock().unwrap();
         let store = &(*h).store;
 
-        let value = match store.get(&key) {
+        let value = match store.render(&key, false) {
             Some(v) => v,
             None => { show_message(s, "Unable to read entry value"); return }
         };
@@ -496,6 +538,7 @@ fn build_entry_select_panel(ctx: &AppCtx) -> NamedView<Panel<NamedView<ScrollVie
     event_wrapped_select_view.set_on_event(Key::F5, wrapper3(ctx.clone(), copy::contents, false));
     event_wrapped_select_view.set_on_event(Key::F6, wrapper(ctx.clone(), search::entry));
     event_wrapped_select_view.set_on_event(Key::F7, wrapper(ctx.clone(), template::retrieve));
+    event_wrapped_select_view.set_on_event(Key::F8, wrapper(ctx.clone(), tags::filter));
 
     let select_view_scrollable = event_wrapped_select_view
         .fixed_width(40)
@@ -504,7 +547,7 @@ fn build_entry_select_panel(ctx: &AppCtx) -> NamedView<Panel<NamedView<ScrollVie
 
     let entry_select_panel = Panel::new(select_view_scrollable)
         .title("Entries")
-        .with_name("EntrySelectPanel");
+        .with_name(ENTRY_SELECT_PANEL);
 
     return entry_select_panel;
 }
@@ -519,6 +562,11 @@ fn main_window(s: &mut Cursive, shared_state: Arc<Mutex<AppState>>, sndr: Arc<Se
     file_tree.add_leaf("Save File", wrapper(ctx.clone(), save::storage));
     file_tree.add_delimiter();
     file_tree.add_leaf("Change password ...", wrapper(ctx.clone(), pw::change));
+    file_tree.add_leaf("Change encryption ...", wrapper(ctx.clone(), rekey::change));
+    file_tree.add_leaf("Recipients ...", wrapper(ctx.clone(), recipients::manage));
+    file_tree.add_leaf("Merge with file ...", wrapper(ctx.clone(), merge::with_file));
+    #[cfg(feature = "git")]
+    file_tree.add_leaf("History ...", wrapper(ctx.clone(), history::show));
     #[cfg(feature = "pwmanclient")]
     file_tree.add_leaf("Cache password", wrapper(ctx.clone(), cache::password));
     #[cfg(feature = "pwmanclient")]
@@ -544,6 +592,12 @@ fn main_window(s: &mut Cursive, shared_state: Arc<Mutex<AppState>>, sndr: Arc<Se
     entry_tree.add_leaf("Rename Entry ...", wrapper(ctx.clone(), rename::entry));
     entry_tree.add_leaf("Clear Entry ...", wrapper(ctx.clone(), clear::entry));
     entry_tree.add_leaf("Load Entry ...", wrapper(ctx.clone(), load::entry));
+    entry_tree.add_leaf("Previous values ...", wrapper(ctx.clone(), previous::show));
+    entry_tree.add_leaf("Edit fields ...", wrapper(ctx.clone(), fields::edit));
+    entry_tree.add_leaf("Edit tags ...", wrapper(ctx.clone(), tags::edit));
+    entry_tree.add_leaf("Attachments ...", wrapper(ctx.clone(), attachments::show));
+    entry_tree.add_leaf("Edit expiry ...", wrapper(ctx.clone(), rotation::edit));
+    entry_tree.add_leaf("Due for rotation ...", wrapper(ctx.clone(), rotation::show));
 
     entry_tree.add_delimiter();
     #[cfg(feature = "qrcode")]
@@ -551,6 +605,7 @@ fn main_window(s: &mut Cursive, shared_state: Arc<Mutex<AppState>>, sndr: Arc<Se
 
     entry_tree.add_leaf("Calc TOTP token ...", wrapper(ctx.clone(), totp::show));
     entry_tree.add_leaf("Search Entry ...       F6", wrapper(ctx.clone(), search::entry));
+    entry_tree.add_leaf("Filter by tag ...      F8", wrapper(ctx.clone(), tags::filter));
 
     s.menubar()
         .add_subtree("File", file_tree)
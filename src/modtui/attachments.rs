@@ -0,0 +1,189 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, SelectView, Panel};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::get_selected_entry_name;
+use super::display_entry;
+use super::visualize_if_modified;
+use crate::jots::MAX_ATTACHMENT_SIZE;
+
+const SELECT_ATTACHMENT: &str = "selectattachment";
+const EDIT_ATTACHMENT_FILE: &str = "editattachmentfile";
+
+// Extracted attachments often contain secrets. Therefore only the owner may read the file and
+// existing files are not overwritten.
+pub fn save_to_file(file_name: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    return options.open(file_name)?.write_all(data);
+}
+
+// The attachment is named like the file without its path
+pub fn name_from_path(file_name: &str) -> String {
+    return match Path::new(file_name).file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => String::from(file_name)
+    };
+}
+
+fn fill_list(s: &mut Cursive, state: &Arc<Mutex<AppState>>, entry_name: &String) {
+    let attachments = state.lock().unwrap().store.attachments(entry_name);
+
+    s.call_on_name(SELECT_ATTACHMENT, |view: &mut SelectView<String>| {
+        view.clear();
+        for (name, size) in attachments {
+            view.add_item(format!("{} ({} bytes)", &name, size), name);
+        }
+    });
+}
+
+fn selected_attachment(s: &mut Cursive) -> Option<String> {
+    return match s.call_on_name(SELECT_ATTACHMENT, |view: &mut SelectView<String>| { view.selection() }) {
+        Some(Some(n)) => Some((*n).clone()),
+        _ => None
+    };
+}
+
+fn after_change(s: &mut Cursive, state: Arc<Mutex<AppState>>, entry_name: &String) {
+    fill_list(s, &state, entry_name);
+    visualize_if_modified(s, state.clone());
+    display_entry(s, state, entry_name, true);
+}
+
+// Asks for a file name and either reads the file or writes the data to it
+fn ask_for_file(s: &mut Cursive, title: &str, default_name: &str, on_ok: impl Fn(&mut Cursive, String) + Send + Sync + 'static) {
+    let res = Dialog::new()
+        .title(title)
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::horizontal()
+            .child(TextView::new("Filename: "))
+            .child(EditView::new()
+                .content(default_name)
+                .with_name(EDIT_ATTACHMENT_FILE)
+                .fixed_width(60))
+        )
+        .button("OK", move |s| {
+            let file_name = match s.call_on_name(EDIT_ATTACHMENT_FILE, |view: &mut EditView| { view.get_content() }) {
+                Some(name) if name.len() > 0 => String::from(name.as_str()),
+                _ => { show_message(s, "File name must not be empty"); return }
+            };
+
+            on_ok(s, file_name);
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+fn add(s: &mut Cursive, state: Arc<Mutex<AppState>>, entry_name: String) {
+    ask_for_file(s, "Rustpwman add attachment", "", move |s, file_name| {
+        let data = match fs::read(&file_name) {
+            Ok(d) => d,
+            Err(e) => { show_message(s, &format!("Unable to read file: {}", e)); return }
+        };
+
+        let res = state.lock().unwrap().store.add_attachment(&entry_name, &name_from_path(&file_name), &data);
+        if let Err(e) = res {
+            show_message(s, &format!("Unable to add attachment: {}", e));
+            return;
+        }
+
+        s.pop_layer();
+        after_change(s, state.clone(), &entry_name);
+    });
+}
+
+fn extract(s: &mut Cursive, state: Arc<Mutex<AppState>>, entry_name: String) {
+    let name = match selected_attachment(s) {
+        Some(n) => n,
+        None => { show_message(s, "No attachment selected"); return }
+    };
+
+    let default_path = std::env::current_dir().unwrap_or_default().join(&name).to_string_lossy().to_string();
+
+    ask_for_file(s, "Rustpwman extract attachment", &default_path, move |s, file_name| {
+        let res = state.lock().unwrap().store.attachment(&entry_name, &name).and_then(|data| save_to_file(&file_name, &data));
+        if let Err(e) = res {
+            show_message(s, &format!("Unable to extract attachment: {}", e));
+            return;
+        }
+
+        s.pop_layer();
+        show_message(s, &format!("Attachment written to '{}'", &file_name));
+    });
+}
+
+fn delete(s: &mut Cursive, state: Arc<Mutex<AppState>>, entry_name: &String) {
+    let name = match selected_attachment(s) {
+        Some(n) => n,
+        None => { show_message(s, "No attachment selected"); return }
+    };
+
+    if !state.lock().unwrap().store.remove_attachment(entry_name, &name) {
+        show_message(s, "Unable to delete attachment");
+        return;
+    }
+
+    after_change(s, state, entry_name);
+}
+
+// Lists the files which are stored in the selected entry and allows to add, extract or delete them
+pub fn show(s: &mut Cursive, state_for_attachments: Arc<Mutex<AppState>>) {
+    let entry_name = match get_selected_entry_name(s) {
+        Some(n) => n,
+        None => {
+            show_message(s, "Unable to determine selected entry");
+            return;
+        }
+    };
+
+    let state_for_add = state_for_attachments.clone();
+    let state_for_extract = state_for_attachments.clone();
+    let state_for_delete = state_for_attachments.clone();
+    let (name_for_add, name_for_extract, name_for_delete) = (entry_name.clone(), entry_name.clone(), entry_name.clone());
+
+    let res = Dialog::new()
+        .title(format!("Rustpwman attachments of '{}'", &entry_name))
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new(format!("Attachments are stored in the encrypted file. Each one can have up to {} bytes.\n\n", MAX_ATTACHMENT_SIZE)))
+            .child(Panel::new(SelectView::<String>::new()
+                .with_name(SELECT_ATTACHMENT)
+                .scrollable()
+                .fixed_size((60, 8))))
+        )
+        .button("Add ...", move |s| { add(s, state_for_add.clone(), name_for_add.clone()); })
+        .button("Extract ...", move |s| { extract(s, state_for_extract.clone(), name_for_extract.clone()); })
+        .button("Delete", move |s| { delete(s, state_for_delete.clone(), &name_for_delete); })
+        .button("Close", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+    fill_list(s, &state_for_attachments, &entry_name);
+}
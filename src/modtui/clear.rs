@@ -23,6 +23,7 @@ use super::get_selected_entry_name;
 use super::display_entry;
 use super::get_special_styles;
 use super::visualize_if_modified;
+use crate::jots::EntryValue;
 
 
 pub fn entry(s: &mut Cursive, state_temp_clear: Arc<Mutex<AppState>>) { 
@@ -48,8 +49,9 @@ pub fn entry(s: &mut Cursive, state_temp_clear: Arc<Mutex<AppState>>) {
             )
             .button("Cancel", |s| { s.pop_layer(); })            
             .button("OK", move |s| {
-                let empty = String::from("Empty entry\n");
-                state_temp_clear.lock().unwrap().store.modify(&name, &empty);
+                // Fields and attachments are removed as well
+                let empty = EntryValue::from_text("Empty entry\n");
+                state_temp_clear.lock().unwrap().store.set_value(&name, &empty);
                 s.pop_layer();
                 visualize_if_modified(s, state_temp_clear.clone());
                 display_entry(s, state_temp_clear.clone(), &name, true);
@@ -32,7 +32,7 @@ pub fn contents(s: &mut Cursive, state_for_copy_entry: Arc<Mutex<AppState>>, sho
         }
     };
 
-    let h = match state_for_copy_entry.lock().unwrap().store.get(&entry_name) {
+    let h = match state_for_copy_entry.lock().unwrap().store.render(&entry_name, false) {
         Some(c) => c,
         None => { show_message(s, "Unable to read value of entry"); return }
     };
@@ -60,7 +60,7 @@ pub fn entry(s: &mut Cursive, state_for_copy_entry: Arc<Mutex<AppState>>, show_c
         }
     };
 
-    let mut h = match state_for_copy_entry.lock().unwrap().store.get(&entry_name) {
+    let mut h = match state_for_copy_entry.lock().unwrap().store.render(&entry_name, false) {
         Some(c) => c,
         None => { show_message(s, "Unable to read value of entry"); return }
     };
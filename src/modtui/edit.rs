@@ -122,7 +122,8 @@ pub fn entry(s: &mut Cursive, state_for_edit_entry: Arc<Mutex<AppState>>, entry_
     .button("OK", move |s| {
         let entry_text = match s.call_on_name(TEXT_AREA_NAME, |view: &mut TextArea| { String::from(view.get_content()) }) {
             Some(text_val) => {
-                if text_val.len() == 0 {
+                // Entries with fields do not need a text
+                if (text_val.len() == 0) && state_for_edit_entry.lock().unwrap().store.fields(&entry_to_edit).is_empty() {
                     show_message(s, "Entry text is empty"); 
                     return;
                 }
@@ -138,7 +139,7 @@ pub fn entry(s: &mut Cursive, state_for_edit_entry: Arc<Mutex<AppState>>, entry_
         s.pop_layer();
     })
     .button("Insert Password ...", move |s: &mut Cursive| {
-        pwgenerate::generate_password(s, state_for_gen_pw.clone());
+        pwgenerate::generate_password(s, state_for_gen_pw.clone(), Arc::new(insert_into_entry));
         refocus_dlg_element(s, DLG_EDIT, TEXT_AREA_NAME);
     })
     .button("Paste clipboard", move |s: &mut Cursive| {
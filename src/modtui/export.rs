@@ -137,7 +137,7 @@ fn create_html(data: &jots::Jots) -> String {
     for key in data {
         let mut line = String::from("<tr>\n");
 
-        let text = match data.get(key) {
+        let text = match data.render(key, false) {
             Some(t) => t,
             None => { return res }
         };
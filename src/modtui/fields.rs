@@ -0,0 +1,156 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, TextArea, Panel};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::get_selected_entry_name;
+use super::display_entry;
+use super::visualize_if_modified;
+use super::TEMPLATE_SEP;
+use crate::jots::{EntryField, FieldKind, STANDARD_FIELDS};
+
+const EDIT_FIELD_PREFIX: &str = "editfield_";
+const TEXT_CUSTOM_FIELDS: &str = "customfields";
+const LABEL_WIDTH: usize = 10;
+const FIELD_WIDTH: usize = 60;
+
+fn view_name(kind: FieldKind) -> String {
+    return format!("{}{}", EDIT_FIELD_PREFIX, kind.name());
+}
+
+// Custom fields are entered as lines of the form "Name: value"
+fn parse_custom_fields(text: &str) -> Result<Vec<EntryField>, String> {
+    let mut res: Vec<EntryField> = Vec::new();
+
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        match line.split_once(TEMPLATE_SEP) {
+            Some((name, value)) if !name.trim().is_empty() => res.push(EntryField::new(name.trim(), value.trim())),
+            _ => return Err(format!("Custom fields have to be of the form 'Name{}value'", TEMPLATE_SEP))
+        }
+    }
+
+    return Ok(res);
+}
+
+fn read_fields(s: &mut Cursive) -> Result<Vec<EntryField>, String> {
+    let mut res: Vec<EntryField> = Vec::new();
+
+    for kind in STANDARD_FIELDS {
+        let value = match kind {
+            FieldKind::Notes => s.call_on_name(&view_name(kind), |view: &mut TextArea| { String::from(view.get_content()) }),
+            _ => s.call_on_name(&view_name(kind), |view: &mut EditView| { String::from(view.get_content().as_str()) })
+        };
+
+        match value {
+            Some(v) => res.push(EntryField::new(kind.name(), &v)),
+            None => return Err(format!("Unable to read field '{}'", kind.name()))
+        }
+    }
+
+    let custom = match s.call_on_name(TEXT_CUSTOM_FIELDS, |view: &mut TextArea| { String::from(view.get_content()) }) {
+        Some(c) => c,
+        None => return Err(String::from("Unable to read custom fields"))
+    };
+
+    for f in parse_custom_fields(&custom)? {
+        if res.iter().any(|i| i.name.eq_ignore_ascii_case(&f.name)) {
+            return Err(format!("Field '{}' is defined more than once", &f.name));
+        }
+
+        res.push(f);
+    }
+
+    return Ok(res);
+}
+
+// Lets the user edit the structured fields of the selected entry. Fields without a value are removed.
+pub fn edit(s: &mut Cursive, state_for_fields: Arc<Mutex<AppState>>) {
+    let name = match get_selected_entry_name(s) {
+        Some(n) => n,
+        None => {
+            show_message(s, "Unable to determine selected entry");
+            return;
+        }
+    };
+
+    let value = match state_for_fields.lock().unwrap().store.value(&name) {
+        Some(v) => v,
+        None => { show_message(s, "Unable to read value of entry"); return }
+    };
+
+    let mut layout = LinearLayout::vertical();
+
+    for kind in STANDARD_FIELDS.iter().filter(|k| **k != FieldKind::Notes) {
+        let current = value.field(kind.name()).map(|f| f.value.clone()).unwrap_or_default();
+        let mut edit_view = EditView::new().content(current);
+        edit_view.set_secret(kind.is_hidden());
+
+        layout.add_child(LinearLayout::horizontal()
+            .child(TextView::new(kind.name()).fixed_width(LABEL_WIDTH))
+            .child(edit_view
+                .with_name(view_name(*kind))
+                .fixed_width(FIELD_WIDTH)));
+    }
+
+    let notes = value.field(FieldKind::Notes.name()).map(|f| f.value.clone()).unwrap_or_default();
+    let custom: String = value.fields.iter()
+        .filter(|f| f.kind() == FieldKind::Custom)
+        .map(|f| format!("{}{}{}\n", &f.name, TEMPLATE_SEP, &f.value))
+        .collect();
+
+    layout.add_child(TextView::new("\n"));
+    layout.add_child(Panel::new(TextArea::new()
+            .content(notes)
+            .with_name(view_name(FieldKind::Notes))
+            .fixed_width(LABEL_WIDTH + FIELD_WIDTH)
+            .min_height(4))
+        .title("Notes"));
+    layout.add_child(Panel::new(TextArea::new()
+            .content(custom)
+            .with_name(TEXT_CUSTOM_FIELDS)
+            .fixed_width(LABEL_WIDTH + FIELD_WIDTH)
+            .min_height(4))
+        .title(format!("Custom fields (Name{}value)", TEMPLATE_SEP)));
+
+    let res = Dialog::new()
+        .title(format!("Rustpwman fields of '{}'", &name))
+        .padding_lrtb(2, 2, 1, 1)
+        .content(layout)
+        .button("OK", move |s| {
+            let fields = match read_fields(s) {
+                Ok(f) => f,
+                Err(m) => { show_message(s, &m); return }
+            };
+
+            state_for_fields.lock().unwrap().store.set_fields(&name, &fields);
+
+            s.pop_layer();
+            visualize_if_modified(s, state_for_fields.clone());
+            display_entry(s, state_for_fields.clone(), &name, true);
+        })
+        .button("Show secrets", |s| {
+            for kind in STANDARD_FIELDS.iter().filter(|k| k.is_hidden()) {
+                s.call_on_name(&view_name(*kind), |view: &mut EditView| { view.set_secret(false) });
+            }
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
@@ -0,0 +1,112 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, SelectView};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::merge::apply;
+use crate::merge;
+use zeroize::Zeroize;
+
+const SELECT_HISTORY: &str = "historylist";
+
+
+// Turns the selected version back into the current data. The differences are applied like a merge
+// with the current data as common ancestor. This means that they can be reverted by Undo.
+fn restore(s: &mut Cursive, state_for_restore: Arc<Mutex<AppState>>, id: &str) {
+    let plan_res = {
+        let state = state_for_restore.lock().unwrap();
+        let mut password = state.get_password().unwrap_or_default();
+
+        let res = state.persister.read_historic(id).and_then(|data| {
+            let current = state.store.entries();
+            let old = state.store.decrypt_other(&data, &password)?;
+
+            return Ok(merge::plan(&current, &old, Some(&current)));
+        });
+
+        password.zeroize();
+        res
+    };
+
+    let plan = match plan_res {
+        Ok(p) => p,
+        Err(e) => {
+            show_message(s, &format!("Unable to read version: {}", e));
+            return;
+        }
+    };
+
+    s.pop_layer();
+
+    if plan.is_empty() {
+        show_message(s, "This version is identical to the current data");
+        return;
+    }
+
+    apply(s, state_for_restore, &plan);
+}
+
+pub fn show(s: &mut Cursive, state_for_history: Arc<Mutex<AppState>>) {
+    let history = match state_for_history.lock().unwrap().persister.history() {
+        Ok(h) => h,
+        Err(e) => {
+            show_message(s, &format!("Unable to read history: {}", e));
+            return;
+        }
+    };
+
+    if history.is_empty() {
+        show_message(s, "No history available");
+        return;
+    }
+
+    let mut select_view = SelectView::<String>::new();
+
+    for i in history {
+        select_view.add_item(format!("{}  {}", i.time_stamp, i.description), i.id);
+    }
+
+    let state_for_restore = state_for_history.clone();
+
+    let res = Dialog::new()
+        .title("Rustpwman history")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("Select a version to restore. The newest version is shown first.\n\n"))
+            .child(select_view
+                .with_name(SELECT_HISTORY)
+                .scrollable()
+                .min_height(5)
+                .max_height(15))
+        )
+        .button("Restore", move |s| {
+            let id = match s.call_on_name(SELECT_HISTORY, |view: &mut SelectView<String>| { view.selection() }) {
+                Some(Some(i)) => String::from(i.as_str()),
+                _ => { show_message(s, "No version selected"); return }
+            };
+
+            restore(s, state_for_restore.clone(), &id);
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
@@ -20,7 +20,7 @@ use cursive::views::{Dialog, TextView};
 use super::AppState;
 use super::show_message;
 use crate::{CfgSource, VERSION_STRING};
-use crate::fcrypt::{self, KdfId};
+use crate::fcrypt::{KdfId, KdfParams};
 
 
 pub fn show(s: &mut Cursive, state_for_info: Arc<Mutex<AppState>>) {
@@ -32,6 +32,8 @@ pub fn show(s: &mut Cursive, state_for_info: Arc<Mutex<AppState>>) {
     let config_type: CfgSource;
     let password_chached: bool;
     let kdf_id: KdfId;
+    let kdf_params: KdfParams;
+    let num_recipients: usize;
     
     info2 = match state_for_info.lock().unwrap().persister.get_canonical_path() {
         Ok(m) => m,
@@ -40,19 +42,29 @@ pub fn show(s: &mut Cursive, state_for_info: Arc<Mutex<AppState>>) {
 
     {
         let s = state_for_info.lock().unwrap();
-        let (deriver, id) = fcrypt::KdfId::Argon2.to_named_func();
-        algo_name = (s.store.cr_gen)(deriver, id).algo_name();
+        algo_name = s.store.algo_name();
         password_chached = s.pw_is_chached;
         config_file_name = s.cfg_name.clone();
         config_type = s.cfg_type;
-        kdf_id = s.kdf_id;
+        kdf_id = s.store.kdf_id();
+        kdf_params = s.store.kdf_params();
+        num_recipients = s.store.recipients().len();
     }
 
     msg_str.push_str(format!("Entry count  : {}\n", num_entries).as_str());
     msg_str.push_str(format!("Location     : {}\n", info2).as_str());
     msg_str.push_str(format!("Access method: {}\n", state_for_info.lock().unwrap().persister.get_type()).as_str());
+    if let Some(sync_state) = state_for_info.lock().unwrap().persister.sync_state() {
+        msg_str.push_str(format!("Sync state   : {}\n", sync_state).as_str());
+    }
     msg_str.push_str(format!("Cipher       : {}\n", algo_name).as_str());
     msg_str.push_str(format!("KDF          : {}\n", kdf_id.to_string()).as_str());
+    if kdf_params != KdfParams::None {
+        msg_str.push_str(format!("KDF params   : {}\n", kdf_params.to_string()).as_str());
+    }
+    if num_recipients > 0 {
+        msg_str.push_str(format!("Recipients   : {}\n", num_recipients).as_str());
+    }
     msg_str.push_str(format!("PW chached   : {}\n", password_chached).as_str());
     msg_str.push_str(format!("Config file  : {}\n", config_file_name).as_str());
     msg_str.push_str(format!("Config ref by: {}\n", config_type).as_str());
@@ -84,6 +96,15 @@ fn make_feature_string() -> String {
     #[cfg(feature = "webdav")]
     msg_str.push_str("- webdav\n");
 
+    #[cfg(feature = "ssh")]
+    msg_str.push_str("- ssh\n");
+
+    #[cfg(feature = "git")]
+    msg_str.push_str("- git\n");
+
+    #[cfg(feature = "s3")]
+    msg_str.push_str("- s3\n");
+
     #[cfg(feature = "chacha20")]
     msg_str.push_str("- ChaCha20Poly1305\n");
 
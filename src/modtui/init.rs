@@ -91,11 +91,33 @@ fn verify_passwords_with_names(s: &mut Cursive, ok_cb: &Box<dyn Fn(&mut Cursive,
     ok_cb(s, &pw2_text, false);
 }
 
-pub fn dialog(sndr: Arc<Sender<String>>, ok_cb: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>) -> impl View {
+// The containers in missing_parents are created by create_parents before ok_cb is called
+pub fn dialog(sndr: Arc<Sender<String>>, ok_cb: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, missing_parents: &Vec<String>,
+              create_parents: Box<dyn Fn() -> std::io::Result<()> + Send + Sync>) -> impl View {
     let sender = sndr.clone();
+    let mut msg = String::from("File not found! Enter a new password\nto create a new empty data file.\n\n");
+
+    if missing_parents.len() > 0 {
+        msg.push_str("The following collections do not exist\nand will be created as well:\n\n");
+
+        for p in missing_parents {
+            msg.push_str(&format!("{}\n", p));
+        }
+
+        msg.push_str("\n");
+    }
     
+    let create_cb: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync> = Box::new(move |s: &mut Cursive, password: &String, pw_cached: bool| {
+        if let Err(e) = create_parents() {
+            show_message(s, &format!("Unable to create collections: {}", e));
+            return;
+        }
+
+        ok_cb(s, password, pw_cached);
+    });
+
     let verify = move |s: &mut Cursive| {
-        verify_passwords(s, &ok_cb);
+        verify_passwords(s, &create_cb);
     };
 
     let res = Dialog::new()
@@ -103,7 +125,7 @@ pub fn dialog(sndr: Arc<Sender<String>>, ok_cb: Box<dyn Fn(&mut Cursive, &String
         .padding_lrtb(2, 2, 1, 1)
         .content(
             LinearLayout::vertical()
-            .child(TextView::new("File not found! Enter a new password\nto create a new empty data file.\n\n"))
+            .child(TextView::new(msg))
             .child(
                 LinearLayout::horizontal()
                     .child(TextView::new("New Password   : "))
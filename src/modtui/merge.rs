@@ -0,0 +1,261 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, Panel};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::redraw_tui;
+use super::visualize_if_modified;
+use super::PW_WIDTH;
+use crate::merge::{self, MergePlan};
+use zeroize::Zeroize;
+
+static EDIT_OTHER_FILE: &str = "mergeotherfile";
+static EDIT_OTHER_PW: &str = "mergeotherpw";
+static EDIT_BASE_FILE: &str = "mergebasefile";
+const FILE_WIDTH: usize = 50;
+const DELETED: &str = "<entry does not exist>";
+
+pub type MergeDone = Arc<dyn Fn(&mut Cursive, MergePlan) + Send + Sync>;
+
+
+fn get_text(s: &mut Cursive, name: &str) -> String {
+    return s.call_on_name(name, |view: &mut EditView| { String::from(view.get_content().as_str()) }).unwrap_or_default();
+}
+
+// Asks the user how to resolve each conflict of the plan, beginning with the one at position pos. When all
+// conflicts are resolved on_done is called. Nothing happens if the user cancels.
+pub fn resolve(s: &mut Cursive, plan: MergePlan, pos: usize, on_done: MergeDone) {
+    if pos >= plan.conflicts.len() {
+        on_done(s, plan);
+        return;
+    }
+
+    let conflict = &plan.conflicts[pos];
+    let mine = conflict.mine.as_ref().map(|v| v.render()).unwrap_or(String::from(DELETED));
+    let theirs = conflict.theirs.as_ref().map(|v| v.render()).unwrap_or(String::from(DELETED));
+    let msg = format!("Entry '{}' has been changed in both versions ({} of {})\n\n", conflict.key, pos + 1, plan.conflicts.len());
+
+    let plan_mine = plan.clone();
+    let plan_theirs = plan.clone();
+    let done_mine = on_done.clone();
+    let done_theirs = on_done.clone();
+
+    let res = Dialog::new()
+        .title("Rustpwman resolve conflict")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new(msg))
+            .child(LinearLayout::horizontal()
+                .child(Panel::new(TextView::new(mine).scrollable().fixed_size((40, 12))).title("Mine"))
+                .child(Panel::new(TextView::new(theirs).scrollable().fixed_size((40, 12))).title("Theirs")))
+        )
+        .button("Use mine", move |s| {
+            s.pop_layer();
+            let mut p = plan_mine.clone();
+            p.conflicts[pos].take_theirs = false;
+            resolve(s, p, pos + 1, done_mine.clone());
+        })
+        .button("Use theirs", move |s| {
+            s.pop_layer();
+            let mut p = plan_theirs.clone();
+            p.conflicts[pos].take_theirs = true;
+            resolve(s, p, pos + 1, done_theirs.clone());
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+// Applies a merge plan and shows what has been changed
+pub fn apply(s: &mut Cursive, state_for_apply: Arc<Mutex<AppState>>, plan: &MergePlan) {
+    let (added, modified, deleted) = state_for_apply.lock().unwrap().store.apply_merge(plan);
+
+    redraw_tui(s, state_for_apply.clone());
+    visualize_if_modified(s, state_for_apply.clone());
+
+    show_message(s, &format!("Added {}, changed {} and deleted {} entries.\nUse Undo to revert single changes before saving.", added, modified, deleted));
+}
+
+fn read_entries(state: &AppState, file_name: &str, password: &str) -> std::io::Result<merge::Entries> {
+    let raw_data = std::fs::read(file_name)?;
+
+    return state.store.decrypt_other(&raw_data, password);
+}
+
+pub fn with_file(s: &mut Cursive, state_for_merge: Arc<Mutex<AppState>>) {
+    let res = Dialog::new()
+        .title("Rustpwman merge with file")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("Changes in the other file are merged into the current data.\nLeave the password empty if it is the same as the current one.\n\n"))
+            .child(LinearLayout::horizontal()
+                .child(TextView::new("Other file     : "))
+                .child(EditView::new()
+                    .with_name(EDIT_OTHER_FILE)
+                    .fixed_width(FILE_WIDTH)))
+            .child(TextView::new("\n"))
+            .child(LinearLayout::horizontal()
+                .child(TextView::new("Password       : "))
+                .child(EditView::new()
+                    .secret()
+                    .with_name(EDIT_OTHER_PW)
+                    .fixed_width(PW_WIDTH)))
+            .child(TextView::new("\n"))
+            .child(LinearLayout::horizontal()
+                .child(TextView::new("Common ancestor: "))
+                .child(EditView::new()
+                    .with_name(EDIT_BASE_FILE)
+                    .fixed_width(FILE_WIDTH)))
+            .child(TextView::new("\nThe common ancestor is optional. It can for instance be a backup\nfrom which both versions have been derived."))
+        )
+        .button("OK", move |s| {
+            let other_file = String::from(get_text(s, EDIT_OTHER_FILE).trim());
+            let base_file = String::from(get_text(s, EDIT_BASE_FILE).trim());
+            let mut password = get_text(s, EDIT_OTHER_PW);
+
+            if other_file.len() == 0 {
+                show_message(s, "No file name given");
+                return;
+            }
+
+            let plan_res = {
+                let state = state_for_merge.lock().unwrap();
+
+                if password.len() == 0 {
+                    password = state.get_password().unwrap_or_default();
+                }
+
+                read_entries(&state, &other_file, &password).and_then(|theirs| {
+                    let base = match base_file.len() {
+                        0 => None,
+                        _ => Some(read_entries(&state, &base_file, &password)?)
+                    };
+
+                    return Ok(merge::plan(&state.store.entries(), &theirs, base.as_ref()));
+                })
+            };
+
+            password.zeroize();
+
+            let plan = match plan_res {
+                Ok(p) => p,
+                Err(e) => {
+                    show_message(s, &format!("Unable to read file: {}", e));
+                    return;
+                }
+            };
+
+            s.pop_layer();
+
+            if plan.is_empty() {
+                show_message(s, "There is nothing to merge");
+                return;
+            }
+
+            let state_for_apply = state_for_merge.clone();
+            resolve(s, plan, 0, Arc::new(move |s, p| { apply(s, state_for_apply.clone(), &p) }));
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+// Offers to merge changes which were made while the WebDAV server was not reachable and which could not be
+// uploaded because the file on the server has been changed in the meantime
+pub fn unsynced(s: &mut Cursive, state_for_sync: Arc<Mutex<AppState>>) {
+    if state_for_sync.lock().unwrap().persister.unsynced().is_none() {
+        return;
+    }
+
+    let state_for_merge = state_for_sync.clone();
+    let state_for_discard = state_for_sync.clone();
+
+    let res = Dialog::new()
+        .title("Rustpwman offline changes")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(TextView::new("Changes which were made while the server was not reachable could not be uploaded\nbecause the file on the server has been changed in the meantime.\n\nThe offline changes are shown as 'Theirs' when merging."))
+        .button("Merge", move |s| {
+            s.pop_layer();
+            merge_unsynced(s, state_for_merge.clone());
+        })
+        .button("Discard", move |s| {
+            s.pop_layer();
+            if let Err(e) = state_for_discard.lock().unwrap().persister.resolve_unsynced(true) {
+                show_message(s, &format!("Unable to discard offline changes: {}", e));
+            }
+        })
+        .button("Later", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+fn merge_unsynced(s: &mut Cursive, state_for_merge: Arc<Mutex<AppState>>) {
+    let plan_res = {
+        let state = state_for_merge.lock().unwrap();
+        let mut password = state.get_password().unwrap_or_default();
+
+        let res = match state.persister.unsynced() {
+            Some((pending, ancestor)) => state.store.decrypt_other(&pending, &password).and_then(|theirs| {
+                let base = match ancestor {
+                    Some(a) => Some(state.store.decrypt_other(&a, &password)?),
+                    None => None
+                };
+
+                return Ok(merge::plan(&state.store.entries(), &theirs, base.as_ref()));
+            }),
+            None => Ok(MergePlan::default())
+        };
+
+        password.zeroize();
+        res
+    };
+
+    let plan = match plan_res {
+        Ok(p) => p,
+        Err(e) => {
+            show_message(s, &format!("Unable to read offline changes: {}", e));
+            return;
+        }
+    };
+
+    if plan.is_empty() {
+        // The offline changes are already contained in the data on the server
+        if let Err(e) = state_for_merge.lock().unwrap().persister.resolve_unsynced(true) {
+            show_message(s, &format!("Unable to discard offline changes: {}", e));
+            return;
+        }
+
+        show_message(s, "There is nothing to merge");
+        return;
+    }
+
+    let state_for_apply = state_for_merge.clone();
+    resolve(s, plan, 0, Arc::new(move |s, p| {
+        if let Err(e) = state_for_apply.lock().unwrap().persister.resolve_unsynced(false) {
+            show_message(s, &format!("Unable to merge offline changes: {}", e));
+            return;
+        }
+
+        apply(s, state_for_apply.clone(), &p);
+    }));
+}
@@ -30,6 +30,7 @@ pub fn storage(s: &mut Cursive, password: &String, state: AppState) -> Option<Ap
         }
     };
     
+    // Missing collections have already been created by the dialog which asked for the new password
     if !does_exist {
         match state.store.persist(&mut state.persister, password) {
             Ok(_) => (),
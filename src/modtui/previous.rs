@@ -0,0 +1,134 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, SelectView, Panel};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::get_selected_entry_name;
+use super::display_entry;
+use super::visualize_if_modified;
+use crate::timeutil::format_secs;
+use crate::jots::{EntryValue, PreviousValue};
+
+const SELECT_PREVIOUS: &str = "previousvalues";
+const TEXT_PREVIOUS: &str = "previousvalue";
+
+fn format_time(t: Option<u64>) -> String {
+    return match t {
+        Some(secs) => format_secs(secs),
+        None => String::from("unknown")
+    };
+}
+
+// Previous values do not contain attachments
+fn to_entry_value(p: &PreviousValue) -> EntryValue {
+    return EntryValue { text: p.value.clone(), fields: p.fields.clone(), attachments: Vec::new() };
+}
+
+fn selected_value(state: &Arc<Mutex<AppState>>, name: &String, index: usize) -> Option<EntryValue> {
+    return state.lock().unwrap().store.previous_values(name).get(index).map(to_entry_value);
+}
+
+// Shows when the selected entry was created and changed and the values it had before. One of these values
+// can be restored. Restoring is a change which can be undone.
+pub fn show(s: &mut Cursive, state_for_previous: Arc<Mutex<AppState>>) {
+    let name = match get_selected_entry_name(s) {
+        Some(n) => n,
+        None => {
+            show_message(s, "Unable to determine selected entry");
+            return;
+        }
+    };
+
+    let (times, previous) = {
+        let state = state_for_previous.lock().unwrap();
+        (state.store.times(&name), state.store.previous_values(&name))
+    };
+
+    let times = match times {
+        Some(t) => t,
+        None => {
+            show_message(s, "Unable to determine selected entry");
+            return;
+        }
+    };
+
+    let info = format!("Created : {}\nModified: {}\n\n", format_time(times.created), format_time(times.modified));
+
+    if previous.is_empty() {
+        show_message(s, &format!("{}There are no previous values", info));
+        return;
+    }
+
+    // Newest value first
+    let mut select_view = SelectView::<usize>::new();
+    for (i, p) in previous.iter().enumerate().rev() {
+        select_view.add_item(format!("Replaced {}", format_secs(p.replaced)), i);
+    }
+
+    let state_for_select = state_for_previous.clone();
+    let name_for_select = name.clone();
+    let state_for_restore = state_for_previous.clone();
+    let first = previous.last().map(|p| to_entry_value(p).render(true)).unwrap_or_default();
+
+    let select_view = select_view.on_select(move |s, i| {
+        let value = selected_value(&state_for_select, &name_for_select, *i).unwrap_or_default().render(true);
+        s.call_on_name(TEXT_PREVIOUS, |view: &mut TextView| { view.set_content(value) });
+    });
+
+    let res = Dialog::new()
+        .title(format!("Rustpwman previous values of '{}'", &name))
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new(info))
+            .child(LinearLayout::horizontal()
+                .child(Panel::new(select_view
+                    .with_name(SELECT_PREVIOUS)
+                    .scrollable()
+                    .fixed_size((36, 12))))
+                .child(Panel::new(TextView::new(first)
+                    .with_name(TEXT_PREVIOUS)
+                    .scrollable()
+                    .fixed_size((50, 12)))))
+        )
+        .button("Restore", move |s| {
+            let index = match s.call_on_name(SELECT_PREVIOUS, |view: &mut SelectView<usize>| { view.selection() }) {
+                Some(Some(i)) => *i,
+                _ => { show_message(s, "No value selected"); return }
+            };
+
+            let mut value = match selected_value(&state_for_restore, &name, index) {
+                Some(v) => v,
+                None => { show_message(s, "Unable to read previous value"); return }
+            };
+
+            // Restoring a previous value keeps the current attachments
+            value.attachments = state_for_restore.lock().unwrap().store.value(&name).map(|v| v.attachments.clone()).unwrap_or_default();
+
+            let _ = state_for_restore.lock().unwrap().store.set_value(&name, &value);
+            s.pop_layer();
+            visualize_if_modified(s, state_for_restore.clone());
+            display_entry(s, state_for_restore.clone(), &name, true);
+        })
+        .button("Close", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
@@ -14,7 +14,7 @@ limitations under the License. */
 
 
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use cursive::Cursive;
 use cursive::views::{Dialog, LinearLayout, TextView, EditView, DialogFocus};
@@ -27,25 +27,44 @@ use crate::fcrypt;
 
 const NAME_PWEDIT : &str = "pwedit";
 const NAME_PWDIALOG: &str = "pwdialog";
+const NAME_KEYFILEEDIT: &str = "keyfileedit";
 
-fn pw_check(s: &mut Cursive, pw_text: &str, ok_cb_with_state: Arc<Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>>) {
+fn pw_check(s: &mut Cursive, pw_text: &str, key_file: &Option<Arc<Mutex<Option<String>>>>, ok_cb_with_state: Arc<Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>>) {
     if let Some(err) = fcrypt::check_password(pw_text) {
         show_message(s, &format!("Password incorrect: {}", err));
         return;
     }
 
+    if let Some(k) = key_file {
+        let key_file_name = match s.call_on_name(NAME_KEYFILEEDIT, |view: &mut EditView| {view.get_content()}) {
+            Some(n) => String::from(n.trim()),
+            None => { show_message(s, "Unable to read name of key file"); return }
+        };
+
+        if key_file_name.len() == 0 {
+            show_message(s, "This file can only be opened with a key file");
+            return;
+        }
+
+        *k.lock().unwrap() = Some(key_file_name);
+    }
+
     let pw = String::from(pw_text);
     ok_cb_with_state(s, &pw, false);
 }
 
-pub fn dialog(sndr: Arc<Sender<String>>, ok_cb_with_state: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>) -> impl View {
+// If key_file is not None the dialog also asks for the name of a key file. It is stored in key_file before
+// ok_cb_with_state is called.
+pub fn dialog(sndr: Arc<Sender<String>>, ok_cb_with_state: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, key_file: Option<Arc<Mutex<Option<String>>>>) -> impl View {
     let sender = sndr.clone();
 
     let cb_wrapped = Arc::new(ok_cb_with_state);
     let cb_for_submit = cb_wrapped.clone();
+    let key_file_for_submit = key_file.clone();
+    let key_file_for_ok = key_file.clone();
 
     let ok_on_submit = move |s: &mut Cursive, pw_text: &str| {
-        pw_check(s, pw_text, cb_for_submit.clone());
+        pw_check(s, pw_text, &key_file_for_submit, cb_for_submit.clone());
     };
 
     let ok_cb = move |s: &mut Cursive| {
@@ -54,29 +73,67 @@ pub fn dialog(sndr: Arc<Sender<String>>, ok_cb_with_state: Box<dyn Fn(&mut Cursi
             None => { show_message(s, "Unable to read password"); return }
         };
 
-        pw_check(s, pw_text.as_str(), cb_wrapped.clone());
+        pw_check(s, pw_text.as_str(), &key_file_for_ok, cb_wrapped.clone());
     };
 
+    let mut layout = LinearLayout::vertical()
+        .child(TextView::new("Please enter password of data file.\n\n"))
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Password: "))
+                .child(EditView::new()
+                    .secret()
+                    .on_submit(ok_on_submit)
+                    .with_name(NAME_PWEDIT)
+                    .fixed_width(PW_WIDTH))
+                .with_name("pwlinear")
+        );
+
+    if let Some(k) = &key_file {
+        let key_file_name = match k.lock().unwrap().clone() {
+            Some(n) => n,
+            None => String::from("")
+        };
+
+        layout.add_child(TextView::new("\nThis file also requires a key file.\n\n"));
+        layout.add_child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Key file: "))
+                .child(EditView::new()
+                    .content(key_file_name)
+                    .with_name(NAME_KEYFILEEDIT)
+                    .fixed_width(PW_WIDTH))
+        );
+    }
+
     let res = Dialog::new()
         .title("Rustpwman enter password")
         .padding_lrtb(2, 2, 1, 1)
+        .content(layout)
+        .button("OK", ok_cb)
+        .button("Cancel", move |s| pwman_quit(s, sender.clone(), String::from("")))
+        .with_name(NAME_PWDIALOG);
+
+    return res;
+}
+
+// Shared files which are opened with a secret X25519 key do not need a password
+pub fn identity_dialog(sndr: Arc<Sender<String>>, identity_file_name: &str, ok_cb_with_state: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>) -> impl View {
+    let sender = sndr.clone();
+
+    let ok_cb = move |s: &mut Cursive| {
+        ok_cb_with_state(s, &String::from(""), false);
+    };
+
+    let res = Dialog::new()
+        .title("Rustpwman open shared file")
+        .padding_lrtb(2, 2, 1, 1)
         .content(
             LinearLayout::vertical()
-            .child(TextView::new("Please enter password of data file.\n\n"))
-            .child(
-                LinearLayout::horizontal()
-                    .child(TextView::new("Password: "))
-                    .child(EditView::new()
-                        .secret()
-                        .on_submit(ok_on_submit)
-                        .with_name(NAME_PWEDIT)
-                        .fixed_width(PW_WIDTH))
-                    .with_name("pwlinear")
-            )
+                .child(TextView::new(format!("The file is opened with the identity stored in\n\n{}\n\nContinue?\n", identity_file_name)))
         )
         .button("OK", ok_cb)
-        .button("Cancel", move |s| pwman_quit(s, sender.clone(), String::from("")))
-        .with_name(NAME_PWDIALOG);
+        .button("Cancel", move |s| pwman_quit(s, sender.clone(), String::from("")));
 
     return res;
 }
@@ -88,7 +145,11 @@ pub fn show_pw_error(siv: &mut Cursive, msg: &str) {
             .button("Ok", |s| {
                 s.pop_layer();
 
-                s.call_on_name(NAME_PWEDIT, |view: &mut EditView| {view.set_content(String::from(""))}).unwrap()(s);
+                // The password entry dialog is not present if the file has been opened with an identity
+                if let Some(cb) = s.call_on_name(NAME_PWEDIT, |view: &mut EditView| {view.set_content(String::from(""))}) {
+                    cb(s);
+                }
+
                 s.call_on_name(NAME_PWDIALOG, |view: &mut Dialog| {view.set_focus(DialogFocus::Content)});
             }),
     );
@@ -14,7 +14,7 @@ limitations under the License. */
 
 
 use cursive::Cursive;
-use cursive::views::{Dialog, LinearLayout, TextView, EditView};
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, SelectView, Panel};
 use cursive::traits::*;
 use std::sync::{Arc, Mutex};
 use super::AppState;
@@ -27,6 +27,7 @@ use image::Luma;
 use std::fs;
 
 const QR_CODE_FILE_NAME: &str = "qrfile";
+const TEXT_OF_ENTRY: &str = "<text of entry>";
 
 fn ask_for_deletion(s: &mut Cursive, file_name: String) {
     let dlg = Dialog::new()
@@ -57,9 +58,8 @@ fn ask_for_deletion(s: &mut Cursive, file_name: String) {
 
 
 
+// Entries with fields let the user select whether the text or one of the fields is encoded
 pub fn create(s: &mut Cursive, state_for_create_qr_entry: Arc<Mutex<AppState>>) {
-    let state_for_open_viewer = state_for_create_qr_entry.clone();
-
     let entry_name = match get_selected_entry_name(s) {
         Some(name) => name,
         None => {
@@ -68,14 +68,50 @@ pub fn create(s: &mut Cursive, state_for_create_qr_entry: Arc<Mutex<AppState>>)
         }
     };
 
-    let h = match state_for_create_qr_entry.lock().unwrap().store.get(&entry_name) {
+    let value = match state_for_create_qr_entry.lock().unwrap().store.value(&entry_name) {
         Some(c) => c,
         None => { show_message(s, "Unable to read value of entry"); return }
     };
 
-    let trimmed = h.trim();
-    
-    let code = match QrCode::new(trimmed) {
+    if value.fields.is_empty() {
+        save(s, state_for_create_qr_entry, value.text.trim());
+        return;
+    }
+
+    let mut select_view = SelectView::<String>::new();
+
+    if !value.text.trim().is_empty() {
+        select_view.add_item(TEXT_OF_ENTRY, String::from(value.text.trim()));
+    }
+
+    for f in &value.fields {
+        select_view.add_item(f.name.clone(), f.value.clone());
+    }
+
+    let state_for_select = state_for_create_qr_entry.clone();
+
+    let select_view = select_view.on_submit(move |s, data: &String| {
+        s.pop_layer();
+        save(s, state_for_select.clone(), data.as_str());
+    });
+
+    let res = Dialog::new()
+    .title("Rustpwman select data for QR code")
+    .padding_lrtb(2, 2, 1, 1)
+    .content(
+        LinearLayout::vertical()
+        .child(TextView::new("Please select the value which is to be encoded.\n\n"))
+        .child(Panel::new(select_view
+            .scrollable()
+            .fixed_height(value.fields.len().min(10) + 1)))
+    )
+    .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+fn save(s: &mut Cursive, state_for_open_viewer: Arc<Mutex<AppState>>, data: &str) {
+    let code = match QrCode::new(data) {
         Ok(c) => c,
         Err(_) => {
             show_message(s, "Unable to encode data as QR code"); 
@@ -57,7 +57,7 @@ fn get_entries_int(state_for_q_clear: Arc<Mutex<AppState>>, clear: bool, formatt
     let mut res = String::from("");
 
     for i in &state.entry_queue {
-        let entry_data = match state.store.get(i) {
+        let entry_data = match state.store.render(i, false) {
             Some(t) => t,
             None => {
                 continue;
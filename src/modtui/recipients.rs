@@ -0,0 +1,262 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, SelectView};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::PW_WIDTH;
+use super::save;
+use crate::fcrypt;
+use zeroize::Zeroize;
+
+static SELECT_RECIPIENTS: &str = "recipientlist";
+static EDIT_OWNER: &str = "recipientowner";
+static EDIT_NAME: &str = "recipientname";
+static EDIT_SECRET1: &str = "recipientsecret1";
+static EDIT_SECRET2: &str = "recipientsecret2";
+static EDIT_REMOVE_PW: &str = "recipientremovepw";
+static DLG_RECIPIENTS: &str = "recipientsdlg";
+const KEY_WIDTH: usize = 46;
+
+
+fn get_text(s: &mut Cursive, name: &str) -> Option<String> {
+    return s.call_on_name(name, |view: &mut EditView| { String::from(view.get_content().as_str()) });
+}
+
+fn fill_list(s: &mut Cursive, state: Arc<Mutex<AppState>>) {
+    let (recipients, opened_by) = {
+        let state = state.lock().unwrap();
+        (state.store.recipients(), state.store.opened_by())
+    };
+
+    s.call_on_name(SELECT_RECIPIENTS, |view: &mut SelectView<String>| {
+        view.clear();
+
+        for (name, recipient_type) in recipients {
+            let marker = if opened_by.as_deref() == Some(name.as_str()) { " (you)" } else { "" };
+            view.add_item(format!("{} [{}]{}", name, recipient_type, marker), name);
+        }
+    });
+}
+
+pub fn manage(s: &mut Cursive, state_for_recipients: Arc<Mutex<AppState>>) {
+    if !state_for_recipients.lock().unwrap().store.is_shared() {
+        share(s, state_for_recipients);
+        return;
+    }
+
+    let state_add_pw = state_for_recipients.clone();
+    let state_add_key = state_for_recipients.clone();
+    let state_remove = state_for_recipients.clone();
+
+    let res = Dialog::new()
+        .title("Rustpwman recipients")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("These recipients are able to open the file:\n\n"))
+            .child(SelectView::<String>::new()
+                .with_name(SELECT_RECIPIENTS)
+                .scrollable()
+                .min_height(5))
+        )
+        .button("Add password ...", move |s| { add(s, state_add_pw.clone(), false) })
+        .button("Add public key ...", move |s| { add(s, state_add_key.clone(), true) })
+        .button("Remove", move |s| {
+            let name = match s.call_on_name(SELECT_RECIPIENTS, |view: &mut SelectView<String>| { view.selection() }) {
+                Some(Some(n)) => String::from(n.as_str()),
+                _ => { show_message(s, "No recipient selected"); return }
+            };
+
+            let needed = state_remove.lock().unwrap().store.passwords_needed_for_removal(&name);
+
+            if needed.is_empty() {
+                remove(s, state_remove.clone(), &name, &Vec::new());
+            } else {
+                ask_passwords(s, state_remove.clone(), name, needed);
+            }
+        })
+        .button("Close", |s| { s.pop_layer(); })
+        .with_name(DLG_RECIPIENTS);
+
+    s.add_layer(res);
+    fill_list(s, state_for_recipients);
+}
+
+// Removes a recipient and saves the file which is encrypted with a new data key
+fn remove(s: &mut Cursive, state_for_remove: Arc<Mutex<AppState>>, name: &str, passwords: &Vec<(String, String)>) {
+    if let Err(e) = state_for_remove.lock().unwrap().store.remove_recipient(name, passwords) {
+        show_message(s, &format!("{}", e));
+        return;
+    }
+
+    save::storage(s, state_for_remove.clone());
+    fill_list(s, state_for_remove);
+}
+
+// The new data key has to be wrapped for all other recipients who use a password. Therefore their passwords are needed.
+fn ask_passwords(s: &mut Cursive, state_for_remove: Arc<Mutex<AppState>>, name: String, needed: Vec<String>) {
+    let mut layout = LinearLayout::vertical()
+        .child(TextView::new(format!("Removing '{}' replaces the data key. Please enter the\npasswords of the other recipients who use a password.\n\n", &name)));
+    let width = needed.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+
+    for (i, n) in needed.iter().enumerate() {
+        layout.add_child(LinearLayout::horizontal()
+            .child(TextView::new(format!("{:width$}: ", n, width = width)))
+            .child(EditView::new()
+                .secret()
+                .with_name(format!("{}{}", EDIT_REMOVE_PW, i))
+                .fixed_width(PW_WIDTH)));
+    }
+
+    let res = Dialog::new()
+        .title("Rustpwman remove recipient")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(layout)
+        .button("OK", move |s| {
+            let mut passwords: Vec<(String, String)> = needed.iter().enumerate()
+                .map(|(i, n)| (n.clone(), get_text(s, &format!("{}{}", EDIT_REMOVE_PW, i)).unwrap_or_default()))
+                .collect();
+
+            s.pop_layer();
+            remove(s, state_for_remove.clone(), &name, &passwords);
+            passwords.zeroize();
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+// Turns the file into a shared file. The current password becomes the secret of the first recipient.
+fn share(s: &mut Cursive, state_for_share: Arc<Mutex<AppState>>) {
+    let res = Dialog::new()
+        .title("Rustpwman share file")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("The file is not shared yet. Your current password will be\nrecorded as the secret of the first recipient.\n\n"))
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new("Your name: "))
+                    .child(EditView::new()
+                        .with_name(EDIT_OWNER)
+                        .fixed_width(PW_WIDTH))
+            )
+        )
+        .button("OK", move |s| {
+            let owner = match get_text(s, EDIT_OWNER) {
+                Some(o) => String::from(o.trim()),
+                None => { show_message(s, "Unable to read name"); return }
+            };
+
+            if owner.len() == 0 {
+                show_message(s, "Name is empty");
+                return;
+            }
+
+            if let Err(e) = state_for_share.lock().unwrap().store.share(&owner) {
+                show_message(s, &format!("{}", e));
+                return;
+            }
+
+            s.pop_layer();
+            save::storage(s, state_for_share.clone());
+            manage(s, state_for_share.clone());
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+fn add(s: &mut Cursive, state_for_add: Arc<Mutex<AppState>>, with_public_key: bool) {
+    let mut layout = LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Name        : "))
+                .child(EditView::new()
+                    .with_name(EDIT_NAME)
+                    .fixed_width(PW_WIDTH))
+        )
+        .child(TextView::new("\n"));
+
+    if with_public_key {
+        layout.add_child(LinearLayout::horizontal()
+            .child(TextView::new("Public key  : "))
+            .child(EditView::new()
+                .with_name(EDIT_SECRET1)
+                .fixed_width(KEY_WIDTH)));
+    } else {
+        layout.add_child(LinearLayout::horizontal()
+            .child(TextView::new("Password    : "))
+            .child(EditView::new()
+                .secret()
+                .with_name(EDIT_SECRET1)
+                .fixed_width(PW_WIDTH)));
+        layout.add_child(TextView::new("\n"));
+        layout.add_child(LinearLayout::horizontal()
+            .child(TextView::new("Verification: "))
+            .child(EditView::new()
+                .secret()
+                .with_name(EDIT_SECRET2)
+                .fixed_width(PW_WIDTH)));
+    }
+
+    let res = Dialog::new()
+        .title("Rustpwman add recipient")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(layout)
+        .button("OK", move |s| {
+            let name = String::from(get_text(s, EDIT_NAME).unwrap_or_default().trim());
+            let secret = get_text(s, EDIT_SECRET1).unwrap_or_default();
+
+            let add_res = if with_public_key {
+                state_for_add.lock().unwrap().store.add_public_key_recipient(&name, secret.trim())
+            } else {
+                if get_text(s, EDIT_SECRET2).unwrap_or_default() != secret {
+                    show_message(s, "Passwords not equal!");
+                    return;
+                }
+
+                if secret.len() == 0 {
+                    show_message(s, "Password is empty");
+                    return;
+                }
+
+                if let Some(e) = fcrypt::check_password(&secret) {
+                    show_message(s, &format!("{}", e));
+                    return;
+                }
+
+                state_for_add.lock().unwrap().store.add_password_recipient(&name, &secret)
+            };
+
+            if let Err(e) = add_res {
+                show_message(s, &format!("{}", e));
+                return;
+            }
+
+            s.pop_layer();
+            save::storage(s, state_for_add.clone());
+            fill_list(s, state_for_add.clone());
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
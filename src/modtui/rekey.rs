@@ -0,0 +1,101 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::save;
+use crate::fcrypt::{CipherId, KdfId, KdfParams};
+use crate::tuiconfig::create_algo_selection_ui;
+
+static EDIT_KDF_PARAMS: &str = "rekeykdfparams";
+
+
+pub fn change(s: &mut Cursive, state_for_rekey: Arc<Mutex<AppState>>) {
+    let current_kdf: KdfId;
+    let current_params: KdfParams;
+    let current_cipher: CipherId;
+
+    {
+        let state = state_for_rekey.lock().unwrap();
+        current_kdf = state.store.kdf_id();
+        current_params = state.store.kdf_params();
+        current_cipher = state.store.cipher_id();
+    }
+
+    let (linear_layout_pbkdf, mut pbkdf_group) = create_algo_selection_ui(current_kdf, "Key derivation function: ");
+    let (linear_layout_cipher, cipher_group) = create_algo_selection_ui(current_cipher, "Encryption algorithm   : ");
+
+    // Show the current parameters for the current PBKDF and the defaults for all others
+    pbkdf_group = pbkdf_group.on_change(move |s: &mut Cursive, selected: &KdfId| {
+        let params = if *selected == current_kdf { current_params } else { KdfParams::default_for(*selected) };
+        s.call_on_name(EDIT_KDF_PARAMS, |view: &mut EditView| { view.set_content(params.to_string()) });
+    });
+
+    let mut layout = LinearLayout::vertical()
+        .child(linear_layout_pbkdf)
+        .child(TextView::new("\n"));
+
+    if CipherId::get_known_ids().len() > 1 {
+        layout.add_child(linear_layout_cipher);
+        layout.add_child(TextView::new("\n"));
+    }
+
+    layout.add_child(LinearLayout::horizontal()
+        .child(TextView::new("KDF parameters         : "))
+        .child(EditView::new()
+            .content(current_params.to_string())
+            .with_name(EDIT_KDF_PARAMS)
+            .fixed_width(30)));
+
+    let res = Dialog::new()
+        .title("Rustpwman change encryption")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(layout)
+        .button("OK", move |s| {
+            let kdf_id = *pbkdf_group.selection();
+            let cipher_id = *cipher_group.selection();
+
+            let params_text = match s.call_on_name(EDIT_KDF_PARAMS, |view: &mut EditView| {view.get_content()}) {
+                Some(t) => String::from(t.trim()),
+                None => { show_message(s, "Unable to read KDF parameters"); return }
+            };
+
+            let kdf_params = if params_text.len() == 0 {
+                KdfParams::default_for(kdf_id)
+            } else {
+                match KdfParams::from_str(kdf_id, &params_text) {
+                    Ok(p) => p,
+                    Err(e) => { show_message(s, &format!("Invalid KDF parameters: {}", e)); return }
+                }
+            };
+
+            if let Err(e) = state_for_rekey.lock().unwrap().store.rekey(cipher_id, kdf_id, kdf_params) {
+                show_message(s, &format!("{}", e));
+                return;
+            }
+
+            s.pop_layer();
+            save::storage(s, state_for_rekey.clone());
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
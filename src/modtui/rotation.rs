@@ -0,0 +1,214 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, SelectView, Panel};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::get_selected_entry_name;
+use super::display_entry;
+use super::visualize_if_modified;
+use super::pwgenerate;
+use crate::jots::{now_secs, EntryExpiry};
+use crate::timeutil::{format_date, parse_date};
+
+const EDIT_EXPIRY_DATE: &str = "editexpirydate";
+const EDIT_ROTATION_DAYS: &str = "editrotationdays";
+const SELECT_DUE: &str = "selectdue";
+
+fn format_due(due: Option<u64>) -> String {
+    return match due {
+        Some(d) => format_date(d),
+        None => String::from("never")
+    };
+}
+
+fn read_expiry(s: &mut Cursive) -> Result<EntryExpiry, String> {
+    let date = s.call_on_name(EDIT_EXPIRY_DATE, |view: &mut EditView| { view.get_content() }).ok_or("Unable to read expiry date")?;
+    let days = s.call_on_name(EDIT_ROTATION_DAYS, |view: &mut EditView| { view.get_content() }).ok_or("Unable to read rotation interval")?;
+
+    let expires = match date.trim() {
+        "" => None,
+        d => Some(parse_date(d).ok_or(format!("'{}' is not a date of the form YYYY-MM-DD", d))?)
+    };
+
+    let rotation_days = match days.trim() {
+        "" => None,
+        d => match d.parse::<u32>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Err(format!("'{}' is not a positive number of days", d))
+        }
+    };
+
+    return Ok(EntryExpiry { expires, rotation_days });
+}
+
+// Sets the date at which the selected entry expires and the number of days after which its password
+// has to be changed
+pub fn edit(s: &mut Cursive, state_for_expiry: Arc<Mutex<AppState>>) {
+    let name = match get_selected_entry_name(s) {
+        Some(n) => n,
+        None => {
+            show_message(s, "Unable to determine selected entry");
+            return;
+        }
+    };
+
+    let (expiry, due) = {
+        let state = state_for_expiry.lock().unwrap();
+        (state.store.expiry(&name).unwrap_or_default(), state.store.due_date(&name))
+    };
+
+    let res = Dialog::new()
+        .title(format!("Rustpwman expiry of '{}'", &name))
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new(format!("Leave a value empty to remove it. Currently due: {}\n\n", format_due(due))))
+            .child(LinearLayout::horizontal()
+                .child(TextView::new("Expires on (YYYY-MM-DD): "))
+                .child(EditView::new()
+                    .content(expiry.expires.map(format_date).unwrap_or_default())
+                    .with_name(EDIT_EXPIRY_DATE)
+                    .fixed_width(12)))
+            .child(LinearLayout::horizontal()
+                .child(TextView::new("Rotate every (days)   : "))
+                .child(EditView::new()
+                    .content(expiry.rotation_days.map(|d| d.to_string()).unwrap_or_default())
+                    .with_name(EDIT_ROTATION_DAYS)
+                    .fixed_width(12)))
+        )
+        .button("OK", move |s| {
+            let expiry = match read_expiry(s) {
+                Ok(e) => e,
+                Err(msg) => { show_message(s, &msg); return }
+            };
+
+            state_for_expiry.lock().unwrap().store.set_expiry(&name, expiry);
+
+            s.pop_layer();
+            visualize_if_modified(s, state_for_expiry.clone());
+            display_entry(s, state_for_expiry.clone(), &name, true);
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+fn fill_list(s: &mut Cursive, state: &Arc<Mutex<AppState>>) {
+    let due = state.lock().unwrap().store.due(now_secs());
+
+    s.call_on_name(SELECT_DUE, |view: &mut SelectView<String>| {
+        view.clear();
+        for (name, d) in due {
+            view.add_item(format!("{} (due {})", &name, format_date(d)), name);
+        }
+    });
+}
+
+// Replaces the password of an entry by a newly generated one. The old value is kept as a previous value
+// of the entry. A reached expiry date is removed as the password is no longer expired.
+fn rotate(s: &mut Cursive, state_for_rotate: Arc<Mutex<AppState>>, name: String) {
+    let value = match state_for_rotate.lock().unwrap().store.value(&name) {
+        Some(v) => v,
+        None => { show_message(s, "Unable to read entry"); return }
+    };
+
+    if value.with_password("").is_none() {
+        show_message(s, "Unable to locate the password of the entry. Please use a Password field\nor a single line starting with 'Password:'.");
+        return;
+    }
+
+    let state_for_consumer = state_for_rotate.clone();
+
+    pwgenerate::generate_password(s, state_for_rotate, Arc::new(move |s: &mut Cursive, new_pw: String| {
+        {
+            let mut state = state_for_consumer.lock().unwrap();
+
+            let new_value = match state.store.value(&name).and_then(|v| v.with_password(&new_pw)) {
+                Some(v) => v,
+                None => { drop(state); show_message(s, "Unable to change password"); return }
+            };
+
+            state.store.set_value(&name, &new_value);
+
+            if let Some(mut expiry) = state.store.expiry(&name) {
+                if expiry.expires.is_some_and(|e| e <= now_secs()) {
+                    expiry.expires = None;
+                    state.store.set_expiry(&name, expiry);
+                }
+            }
+        }
+
+        visualize_if_modified(s, state_for_consumer.clone());
+        display_entry(s, state_for_consumer.clone(), &name, true);
+        fill_list(s, &state_for_consumer);
+    }));
+}
+
+// Lists all entries which are due for rotation
+pub fn show(s: &mut Cursive, state_for_due: Arc<Mutex<AppState>>) {
+    if state_for_due.lock().unwrap().store.due(now_secs()).is_empty() {
+        show_message(s, "No entries are due for rotation");
+        return;
+    }
+
+    show_if_due(s, state_for_due);
+}
+
+// Called after a file has been opened. Nothing is shown if no entry is due.
+pub fn show_if_due(s: &mut Cursive, state_for_due: Arc<Mutex<AppState>>) {
+    if state_for_due.lock().unwrap().store.due(now_secs()).is_empty() {
+        return;
+    }
+
+    let state_for_rotate = state_for_due.clone();
+    let state_for_show = state_for_due.clone();
+
+    let res = Dialog::new()
+        .title("Rustpwman entries due for rotation")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("The passwords of these entries have expired or have not been changed\nwithin their rotation interval.\n\n"))
+            .child(Panel::new(SelectView::<String>::new()
+                .with_name(SELECT_DUE)
+                .scrollable()
+                .fixed_size((60, 12))))
+        )
+        .button("Generate new password", move |s| {
+            match s.call_on_name(SELECT_DUE, |view: &mut SelectView<String>| { view.selection() }) {
+                Some(Some(name)) => rotate(s, state_for_rotate.clone(), name.to_string()),
+                _ => show_message(s, "No entry selected")
+            }
+        })
+        .button("Show entry", move |s| {
+            match s.call_on_name(SELECT_DUE, |view: &mut SelectView<String>| { view.selection() }) {
+                Some(Some(name)) => {
+                    s.pop_layer();
+                    display_entry(s, state_for_show.clone(), &name, true);
+                },
+                _ => show_message(s, "No entry selected")
+            }
+        })
+        .button("Close", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+    fill_list(s, &state_for_due);
+}
@@ -16,10 +16,15 @@ limitations under the License. */
 use std::sync::{Arc, Mutex};
 
 use cursive::Cursive;
+use cursive::views::Dialog;
+use std::io::{Error, ErrorKind};
 
 use super::AppState;
 use super::show_message;
 use super::visualize_if_modified;
+use super::redraw_tui;
+use super::merge;
+use crate::persist;
 
 pub fn storage(s: &mut Cursive, state_temp_save: Arc<Mutex<AppState>>) {
     // force release of mutable reference to state_temp_save before
@@ -28,10 +33,125 @@ pub fn storage(s: &mut Cursive, state_temp_save: Arc<Mutex<AppState>>) {
         let mut mut_state = state_temp_save.lock().unwrap();
 
         if let Err(e) = mut_state.persist_store() {
-            show_message(s, &format!("Unable to save: {}", e));
+            drop(mut_state);
+
+            if persist::is_conflict(&e) {
+                show_conflict(s, state_temp_save.clone());
+            } else {
+                show_message(s, &format!("Unable to save: {}", e));
+            }
+
             return; 
         }        
     }
 
     visualize_if_modified(s, state_temp_save.clone());
-}
\ No newline at end of file
+}
+
+fn get_password(state: &AppState) -> std::io::Result<String> {
+    return match state.get_password() {
+        Some(p) => Ok(p),
+        None => Err(Error::new(ErrorKind::Other, "No password available"))
+    };
+}
+
+// Offers to resolve a conflict between the data in memory and data which has been written by someone else
+fn show_conflict(s: &mut Cursive, state_for_conflict: Arc<Mutex<AppState>>) {
+    let state_reload = state_for_conflict.clone();
+    let state_overwrite = state_for_conflict.clone();
+    let state_merge = state_for_conflict.clone();
+
+    let msg = "The password file has been changed by someone else since it was read.\n\n\
+               Reload   : Discard your changes and use the stored data\n\
+               Overwrite: Replace the stored data by your version\n\
+               Merge    : Combine both versions and save the result";
+
+    s.add_layer(
+        Dialog::text(msg)
+            .title("Rustpwman conflict")
+            .button("Reload", move |s| {
+                s.pop_layer();
+                reload(s, state_reload.clone());
+            })
+            .button("Overwrite", move |s| {
+                s.pop_layer();
+                overwrite(s, state_overwrite.clone());
+            })
+            .button("Merge", move |s| {
+                s.pop_layer();
+                merge(s, state_merge.clone());
+            })
+            .button("Cancel", |s| { s.pop_layer(); })
+    );
+}
+
+fn reload(s: &mut Cursive, state_for_reload: Arc<Mutex<AppState>>) {
+    let res = {
+        let mut state = state_for_reload.lock().unwrap();
+        let state = &mut *state;
+
+        get_password(state).and_then(|pw| state.store.retrieve(&mut state.persister, &pw))
+    };
+
+    if let Err(e) = res {
+        show_message(s, &format!("Unable to reload: {}", e));
+        return;
+    }
+
+    redraw_tui(s, state_for_reload.clone());
+    visualize_if_modified(s, state_for_reload.clone());
+}
+
+fn overwrite(s: &mut Cursive, state_for_overwrite: Arc<Mutex<AppState>>) {
+    // Reading the stored data makes the persister accept it as the version which is overwritten
+    let res = state_for_overwrite.lock().unwrap().persister.retrieve();
+
+    if let Err(e) = res {
+        show_message(s, &format!("Unable to overwrite: {}", e));
+        return;
+    }
+
+    storage(s, state_for_overwrite);
+}
+
+fn merge(s: &mut Cursive, state_for_merge: Arc<Mutex<AppState>>) {
+    let res = {
+        let mut state = state_for_merge.lock().unwrap();
+        let state = &mut *state;
+
+        get_password(state).and_then(|pw| state.store.plan_merge_stored(&mut state.persister, &pw))
+    };
+
+    let (plan, stored_data) = match res {
+        Ok(r) => r,
+        Err(e) => {
+            show_message(s, &format!("Unable to merge: {}", e));
+            return;
+        }
+    };
+
+    let state_for_apply = state_for_merge.clone();
+
+    // The stored data is only accepted as the new base after all conflicts have been resolved
+    merge::resolve(s, plan, 0, Arc::new(move |s, p| {
+        let res = {
+            let mut state = state_for_apply.lock().unwrap();
+            let state = &mut *state;
+
+            get_password(state).and_then(|pw| state.store.adopt_stored(&mut state.persister, &pw, &stored_data))
+        };
+
+        if let Err(e) = res {
+            if persist::is_conflict(&e) {
+                show_message(s, "The password file has been changed again. Please save again.");
+            } else {
+                show_message(s, &format!("Unable to merge: {}", e));
+            }
+            return;
+        }
+
+        state_for_apply.lock().unwrap().store.apply_merge(&p);
+        redraw_tui(s, state_for_apply.clone());
+        storage(s, state_for_apply.clone());
+    }));
+}
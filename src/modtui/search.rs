@@ -50,7 +50,7 @@ pub fn perform_search(s: &mut Cursive, state_for_add_entry: Arc<Mutex<AppState>>
     // create artificial scope to ensure unlocking of global state
     {
         let state = state_for_add_entry.lock().unwrap();
-        search_res = state.store.search(&search_term);
+        search_res = state.store.search(&search_term, None);
     }
 
     if search_res.is_empty() {
@@ -0,0 +1,134 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::sync::{Arc, Mutex};
+
+use cursive::Cursive;
+use cursive::views::{Dialog, LinearLayout, TextView, EditView, SelectView};
+use cursive::traits::*;
+
+use super::AppState;
+use super::show_message;
+use super::get_selected_entry_name;
+use super::display_entry;
+use super::redraw_tui;
+use super::visualize_if_modified;
+
+const EDIT_TAGS: &str = "edittags";
+const SELECT_TAG: &str = "selecttag";
+const TAGS_WIDTH: usize = 60;
+
+
+pub fn edit(s: &mut Cursive, state_for_tags: Arc<Mutex<AppState>>) {
+    let name = match get_selected_entry_name(s) {
+        Some(n) => n,
+        None => {
+            show_message(s, "Unable to determine selected entry");
+            return;
+        }
+    };
+
+    let current = state_for_tags.lock().unwrap().store.tags(&name).join(", ");
+
+    let res = Dialog::new()
+        .title(format!("Rustpwman tags of '{}'", &name))
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("Separate tags by commas. Use a slash to create folders, e.g. work/servers.\n\n"))
+            .child(LinearLayout::horizontal()
+                .child(TextView::new("Tags: "))
+                .child(EditView::new()
+                    .content(current)
+                    .with_name(EDIT_TAGS)
+                    .fixed_width(TAGS_WIDTH)))
+        )
+        .button("OK", move |s| {
+            let text = match s.call_on_name(EDIT_TAGS, |view: &mut EditView| { view.get_content() }) {
+                Some(t) => t,
+                None => { show_message(s, "Unable to read tags"); return }
+            };
+
+            let tags: Vec<String> = text.split(',').map(String::from).collect();
+            state_for_tags.lock().unwrap().store.set_tags(&name, &tags);
+
+            s.pop_layer();
+            visualize_if_modified(s, state_for_tags.clone());
+            redraw_tui(s, state_for_tags.clone());
+            display_entry(s, state_for_tags.clone(), &name, true);
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
+
+// Restricts the entries in the main window to those with a selected tag
+pub fn filter(s: &mut Cursive, state_for_filter: Arc<Mutex<AppState>>) {
+    let (tags, current) = {
+        let state = state_for_filter.lock().unwrap();
+        (state.store.all_tags(), state.tag_filter.clone())
+    };
+
+    if tags.is_empty() {
+        show_message(s, "No entry has a tag");
+        return;
+    }
+
+    let mut select_view = SelectView::<Option<String>>::new();
+    select_view.add_item("<all entries>", None);
+
+    for t in tags {
+        select_view.add_item(t.clone(), Some(t));
+    }
+
+    let pos = select_view.iter().position(|(_, t)| *t == current);
+    if let Some(p) = pos {
+        let _ = select_view.set_selection(p);
+    }
+
+    let state_for_select = state_for_filter.clone();
+
+    let select_view = select_view.on_submit(move |s, tag: &Option<String>| {
+        state_for_select.lock().unwrap().tag_filter = tag.clone();
+        s.pop_layer();
+        redraw_tui(s, state_for_select.clone());
+    });
+
+    let res = Dialog::new()
+        .title("Rustpwman filter by tag")
+        .padding_lrtb(2, 2, 1, 1)
+        .content(
+            LinearLayout::vertical()
+            .child(TextView::new("Only entries with the selected tag are shown.\n\n"))
+            .child(select_view
+                .with_name(SELECT_TAG)
+                .scrollable()
+                .min_width(30)
+                .max_height(15))
+        )
+        .button("Select", move |s| {
+            let tag = match s.call_on_name(SELECT_TAG, |view: &mut SelectView<Option<String>>| { view.selection() }) {
+                Some(Some(t)) => (*t).clone(),
+                _ => { show_message(s, "No tag selected"); return }
+            };
+
+            state_for_filter.lock().unwrap().tag_filter = tag;
+            s.pop_layer();
+            redraw_tui(s, state_for_filter.clone());
+        })
+        .button("Cancel", |s| { s.pop_layer(); });
+
+    s.add_layer(res);
+}
@@ -25,6 +25,7 @@ use super::show_message;
 use super::get_selected_entry_name;
 use crate::clip::set_clipboard;
 use crate::clip::execute_viewer;
+use crate::jots::EntryValue;
 
 const SELECT_VIEW: &str = "templ_key_select";
 const DLG_TEMPL: &str = "templ_dialog";
@@ -54,15 +55,8 @@ pub fn parse_entry(entry: &String, keys: &Vec<String>) -> (HashMap<String, Strin
     return (values, counts);
 }
 
-fn retrieve_template_value(state_for_copy_entry: Arc<Mutex<AppState>>, template_key: &String, content: &String) -> Result<String, String> {
-    let known_keys: Vec<String>;
-
-    {
-        let state = state_for_copy_entry.lock().unwrap();
-        known_keys = state.template_strings.clone();
-    }
-
-    let (kv, kv_count) = parse_entry(content, &known_keys);
+pub fn get_template_value(content: &String, known_keys: &Vec<String>, template_key: &String) -> Result<String, String> {
+    let (kv, kv_count) = parse_entry(content, known_keys);
     match kv_count.get(template_key) {
         None => { return Err(String::from("Template string not found")); }
         Some(c) => {
@@ -78,7 +72,27 @@ fn retrieve_template_value(state_for_copy_entry: Arc<Mutex<AppState>>, template_
     return Ok(templ_val);
 }
 
-fn get_selected_content(s: &mut Cursive, state_for_copy_entry: Arc<Mutex<AppState>>) -> Result<String, String> {
+// A structured field takes precedence. Only if the entry has no field with this name its text is searched.
+pub fn get_field_value(value: &EntryValue, known_keys: &Vec<String>, template_key: &String) -> Result<String, String> {
+    if let Some(f) = value.field(template_key) {
+        return Ok(f.value.clone());
+    }
+
+    return get_template_value(&String::from(value.text.trim()), known_keys, template_key);
+}
+
+fn retrieve_template_value(state_for_copy_entry: Arc<Mutex<AppState>>, template_key: &String, content: &EntryValue) -> Result<String, String> {
+    let known_keys: Vec<String>;
+
+    {
+        let state = state_for_copy_entry.lock().unwrap();
+        known_keys = state.template_strings.clone();
+    }
+
+    return get_field_value(content, &known_keys, template_key);
+}
+
+fn get_selected_content(s: &mut Cursive, state_for_copy_entry: Arc<Mutex<AppState>>) -> Result<EntryValue, String> {
     let entry_name = match get_selected_entry_name(s) {
         Some(name) => name,
         None => {
@@ -86,19 +100,10 @@ fn get_selected_content(s: &mut Cursive, state_for_copy_entry: Arc<Mutex<AppStat
         }
     };
 
-    let content: String;
-
-    {
-        let state =  state_for_copy_entry.lock().unwrap();
-        let h = match state.store.get(&entry_name) {
-            Some(c) => c,
-            None => { return Err(String::from("Unable to read value of entry")); }
-        };
-
-        content = String::from(h.trim());
-    }
-
-    return Ok(content);
+    return match state_for_copy_entry.lock().unwrap().store.value(&entry_name) {
+        Some(c) => Ok(c),
+        None => Err(String::from("Unable to read value of entry"))
+    };
 }
 
 pub fn to_clipboard(s: &mut Cursive, state_for_copy_entry: Arc<Mutex<AppState>>, template_key: &String, close_parent: bool) {
@@ -212,7 +217,17 @@ pub fn retrieve(s: &mut Cursive, state_for_templ_get: Arc<Mutex<AppState>>) {
     let state_for_f9_callback = state_for_templ_get.clone();
     let state_for_retr_only = state_for_templ_get.clone();
 
-    let known_template_keys = state_for_templ_get.lock().unwrap().template_strings.clone();
+    let mut known_template_keys = state_for_templ_get.lock().unwrap().template_strings.clone();
+
+    // The fields of the selected entry can be retrieved in the same way as template strings
+    if let Some(name) = get_selected_entry_name(s) {
+        for f in state_for_templ_get.lock().unwrap().store.fields(&name) {
+            if !known_template_keys.iter().any(|k| k.eq_ignore_ascii_case(&f.name)) {
+                known_template_keys.push(f.name.clone());
+            }
+        }
+    }
+
     let num_templ_strings = known_template_keys.len();
 
     let mut select_view = SelectView::<String>::new();
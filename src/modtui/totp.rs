@@ -29,6 +29,7 @@ use super::AppState;
 use super::show_message;
 use super::get_selected_entry_name;
 use crate::fcrypt::totpcalc;
+use crate::jots::FieldKind;
 use crate::clip::set_clipboard;
 use super::get_special_styles;
 
@@ -44,7 +45,7 @@ pub fn show(s: &mut Cursive, state: Arc<Mutex<AppState>>) {
         }
     };
 
-    let entry_content = match state.lock().unwrap().store.get(&entry_name) {
+    let entry_value = match state.lock().unwrap().store.value(&entry_name) {
         Some(c) => c,
         None => {
             show_message(s, "Unable to read value of entry");
@@ -52,9 +53,15 @@ pub fn show(s: &mut Cursive, state: Arc<Mutex<AppState>>) {
         }
     };
 
-    let opt_params = totpcalc::TotpParams::from_totp_params(entry_content.clone());
+    // A TOTP field can either contain an otpauth:// URL or only the secret
+    let opt_params = match entry_value.field(FieldKind::Totp.name()) {
+        Some(f) if f.value.contains("otpauth://") => totpcalc::TotpParams::from_totp_params(f.value.clone()),
+        Some(f) => totpcalc::TotpParams::from_secret(&f.value),
+        None => totpcalc::TotpParams::from_totp_params(entry_value.text.clone())
+    };
+
     if opt_params.is_none() {
-        show_message(s, "Selected entry does not contain a parseable otpauth:// URL");
+        show_message(s, "Selected entry does not contain a parseable otpauth:// URL or TOTP secret");
         return;
     }
 
@@ -13,8 +13,6 @@ See the License for the specific language governing permissions and
 limitations under the License. */
 
 
-#[cfg(feature = "writebackup")]
-use std::fs;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
@@ -37,13 +35,17 @@ use super::pwentry;
 #[cfg(feature = "pwmanclient")]
 use super::cache;
 use super::init;
+use super::show_message;
 use super::export;
+use super::merge;
+use super::rotation;
 use crate::RustPwMan;
 
 
 #[allow(unused_variables)]
 pub fn main(app: &RustPwMan, data_file_name: String, default_sec_bits: usize, derive_func: KeyDeriver, deriver_id: fcrypt::KdfId, default_pw_gen: GenerationStrategy,
-            paste_cmd: String, copy_cmd: String, make_default: persist::PersistCreator, crypt_gen: Box<dyn Fn() -> CryptorGen + Send + Sync>, export: bool, qr_viewer: Option<String>) {
+            paste_cmd: String, copy_cmd: String, make_default: persist::PersistCreator, crypt_gen: Box<dyn Fn() -> CryptorGen + Send + Sync>, export: bool, qr_viewer: Option<String>, key_file_name: Option<String>,
+            identity_file_name: Option<String>) {
     let (tx, rx): (Sender<String>, Receiver<String>) = mpsc::channel();
 
     let capture_file_name = data_file_name.clone();
@@ -53,10 +55,21 @@ pub fn main(app: &RustPwMan, data_file_name: String, default_sec_bits: usize, de
     let sender = Arc::new(tx);
     let sender_main = sender.clone();
 
-    let p = make_default(&data_file_name);
+    let mut p = make_default(&data_file_name);
+
+    // The name of the key file can be changed in the password entry dialog
+    let key_file_sel: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(key_file_name));
+    let key_file_for_cb = key_file_sel.clone();
+    let identity_for_cb = identity_file_name.clone();
+
+    // Only ask for a key file if the header of the file says that one is needed
+    let key_file_dlg = match key_file_required(&mut p) {
+        true => Some(key_file_sel.clone()),
+        false => None
+    };
 
     #[cfg(feature = "writebackup")]
-    let backup_f_name = app.get_backup_file_name();
+    let backup_manager = app.make_backup_manager(&crate::backup::store_id(&p, &data_file_name));
 
     let infos = app.get_info().unwrap();
 
@@ -76,13 +89,34 @@ pub fn main(app: &RustPwMan, data_file_name: String, default_sec_bits: usize, de
     // stuff to run after successfull password entry
     let pw_callback = Box::new(move |s: &mut Cursive, password: &String, pw_cached: bool| {
         #[cfg(feature = "writebackup")]
-        let backup_file_name = backup_f_name.clone();
+        let backups = backup_manager.clone();
         let p_cb = make_default(&capture_file_name);
         let mut jots_store = jots::Jots::new(derive_func, deriver_id, crypt_gen());
 
+        let key_file_name = key_file_for_cb.lock().unwrap().clone();
+        if let Some(f) = key_file_name {
+            match fcrypt::load_key_file(&f) {
+                Ok(k) => jots_store.set_key_file(Some(k)),
+                Err(e) => {
+                    show_message(s, &format!("Unable to read key file: {}", e));
+                    return;
+                }
+            }
+        }
+
+        if let Some(f) = &identity_for_cb {
+            match fcrypt::envelope::load_identity(f) {
+                Ok(i) => jots_store.set_identity(Some(i)),
+                Err(e) => {
+                    show_message(s, &format!("Unable to read identity: {}", e));
+                    return;
+                }
+            }
+        }
+
         #[cfg(feature = "writebackup")]
         {            
-            let bkp_cb = move |data: &Vec<u8>| { return fs::write(backup_file_name.clone(), data);};
+            let bkp_cb = move |data: &Vec<u8>| { return backups.write(data); };
             jots_store.backup_cb = Some(Box::new(bkp_cb));
         }
 
@@ -102,22 +136,29 @@ pub fn main(app: &RustPwMan, data_file_name: String, default_sec_bits: usize, de
             s.pop_layer(); // Close password, file init or confirmation dialog
             if !export {
                 main_window(s, shared_state.clone(), sender_main.clone());
+                rotation::show_if_due(s, shared_state.clone());
+                merge::unsynced(s, shared_state.clone());
             } else {
                 export::window(s, shared_state.clone(), sender_main.clone());
             }
         }
     });
 
-    if !export {
+    let open_with_identity = identity_file_name.is_some() && matches!(p.does_exist(), Ok(true));
+
+    if open_with_identity {
+        let d = pwentry::identity_dialog(sender.clone(), &identity_file_name.unwrap(), pw_callback);
+        siv.add_layer(d);
+    } else if !export {
         // Add a layer for the password entry dialog
         #[cfg(feature = "pwmanclient")]
-        setup_password_entry_with_pwman(&mut siv, sender, pw_callback, &p);
+        setup_password_entry_with_pwman(&mut siv, sender, pw_callback, p, key_file_dlg);
 
         #[cfg(not(feature = "pwmanclient"))]
-        setup_password_entry_without_pwman(&mut siv, sender, pw_callback, &p);
+        setup_password_entry_without_pwman(&mut siv, sender, pw_callback, p, key_file_dlg);
     } else {
         // force user to enter the password
-        setup_password_entry_without_pwman(&mut siv, sender, pw_callback, &p);
+        setup_password_entry_without_pwman(&mut siv, sender, pw_callback, p, key_file_dlg);
     }
 
     crate::load_theme!(siv);
@@ -136,6 +177,27 @@ pub fn main(app: &RustPwMan, data_file_name: String, default_sec_bits: usize, de
 
 }
 
+// Only the header is of interest here. peek does not adopt the version of the data and does not
+// upload changes which have been queued while offline.
+fn key_file_required(p: &mut SendSyncPersister) -> bool {
+    if let Ok(true) = p.does_exist() {
+        if let Ok(data) = p.peek() {
+            if let Ok(info) = fcrypt::peek_container_info(data.as_slice()) {
+                return info.key_file;
+            }
+        }
+    }
+
+    return false;
+}
+
+// The missing collections are created when the user confirms the creation of the new file
+fn show_init_dialog(siv: &mut Cursive, sender: Arc<Sender<String>>, pw_callback: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, p: SendSyncPersister) {
+    let missing_parents = p.missing_parents().unwrap_or_default();
+    let d = init::dialog(sender.clone(), pw_callback, &missing_parents, Box::new(move || p.create_parents()));
+    siv.add_layer(d);
+}
+
 fn show_unable_to_check_error(siv: &mut Cursive, msg: &str, sender: Arc<Sender<String>>) {
     siv.add_layer(
         Dialog::text(msg)
@@ -148,7 +210,7 @@ fn show_unable_to_check_error(siv: &mut Cursive, msg: &str, sender: Arc<Sender<S
 }
 
 #[cfg(feature = "pwmanclient")]
-fn setup_password_entry_with_pwman(siv: &mut Cursive, sender: Arc<Sender<String>>, pw_callback: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, p: &SendSyncPersister) {
+fn setup_password_entry_with_pwman(siv: &mut Cursive, sender: Arc<Sender<String>>, pw_callback: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, p: SendSyncPersister, key_file: Option<Arc<Mutex<Option<String>>>>) {
     let does_exist = match p.does_exist() {
         Ok(b) => b,
         Err(_) => {
@@ -158,6 +220,15 @@ fn setup_password_entry_with_pwman(siv: &mut Cursive, sender: Arc<Sender<String>
     };
 
     if does_exist {
+        // A cached password is of no use if the name of the key file is still unknown
+        if let Some(k) = &key_file {
+            if k.lock().unwrap().is_none() {
+                let d = pwentry::dialog(sender.clone(), pw_callback, key_file.clone());
+                siv.add_layer(d);
+                return;
+            }
+        }
+
         let store_id = match p.get_canonical_path() {
             Ok(s) => s,
             Err(_) => {
@@ -174,23 +245,22 @@ fn setup_password_entry_with_pwman(siv: &mut Cursive, sender: Arc<Sender<String>
                         siv.add_layer(d);
                     },
                     Err(_) => {
-                        let d = pwentry::dialog(sender.clone(), pw_callback);
+                        let d = pwentry::dialog(sender.clone(), pw_callback, key_file.clone());
                         siv.add_layer(d);
                     }
                 }
             }
             Err(_) => {
-                let d = pwentry::dialog(sender.clone(), pw_callback);
+                let d = pwentry::dialog(sender.clone(), pw_callback, key_file.clone());
                 siv.add_layer(d);
             }
         };
     } else {
-        let d = init::dialog(sender.clone(), pw_callback);
-        siv.add_layer(d);
+        show_init_dialog(siv, sender, pw_callback, p);
     }
 }
 
-fn setup_password_entry_without_pwman(siv: &mut Cursive, sender: Arc<Sender<String>>, pw_callback: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, p: &SendSyncPersister) {
+fn setup_password_entry_without_pwman(siv: &mut Cursive, sender: Arc<Sender<String>>, pw_callback: Box<dyn Fn(&mut Cursive, &String, bool) + Send + Sync>, p: SendSyncPersister, key_file: Option<Arc<Mutex<Option<String>>>>) {
     let does_exist = match p.does_exist() {
         Ok(b) => b,
         Err(_) => {
@@ -200,10 +270,9 @@ fn setup_password_entry_without_pwman(siv: &mut Cursive, sender: Arc<Sender<Stri
     };
 
     if does_exist {
-        let d = pwentry::dialog(sender.clone(), pw_callback);
+        let d = pwentry::dialog(sender.clone(), pw_callback, key_file);
         siv.add_layer(d);
     } else {
-        let d = init::dialog(sender.clone(), pw_callback);
-        siv.add_layer(d);
+        show_init_dialog(siv, sender, pw_callback, p);
     }
 }
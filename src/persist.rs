@@ -17,32 +17,211 @@ use std::fs::File;
 use std::fs;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
 use std::io::{Error, ErrorKind};
 use std::path::PathBuf;
+use std::fmt;
 
 pub type PersistCreator = Box<dyn Fn(&String) -> SendSyncPersister + Send + Sync>;
 pub type SendSyncPersister = Box<dyn Persister + Send + Sync>;
 
 pub trait Persister {
     fn does_exist(&self) -> std::io::Result<bool>;
+    // Persisters which detect conflicting changes refuse to overwrite data which differs from the
+    // version seen by the last call to retrieve or persist. In this case a ConflictError is returned.
     fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()>;
     fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>>;
+    // Same as retrieve but does not change the version which persist expects to overwrite
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return self.retrieve();
+    }
     fn get_canonical_path(&self) -> std::io::Result<String>;
     fn get_type(&self) -> String;
+    // Persisters which keep a local mirror of remote data report whether it is in sync. None means that there is no mirror.
+    fn sync_state(&self) -> Option<SyncState> {
+        return None;
+    }
+    // Returns changes which were made while offline but could not be uploaded because the remote data has changed
+    // as well. The second element is the version they are based on if it is known.
+    fn unsynced(&self) -> Option<(Vec<u8>, Option<Vec<u8>>)> {
+        return None;
+    }
+    // Marks the unsynced changes as merged, i.e. they are removed after the next successful call to persist. If
+    // discard is true they are removed immediately.
+    fn resolve_unsynced(&mut self, _discard: bool) -> std::io::Result<()> {
+        return Ok(());
+    }
+    // Returns the containers which have to be created before the data can be stored, outermost first
+    fn missing_parents(&self) -> std::io::Result<Vec<String>> {
+        return Ok(vec![]);
+    }
+    fn create_parents(&self) -> std::io::Result<()> {
+        return Ok(());
+    }
+    // Returns earlier versions of the stored data, the newest first. Most persisters do not keep a history.
+    fn history(&self) -> std::io::Result<Vec<HistoryEntry>> {
+        return Ok(vec![]);
+    }
+    fn read_historic(&self, _id: &str) -> std::io::Result<Vec<u8>> {
+        return Err(Error::new(ErrorKind::Other, "No history available"));
+    }
+}
+
+// An earlier version of the stored data
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub time_stamp: String,
+    pub description: String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncState {
+    // The data has been read from or written to the server
+    Online,
+    // The server is not reachable and the data has been read from the local mirror
+    Offline,
+    // The server is not reachable and changes are waiting to be uploaded
+    OfflinePending,
+    // Changes made while offline could not be uploaded because the data on the server has changed as well
+    Conflict
+}
+
+impl fmt::Display for SyncState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            SyncState::Online => "online",
+            SyncState::Offline => "offline",
+            SyncState::OfflinePending => "offline, changes not uploaded",
+            SyncState::Conflict => "offline changes need to be merged"
+        };
+
+        return write!(f, "{}", s);
+    }
+}
+
+// Signals that the stored data has been changed by someone else since it was last read
+#[derive(Debug)]
+pub struct ConflictError;
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "The password file has been changed by someone else since it was read");
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+pub fn conflict_error() -> Error {
+    return Error::new(ErrorKind::Other, ConflictError);
+}
+
+pub fn is_conflict(e: &Error) -> bool {
+    return match e.get_ref() {
+        Some(inner) => inner.is::<ConflictError>(),
+        None => false
+    };
+}
+
+// Writes data to a temporary file in the directory of file_name which is then renamed to file_name. If anything goes
+// wrong file_name either contains the old or the new data but never a partially written file.
+pub fn write_file_atomic(file_name: &str, data: &[u8]) -> std::io::Result<()> {
+    // Replace the file a symbolic link points to and not the link itself
+    let target = match fs::canonicalize(file_name) {
+        Ok(p) => p,
+        Err(_) => PathBuf::from(file_name)
+    };
+
+    let dir = match target.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from(".")
+    };
+
+    let base_name = match target.file_name().and_then(|n| n.to_str()) {
+        Some(n) => String::from(n),
+        None => return Err(Error::new(ErrorKind::Other, format!("'{}' is not a valid file name", file_name)))
+    };
+
+    let nanos = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => d.subsec_nanos(),
+        Err(_) => 0
+    };
+
+    let temp_name = dir.join(format!(".{}.{}.{}.tmp", base_name, std::process::id(), nanos));
+
+    let write_temp = || -> std::io::Result<()> {
+        let file = fs::OpenOptions::new().write(true).create_new(true).open(&temp_name)?;
+        let mut w = BufWriter::new(file);
+
+        w.write_all(data)?;
+        let file = w.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+
+        // Keep the permissions of the file which is replaced
+        if let Ok(m) = fs::metadata(&target) {
+            fs::set_permissions(&temp_name, m.permissions())?;
+        }
+
+        return fs::rename(&temp_name, &target);
+    };
+
+    if let Err(e) = write_temp() {
+        let _ = fs::remove_file(&temp_name);
+        return Err(e);
+    }
+
+    // Make sure the rename itself is durable
+    #[cfg(unix)]
+    {
+        if let Ok(d) = File::open(&dir) {
+            let _ = d.sync_all();
+        }
+    }
+
+    return Ok(());
 }
 
 pub struct FilePersister {
-    file_name: String
+    file_name: String,
+    // Modification time and size of the file as seen by the last call to retrieve or persist
+    version: Option<String>
 }
 
 impl FilePersister {
     pub fn new(file_name: &String) -> SendSyncPersister {
         let res = FilePersister {
             file_name: file_name.clone(),
+            version: None
         };
 
         return Box::new(res);
     }
+
+    fn current_version(&self) -> std::io::Result<Option<String>> {
+        let meta = match fs::metadata(self.file_name.as_str()) {
+            Ok(m) => m,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+
+        let nanos = match meta.modified()?.duration_since(std::time::UNIX_EPOCH) {
+            Ok(d) => d.as_nanos(),
+            Err(_) => 0
+        };
+
+        return Ok(Some(format!("{}-{}", nanos, meta.len())));
+    }
+
+    fn read_data(&self) -> std::io::Result<Box<Vec<u8>>> {
+        let file = File::open(&self.file_name)?;
+        let mut reader = BufReader::new(file);
+        let mut data: Vec<u8> = vec![];
+
+        reader.read_to_end(&mut data)?;
+
+        return Ok(Box::<Vec<u8>>::new(data));
+    }
 }
 
 impl Persister for FilePersister {
@@ -52,22 +231,27 @@ impl Persister for FilePersister {
     }
 
     fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
-        let file = File::create(&self.file_name)?;
-        let mut w = BufWriter::new(file);
+        if self.version.is_some() && (self.current_version()? != self.version) {
+            return Err(conflict_error());
+        }
 
-        std::io::copy(&mut data.as_slice(), &mut w).unwrap();
+        write_file_atomic(&self.file_name, data.as_slice())?;
+        self.version = self.current_version()?;
 
         return Ok(());
     }
 
     fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
-        let file = File::open(&self.file_name)?;
-        let mut reader = BufReader::new(file);
-        let mut data: Vec<u8> = vec![];
+        // Determine the version first. If the file is changed while it is read the next call to persist fails.
+        let version = self.current_version()?;
+        let data = self.read_data()?;
+        self.version = version;
 
-        std::io::copy(&mut reader, &mut data).unwrap();
+        return Ok(data);
+    }
 
-        return Ok(Box::<Vec<u8>>::new(data));
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return self.read_data();
     }
 
     fn get_canonical_path(&self) -> std::io::Result<String> {
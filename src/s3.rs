@@ -0,0 +1,309 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::io::{Error, ErrorKind};
+
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::{Method, Url, StatusCode};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{ETAG, IF_MATCH, IF_NONE_MATCH, AUTHORIZATION};
+use sha2::{Sha256, Digest};
+
+use crate::persist::{Persister, SendSyncPersister, conflict_error};
+use crate::timeutil::{SECONDS_PER_DAY, civil_from_days, now_millis};
+use crate::tomlconfig::{S3Params, WebDavClientParams};
+use crate::httpclient::make_client;
+
+pub const S3_PREFIX: &str = "s3:";
+pub const DEFAULT_REGION: &str = "us-east-1";
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const SERVICE: &str = "s3";
+const HEADER_CONTENT_SHA256: &str = "x-amz-content-sha256";
+const HEADER_DATE: &str = "x-amz-date";
+
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key: String,
+    pub secret: String,
+    pub region: String
+}
+
+// Everything that is needed to access objects in a bucket
+#[derive(Clone)]
+pub struct S3Bucket {
+    pub client: Client,
+    pub endpoint: String,
+    pub bucket: String,
+    pub credentials: Credentials
+}
+
+impl S3Bucket {
+    // The secret is passed separately as it may have to be de-obfuscated. The HTTP client uses the same
+    // settings as the one for WebDAV.
+    pub fn from_params(params: &S3Params, secret: Option<String>, client_params: &WebDavClientParams) -> Result<S3Bucket, String> {
+        let missing = |name: &str| format!("The config entry '{}' is missing", name);
+
+        let endpoint = params.s3_endpoint.clone().ok_or(missing("s3_endpoint"))?;
+        let bucket = params.s3_bucket.clone().ok_or(missing("s3_bucket"))?;
+        let access_key = params.s3_access_key.clone().ok_or(missing("s3_access_key"))?;
+        let secret = secret.ok_or(missing("s3_secret"))?;
+
+        let client = make_client(client_params).map_err(|e| e.to_string())?;
+
+        let res = S3Bucket {
+            client: client,
+            endpoint: String::from(endpoint.trim_end_matches('/')),
+            bucket: bucket,
+            credentials: Credentials {
+                access_key: access_key,
+                secret: secret,
+                region: params.s3_region.clone().unwrap_or(String::from(DEFAULT_REGION))
+            }
+        };
+
+        return Ok(res);
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    return data.iter().map(|b| format!("{:02x}", b)).collect();
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    return hex(&Sha256::digest(data));
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(key).expect("This should not happen: HMAC key length mismatch");
+    hmac.update(data.as_bytes());
+
+    return hmac.finalize().into_bytes().to_vec();
+}
+
+// Percent encodes an object key as required by AWS. Slashes separate path segments and are kept.
+fn encode_key(key: &str) -> String {
+    let mut res = String::new();
+
+    for b in key.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    return res;
+}
+
+// Time stamp of the form 20130524T000000Z
+pub fn amz_date(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / SECONDS_PER_DAY) as i64);
+    let rem = secs % SECONDS_PER_DAY;
+
+    return format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", y, m, d, rem / 3600, (rem % 3600) / 60, rem % 60);
+}
+
+// Calculates the value of the Authorization header as defined by AWS signature version 4. The headers have
+// to contain the lower case names and the values of all signed headers including x-amz-date. The path has to
+// be percent encoded already. Requests with query parameters are not supported.
+pub fn authorization(credentials: &Credentials, method: &str, path: &str, headers: &[(String, String)], payload_hash: &str) -> String {
+    let mut sorted = headers.to_vec();
+    sorted.sort();
+
+    let amz_date = sorted.iter().find(|(k, _)| k == HEADER_DATE).map(|(_, v)| v.clone()).unwrap_or_default();
+    let date = amz_date.get(0..8).unwrap_or_default();
+
+    let canonical_headers: String = sorted.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+    let signed_headers = sorted.iter().map(|(k, _)| k.as_str()).collect::<Vec<&str>>().join(";");
+    let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, path, canonical_headers, signed_headers, payload_hash);
+
+    let scope = format!("{}/{}/{}/aws4_request", date, &credentials.region, SERVICE);
+    let string_to_sign = format!("{}\n{}\n{}\n{}", ALGORITHM, amz_date, scope, sha256_hex(canonical_request.as_bytes()));
+
+    let mut key = hmac_sha256(format!("AWS4{}", &credentials.secret).as_bytes(), date);
+    key = hmac_sha256(&key, &credentials.region);
+    key = hmac_sha256(&key, SERVICE);
+    key = hmac_sha256(&key, "aws4_request");
+    let signature = hex(&hmac_sha256(&key, &string_to_sign));
+
+    return format!("{} Credential={}/{}, SignedHeaders={}, Signature={}", ALGORITHM, &credentials.access_key, scope, signed_headers, signature);
+}
+
+fn to_io_error(e: reqwest::Error) -> Error {
+    let kind = if e.is_connect() {
+        ErrorKind::NotConnected
+    } else if e.is_timeout() {
+        ErrorKind::TimedOut
+    } else {
+        ErrorKind::Other
+    };
+
+    return Error::new(kind, format!("Error: '{}'", e));
+}
+
+fn etag(response: &Response) -> Option<String> {
+    return response.headers().get(ETAG).and_then(|e| e.to_str().ok()).map(String::from);
+}
+
+// Version of the object as seen by the last call to retrieve or persist
+#[derive(Debug, Clone, PartialEq)]
+enum S3Version {
+    ETag(String),
+    // The server did not send an ETag. The object has to be read again before it can be overwritten.
+    Unknown
+}
+
+// Stores the password file as an object in a bucket of an S3 compatible object store like MinIO, Ceph RGW
+// or Garage. Path style URLs are used as these are supported by all of them. An upload only replaces the
+// object if its ETag has not changed since it was read. If the object has not been read an upload only
+// succeeds if the object does not exist.
+pub struct S3Persister {
+    bucket: Result<S3Bucket, String>,
+    key: String,
+    version: Option<S3Version>
+}
+
+impl S3Persister {
+    pub fn new(bucket: &Result<S3Bucket, String>, key: &String) -> SendSyncPersister {
+        let res = S3Persister {
+            bucket: bucket.clone(),
+            key: String::from(key.trim_start_matches('/')),
+            version: None
+        };
+
+        return Box::new(res);
+    }
+
+    fn get_bucket(&self) -> std::io::Result<&S3Bucket> {
+        return match &self.bucket {
+            Ok(b) => Ok(b),
+            Err(e) => Err(Error::new(ErrorKind::Other, e.clone()))
+        };
+    }
+
+    fn url_str(&self) -> String {
+        return match &self.bucket {
+            Ok(b) => format!("{}/{}/{}", &b.endpoint, &b.bucket, encode_key(&self.key)),
+            Err(_) => format!("{}{}", S3_PREFIX, &self.key)
+        };
+    }
+
+    // Creates a signed request. The host header is added by the client and therefore only signed.
+    fn request(&self, method: Method, payload: &[u8]) -> std::io::Result<RequestBuilder> {
+        let bucket = self.get_bucket()?;
+        let url_str = self.url_str();
+
+        let url = match Url::parse(&url_str) {
+            Ok(u) => u,
+            Err(_) => return Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
+        };
+
+        let host = match (url.host_str(), url.port()) {
+            (Some(h), Some(p)) => format!("{}:{}", h, p),
+            (Some(h), None) => String::from(h),
+            (None, _) => return Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
+        };
+
+        let payload_hash = sha256_hex(payload);
+        let date = amz_date((now_millis() / 1000) as u64);
+
+        let headers = vec![
+            (String::from("host"), host),
+            (String::from(HEADER_CONTENT_SHA256), payload_hash.clone()),
+            (String::from(HEADER_DATE), date.clone())
+        ];
+
+        let auth = authorization(&bucket.credentials, method.as_str(), url.path(), &headers, &payload_hash);
+
+        return Ok(bucket.client.request(method, url)
+            .header(HEADER_CONTENT_SHA256, payload_hash)
+            .header(HEADER_DATE, date)
+            .header(AUTHORIZATION, auth));
+    }
+
+    fn send(request: RequestBuilder) -> std::io::Result<Response> {
+        return request.send().map_err(to_io_error);
+    }
+
+    fn read_data(&self) -> std::io::Result<(Box<Vec<u8>>, S3Version)> {
+        let response = S3Persister::send(self.request(Method::GET, &[])?)?;
+
+        if !response.status().is_success() {
+            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", response.status().as_u16())));
+        }
+
+        let version = etag(&response).map_or(S3Version::Unknown, S3Version::ETag);
+        let data = response.bytes().map_err(to_io_error)?;
+
+        return Ok((Box::new(data.to_vec()), version));
+    }
+}
+
+impl Persister for S3Persister {
+    fn does_exist(&self) -> std::io::Result<bool> {
+        let status = S3Persister::send(self.request(Method::HEAD, &[])?)?.status();
+
+        if status.is_success() {
+            return Ok(true);
+        }
+
+        if status == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
+    }
+
+    fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
+        let request = match &self.version {
+            Some(S3Version::ETag(v)) => self.request(Method::PUT, data)?.header(IF_MATCH, v.as_str()),
+            Some(S3Version::Unknown) => return Err(conflict_error()),
+            None => self.request(Method::PUT, data)?.header(IF_NONE_MATCH, "*")
+        }.body(data.clone());
+
+        let response = S3Persister::send(request)?;
+
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Err(conflict_error());
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", response.status().as_u16())));
+        }
+
+        self.version = Some(etag(&response).map_or(S3Version::Unknown, S3Version::ETag));
+
+        return Ok(());
+    }
+
+    fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        let (data, version) = self.read_data()?;
+        self.version = Some(version);
+
+        return Ok(data);
+    }
+
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return Ok(self.read_data()?.0);
+    }
+
+    fn get_canonical_path(&self) -> std::io::Result<String> {
+        return Ok(self.url_str());
+    }
+
+    fn get_type(&self) -> String {
+        return String::from("S3");
+    }
+}
@@ -0,0 +1,318 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::io::{Error, ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use sha2::{Sha256, Digest};
+
+use crate::persist::{Persister, SendSyncPersister, conflict_error};
+use crate::ssh::{SshLocation, lock_name, locked_error};
+
+pub const DEFAULT_SFTP_COMMAND: &str = "sftp";
+
+// Characters which sftp interprets in batch files or as part of a glob pattern
+const SPECIAL_CHARS: &str = "\"\\*?[]";
+
+// Quotes a path for an sftp batch file. Paths which can not be quoted are rejected beforehand.
+fn batch_quote(path: &str) -> String {
+    return format!("\"{}\"", path);
+}
+
+fn check_path(path: &str) -> bool {
+    return !path.chars().any(|c| c.is_control() || SPECIAL_CHARS.contains(c));
+}
+
+// Turns the permissions as printed by ls -l (e.g. -rw-r-----) into an octal mode for chmod
+pub fn parse_mode(perms: &str) -> Option<String> {
+    let chars: Vec<char> = perms.chars().collect();
+    if chars.len() != 10 {
+        return None;
+    }
+
+    let mut mode = 0;
+
+    for (i, c) in chars[1..].iter().enumerate() {
+        let bit = match (i % 3, c) {
+            (_, '-') | (2, 'S') | (2, 'T') => 0,
+            (0, 'r') | (1, 'w') | (2, 'x') | (2, 's') | (2, 't') => 1,
+            _ => return None
+        };
+
+        mode = (mode << 1) | bit;
+    }
+
+    return Some(format!("{:o}", mode));
+}
+
+// A directory which holds the local copies of the transferred files. It is removed when it is dropped.
+struct LocalDir {
+    path: PathBuf
+}
+
+impl LocalDir {
+    fn new() -> std::io::Result<LocalDir> {
+        let nanos = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(d) => d.subsec_nanos(),
+            Err(_) => 0
+        };
+
+        let path = std::env::temp_dir().join(format!("rustpwman_sftp_{}_{}", std::process::id(), nanos));
+        let mut builder = std::fs::DirBuilder::new();
+
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        builder.create(&path)?;
+        let res = LocalDir { path: path };
+
+        if !check_path(&res.file("")) {
+            return Err(Error::new(ErrorKind::Other, format!("The temporary directory '{}' can not be used with sftp", res.path.display())));
+        }
+
+        return Ok(res);
+    }
+
+    fn file(&self, name: &str) -> String {
+        return self.path.join(name).to_string_lossy().to_string();
+    }
+}
+
+impl Drop for LocalDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// Result of running an sftp batch file
+struct BatchResult {
+    success: bool,
+    output: String,
+    errors: String
+}
+
+// Stores the password file on a remote host via the SFTP subsystem. This also works for accounts which can not
+// run shell commands, e.g. because they are restricted to internal-sftp. The sftp command line client is used
+// in batch mode, i.e. keys from the ssh agent and settings from the ssh config file are used and key based
+// authentication is required. The server has to support the posix-rename extension, which OpenSSH does, in
+// order to replace the file atomically.
+pub struct SftpPersister {
+    url: String,
+    location: Result<SshLocation, String>,
+    sftp_command: String,
+    // SHA-256 of the remote file as seen by the last call to retrieve or persist
+    version: Option<String>
+}
+
+impl SftpPersister {
+    pub fn new(url: &String, sftp_command: &String) -> SendSyncPersister {
+        let location = SshLocation::parse(url).map_err(|e| e.to_string()).and_then(|l| {
+            if !check_path(&l.path) {
+                return Err(format!("The path in '{}' must not contain any of the characters {}", url, SPECIAL_CHARS));
+            }
+
+            return Ok(l);
+        });
+
+        let res = SftpPersister {
+            url: url.clone(),
+            location: location,
+            sftp_command: sftp_command.clone(),
+            version: None
+        };
+
+        return Box::new(res);
+    }
+
+    fn version_of(data: &[u8]) -> String {
+        return Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect();
+    }
+
+    // The remote path as used in a batch file. sftp starts in the home directory of the user.
+    fn remote_path(&self) -> String {
+        return match &self.location {
+            Ok(l) => match l.path.strip_prefix("~/") {
+                Some(p) => String::from(p),
+                None => l.path.clone()
+            },
+            Err(_) => String::new()
+        };
+    }
+
+    // Runs the given commands. Errors cause sftp to stop unless a command is prefixed with -.
+    fn run(&self, commands: &Vec<String>) -> std::io::Result<BatchResult> {
+        let location = match &self.location {
+            Ok(l) => l,
+            Err(e) => return Err(Error::new(ErrorKind::Other, e.clone()))
+        };
+
+        let mut cmd = Command::new(&self.sftp_command);
+        cmd.arg("-b").arg("-").arg("-o").arg("BatchMode=yes");
+
+        if let Some(p) = location.port {
+            cmd.arg("-P").arg(p.to_string());
+        }
+
+        cmd.arg("--").arg(&location.host);
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => return Err(Error::new(ErrorKind::Other, format!("Unable to start '{}': {}", self.sftp_command, e)))
+        };
+
+        {
+            let mut stdin = child.stdin.take().unwrap();
+            let _ = stdin.write_all(format!("{}\n", commands.join("\n")).as_bytes());
+        }
+
+        let output = child.wait_with_output()?;
+
+        return Ok(BatchResult {
+            success: output.status.success(),
+            output: String::from_utf8_lossy(&output.stdout).to_string(),
+            errors: String::from_utf8_lossy(&output.stderr).trim().to_string()
+        });
+    }
+
+    // Runs commands whose errors are all ignored. If sftp fails nonetheless the connection has failed.
+    fn run_ignoring_errors(&self, commands: &Vec<String>) -> std::io::Result<BatchResult> {
+        let res = self.run(commands)?;
+
+        if !res.success {
+            return Err(Error::new(ErrorKind::NotConnected, format!("SFTP error: {}", res.errors)));
+        }
+
+        return Ok(res);
+    }
+
+    // Returns the mode of the remote file or None if it does not exist
+    fn remote_mode(&self, listing: &str) -> Option<String> {
+        return listing.lines()
+            .filter(|l| !l.starts_with("sftp>"))
+            .filter_map(|l| l.split_whitespace().next())
+            .find(|p| p.starts_with('-'))
+            .and_then(parse_mode);
+    }
+
+    // Reads the remote file and its mode. None means that the file does not exist.
+    fn read_remote(&self, local: &LocalDir) -> std::io::Result<Option<(Vec<u8>, Option<String>)>> {
+        let remote = batch_quote(&self.remote_path());
+        let local_file = local.file("current");
+
+        let res = self.run_ignoring_errors(&vec![format!("-ls -ln {}", &remote), format!("-get {} {}", &remote, batch_quote(&local_file))])?;
+
+        return match std::fs::read(&local_file) {
+            Ok(data) => Ok(Some((data, self.remote_mode(&res.output)))),
+            Err(_) => Ok(None)
+        };
+    }
+
+    fn read_data(&self) -> std::io::Result<Vec<u8>> {
+        let local = LocalDir::new()?;
+
+        return match self.read_remote(&local)? {
+            Some((data, _)) => Ok(data),
+            None => Err(Error::new(ErrorKind::Other, format!("Unable to read '{}'", &self.url)))
+        };
+    }
+
+    fn write_locked(&self, local: &LocalDir, data: &Vec<u8>) -> std::io::Result<()> {
+        let remote_mode = match (self.read_remote(local)?, &self.version) {
+            (None, None) => None,
+            (Some((current, mode)), Some(v)) if SftpPersister::version_of(&current) == *v => mode,
+            _ => return Err(conflict_error())
+        };
+
+        let local_file = local.file("new");
+        std::fs::write(&local_file, data)?;
+
+        let remote = self.remote_path();
+        let temp = batch_quote(&format!("{}.tmp", &remote));
+        let mut commands = vec![format!("put {} {}", batch_quote(&local_file), &temp)];
+
+        // A new file is only readable by the owner
+        commands.push(format!("chmod {} {}", remote_mode.unwrap_or(String::from("600")), &temp));
+        commands.push(format!("rename {} {}", &temp, batch_quote(&remote)));
+
+        let res = self.run(&commands)?;
+
+        if !res.success {
+            let _ = self.run(&vec![format!("-rm {}", &temp)]);
+            return Err(Error::new(ErrorKind::Other, format!("Unable to write '{}': {}", &self.url, res.errors)));
+        }
+
+        return Ok(());
+    }
+}
+
+impl Persister for SftpPersister {
+    fn does_exist(&self) -> std::io::Result<bool> {
+        let local = LocalDir::new()?;
+
+        return Ok(self.read_remote(&local)?.is_some());
+    }
+
+    fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
+        // The data is written to a temporary file which then replaces the target. This only happens if the target
+        // has not been changed since it was read or, if it has not been read, does not exist. Checking and replacing
+        // happens while holding the lock.
+        let local = LocalDir::new()?;
+        let lock = batch_quote(&lock_name(&self.remote_path()));
+
+        let res = self.run(&vec![format!("mkdir {}", &lock)])?;
+        if !res.success {
+            let listing = self.run_ignoring_errors(&vec![format!("-ls -ld {}", &lock)])?;
+            if listing.output.lines().any(|l| l.starts_with('d')) {
+                return Err(locked_error(&self.url));
+            }
+
+            return Err(Error::new(ErrorKind::Other, format!("Unable to write '{}': {}", &self.url, res.errors)));
+        }
+
+        let res = self.write_locked(&local, data);
+        let unlocked = self.run(&vec![format!("rmdir {}", &lock)]);
+        res?;
+
+        if !unlocked?.success {
+            return Err(Error::new(ErrorKind::Other, format!("Unable to remove the lock of '{}'", &self.url)));
+        }
+
+        self.version = Some(SftpPersister::version_of(data));
+
+        return Ok(());
+    }
+
+    fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        let data = self.read_data()?;
+        self.version = Some(SftpPersister::version_of(&data));
+
+        return Ok(Box::new(data));
+    }
+
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return Ok(Box::new(self.read_data()?));
+    }
+
+    fn get_canonical_path(&self) -> std::io::Result<String> {
+        return Ok(self.url.clone());
+    }
+
+    fn get_type(&self) -> String {
+        return String::from("SFTP");
+    }
+}
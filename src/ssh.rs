@@ -0,0 +1,273 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::io::{Error, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use crate::persist::{Persister, SendSyncPersister, conflict_error};
+
+pub const SSH_PREFIX: &str = "ssh://";
+// Files behind URLs with this prefix are accessed via the SFTP subsystem. See sftp.rs.
+pub const SFTP_PREFIX: &str = "sftp://";
+pub const DEFAULT_SSH_COMMAND: &str = "ssh";
+
+// Exit code of the remote script if the file has been changed by someone else
+const EXIT_CONFLICT: i32 = 3;
+// Exit code of the remote script if another instance is saving the file
+const EXIT_LOCKED: i32 = 4;
+// Exit code of ssh itself if the connection fails
+const EXIT_SSH_ERROR: i32 = 255;
+
+// Location of a file as given by ssh://[user@]host[:port]/path or sftp://[user@]host[:port]/path. A path
+// which begins with /~/ is relative to the home directory of the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshLocation {
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String
+}
+
+impl SshLocation {
+    pub fn parse(url: &str) -> std::io::Result<SshLocation> {
+        let invalid = || Error::new(ErrorKind::Other, format!("Not a valid SSH URL: '{}'", url));
+
+        let lower_url = url.to_lowercase();
+        let rest = match [SSH_PREFIX, SFTP_PREFIX].iter().find(|p| lower_url.starts_with(*p)) {
+            Some(p) => &url[p.len()..],
+            None => return Err(invalid())
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => return Err(invalid())
+        };
+
+        // The user name is passed on to ssh as part of the host
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((h, p)) => (h, Some(p.parse::<u16>().map_err(|_| invalid())?)),
+            None => (authority, None)
+        };
+
+        // ssh would parse a host which begins with a dash as an option
+        if host.is_empty() || host.starts_with('-') || (path.len() < 2) {
+            return Err(invalid());
+        }
+
+        let path = match path.strip_prefix("/~/") {
+            Some(p) => format!("~/{}", p),
+            None => String::from(path)
+        };
+
+        return Ok(SshLocation { host: String::from(host), port: port, path: path });
+    }
+
+    // Returns the path as a word for the remote shell
+    fn shell_path(&self) -> String {
+        return match self.path.strip_prefix("~/") {
+            Some(p) => format!("\"$HOME\"/{}", shell_quote(p)),
+            None => shell_quote(&self.path)
+        };
+    }
+}
+
+// The lock is a directory next to the file as creating a directory is atomic over SSH as well as SFTP
+pub fn lock_name(path: &str) -> String {
+    return format!("{}.lock", path);
+}
+
+pub fn locked_error(url: &str) -> Error {
+    return Error::new(ErrorKind::Other, format!("'{}' is being saved by another instance. If this is not the case remove the directory '{}'.", url, lock_name(url)));
+}
+
+fn shell_quote(s: &str) -> String {
+    return format!("'{}'", s.replace('\'', "'\\''"));
+}
+
+// Checksum as calculated by the POSIX cksum utility. It is used to detect changes of the remote file
+// as this utility is available on all hosts which provide a POSIX shell.
+pub fn cksum(data: &[u8]) -> String {
+    let mut crc: u32 = 0;
+
+    let mut update = |b: u8| {
+        crc ^= (b as u32) << 24;
+        for _ in 0..8 {
+            crc = if (crc & 0x80000000) != 0 { (crc << 1) ^ 0x04c11db7 } else { crc << 1 };
+        }
+    };
+
+    for b in data {
+        update(*b);
+    }
+
+    let mut len = data.len();
+    while len > 0 {
+        update((len & 0xff) as u8);
+        len >>= 8;
+    }
+
+    return format!("{} {}", !crc, data.len());
+}
+
+// Stores the password file on a remote host. The ssh command line client is used to run shell commands on
+// the host, i.e. accounts which are restricted to SFTP have to use SftpPersister. Keys from the ssh agent and settings
+// from the ssh config file are used. As rustpwman can not ask for an SSH password key based authentication
+// is required.
+pub struct SshPersister {
+    url: String,
+    location: Result<SshLocation, String>,
+    ssh_command: String,
+    // Checksum of the remote file as seen by the last call to retrieve or persist
+    version: Option<String>
+}
+
+impl SshPersister {
+    pub fn new(url: &String, ssh_command: &String) -> SendSyncPersister {
+        let res = SshPersister {
+            url: url.clone(),
+            location: SshLocation::parse(url).map_err(|e| e.to_string()),
+            ssh_command: ssh_command.clone(),
+            version: None
+        };
+
+        return Box::new(res);
+    }
+
+    // Runs script on the remote host and returns its exit code and output
+    fn run(&self, script: &str, input: Option<&Vec<u8>>) -> std::io::Result<(i32, Vec<u8>)> {
+        let location = match &self.location {
+            Ok(l) => l,
+            Err(e) => return Err(Error::new(ErrorKind::Other, e.clone()))
+        };
+
+        let mut cmd = Command::new(&self.ssh_command);
+        cmd.arg("-o").arg("BatchMode=yes");
+
+        if let Some(p) = location.port {
+            cmd.arg("-p").arg(p.to_string());
+        }
+
+        // The script is always run by a POSIX shell regardless of the login shell of the user
+        cmd.arg("--").arg(&location.host).arg(format!("sh -c {}", shell_quote(script)));
+        cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() });
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => return Err(Error::new(ErrorKind::Other, format!("Unable to start '{}': {}", self.ssh_command, e)))
+        };
+
+        if let Some(data) = input {
+            // Dropping stdin signals the end of the data to the remote side. Errors are ignored because the
+            // remote script may have ended without reading the data. The exit code tells what happened.
+            let mut stdin = child.stdin.take().unwrap();
+            let _ = stdin.write_all(data);
+        }
+
+        let output = child.wait_with_output()?;
+        let code = output.status.code().unwrap_or(EXIT_SSH_ERROR);
+
+        if code == EXIT_SSH_ERROR {
+            let msg = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::new(ErrorKind::NotConnected, format!("SSH error: {}", msg.trim())));
+        }
+
+        return Ok((code, output.stdout));
+    }
+
+    fn shell_path(&self) -> String {
+        return match &self.location {
+            Ok(l) => l.shell_path(),
+            Err(_) => String::new()
+        };
+    }
+
+    fn read_data(&self) -> std::io::Result<Vec<u8>> {
+        let (code, data) = self.run(&format!("cat -- {}", self.shell_path()), None)?;
+
+        if code != 0 {
+            return Err(Error::new(ErrorKind::Other, format!("Unable to read '{}'", &self.url)));
+        }
+
+        return Ok(data);
+    }
+}
+
+impl Persister for SshPersister {
+    fn does_exist(&self) -> std::io::Result<bool> {
+        let (code, _) = self.run(&format!("test -e {}", self.shell_path()), None)?;
+
+        return Ok(code == 0);
+    }
+
+    fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
+        // The data is written to a temporary file which then replaces the target. This only happens if the target
+        // has not been changed since it was read or, if it has not been read, does not exist. Checking and replacing
+        // happens while holding the lock.
+        let check = match &self.version {
+            Some(v) => format!("[ -e \"$f\" ] && [ \"$(cksum < \"$f\")\" = {} ] || exit {}\n", shell_quote(v), EXIT_CONFLICT),
+            None => format!("[ ! -e \"$f\" ] || exit {}\n", EXIT_CONFLICT)
+        };
+
+        // A file which was not transmitted completely never replaces the target. The temporary file is only
+        // readable by the owner. It gets the mode of the target if either GNU or BSD stat is available. Some
+        // implementations of wc prepend spaces to the number.
+        let script = format!(
+            "f={}\nl=\"$f.lock\"\n\
+             mkdir \"$l\" 2>/dev/null || {{ [ -d \"$l\" ] && exit {}; exit 1; }}\n\
+             trap 'rmdir \"$l\"' EXIT\n\
+             {}t=\"$f.tmp.$$\"\n\
+             (umask 077 && cat > \"$t\") && [ \"$(wc -c < \"$t\" | tr -d ' ')\" = {} ] || {{ rm -f \"$t\"; exit 1; }}\n\
+             if [ -e \"$f\" ]; then m=$(stat -c %a \"$f\" 2>/dev/null || stat -f %Lp \"$f\" 2>/dev/null) && chmod \"$m\" \"$t\"; fi\n\
+             mv -f \"$t\" \"$f\" || {{ rm -f \"$t\"; exit 1; }}\n",
+            self.shell_path(), EXIT_LOCKED, check, data.len());
+        let (code, _) = self.run(&script, Some(data))?;
+
+        if code == EXIT_CONFLICT {
+            return Err(conflict_error());
+        }
+
+        if code == EXIT_LOCKED {
+            return Err(locked_error(&self.url));
+        }
+
+        if code != 0 {
+            return Err(Error::new(ErrorKind::Other, format!("Unable to write '{}'", &self.url)));
+        }
+
+        self.version = Some(cksum(data));
+
+        return Ok(());
+    }
+
+    fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        let data = self.read_data()?;
+        self.version = Some(cksum(&data));
+
+        return Ok(Box::new(data));
+    }
+
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        return Ok(Box::new(self.read_data()?));
+    }
+
+    fn get_canonical_path(&self) -> std::io::Result<String> {
+        return Ok(self.url.clone());
+    }
+
+    fn get_type(&self) -> String {
+        return String::from("SSH");
+    }
+}
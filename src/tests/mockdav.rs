@@ -0,0 +1,201 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use base64::prelude::*;
+use super::mockhttp::{Request, Response, serve, etag};
+
+pub const MOCK_USER: &str = "user";
+pub const MOCK_PASSWORD: &str = "secret";
+
+// The state of the mock server. Paths of collections end with a slash.
+#[derive(Default)]
+pub struct DavState {
+    pub files: HashMap<String, Vec<u8>>,
+    pub collections: HashSet<String>,
+    // If set every request is answered with this status code
+    pub forced_status: Option<u16>,
+    // Method and path of all requests which were received
+    pub requests: Vec<String>,
+    // If set Last-Modified is sent instead of ETags
+    pub no_etags: bool,
+    // Modification times of the files in seconds. Every write advances the clock by one second.
+    pub modified: HashMap<String, u64>,
+    pub clock: u64
+}
+
+// A minimal in-process WebDAV server which supports PROPFIND, GET, HEAD, PUT, DELETE, MOVE and MKCOL with basic
+// authentication and ETags.
+pub struct MockDav {
+    pub state: Arc<Mutex<DavState>>,
+    port: u16
+}
+
+fn parent_of(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+
+    return match trimmed.rfind('/') {
+        Some(pos) => String::from(&trimmed[..pos + 1]),
+        None => String::from("/")
+    };
+}
+
+// Strips scheme and authority from an absolute URL
+fn path_of(url: &str) -> String {
+    return match url.find("://") {
+        Some(pos) => {
+            let rest = &url[pos + 3..];
+            match rest.find('/') {
+                Some(p) => String::from(&rest[p..]),
+                None => String::from("/")
+            }
+        },
+        None => String::from(url)
+    };
+}
+
+fn http_date(secs: u64) -> String {
+    return format!("Sat, 01 Jan 2000 {:02}:{:02}:{:02} GMT", secs / 3600, (secs / 60) % 60, secs % 60);
+}
+
+// The header which identifies the version of a file
+fn version_header(state: &DavState, path: &str) -> Vec<(String, String)> {
+    return match (state.no_etags, state.files.get(path)) {
+        (_, None) => vec![],
+        (false, Some(d)) => vec![(String::from("ETag"), etag(d))],
+        (true, Some(_)) => vec![(String::from("Last-Modified"), http_date(state.modified.get(path).copied().unwrap_or(0)))]
+    };
+}
+
+fn write_file(state: &mut DavState, path: &str, data: Vec<u8>) {
+    state.clock += 1;
+    state.modified.insert(String::from(path), state.clock);
+    state.files.insert(String::from(path), data);
+}
+
+fn is_authorized(request: &Request) -> bool {
+    let expected = format!("Basic {}", BASE64_STANDARD.encode(format!("{}:{}", MOCK_USER, MOCK_PASSWORD)));
+
+    return request.headers.get("authorization") == Some(&expected);
+}
+
+fn handle(state: &mut DavState, request: &Request) -> Response {
+    state.requests.push(format!("{} {}", request.method, request.path));
+
+    if let Some(s) = state.forced_status {
+        return Response::new(s);
+    }
+
+    if !is_authorized(request) {
+        return Response::new(401);
+    }
+
+    let path = request.path.clone();
+
+    match request.method.as_str() {
+        "PROPFIND" => {
+            let exists = state.files.contains_key(&path) || state.collections.contains(&path);
+            return Response::new(if exists { 207 } else { 404 });
+        },
+        "GET" | "HEAD" => {
+            return match state.files.get(&path) {
+                Some(d) => Response { status: 200, headers: version_header(state, &path), body: d.clone() },
+                None => Response::new(404)
+            };
+        },
+        "PUT" => {
+            if !state.collections.contains(&parent_of(&path)) {
+                return Response::new(409);
+            }
+
+            if let Some(since) = request.headers.get("if-unmodified-since") {
+                if state.files.contains_key(&path) && (http_date(state.modified.get(&path).copied().unwrap_or(0)) != *since) {
+                    return Response::new(412);
+                }
+            }
+
+            write_file(state, &path, request.body.clone());
+            return Response { status: 201, headers: version_header(state, &path), body: vec![] };
+        },
+        "DELETE" => {
+            return match state.files.remove(&path) {
+                Some(_) => Response::new(204),
+                None => Response::new(404)
+            };
+        },
+        "MKCOL" => {
+            if state.collections.contains(&path) {
+                return Response::new(405);
+            }
+
+            if !state.collections.contains(&parent_of(&path)) {
+                return Response::new(409);
+            }
+
+            state.collections.insert(path);
+            return Response::new(201);
+        },
+        "MOVE" => {
+            let dest = match request.headers.get("destination") {
+                Some(d) => path_of(d),
+                None => return Response::new(400)
+            };
+
+            if !state.files.contains_key(&path) {
+                return Response::new(404);
+            }
+
+            let overwrite = request.headers.get("overwrite").map(|o| o.as_str()) != Some("F");
+            if state.files.contains_key(&dest) && !overwrite {
+                return Response::new(412);
+            }
+
+            // Only tagged lists with a single entity tag are supported
+            if let Some(cond) = request.headers.get("if") {
+                let current = state.files.get(&dest).map(etag);
+                let matches = match current {
+                    Some(e) => cond.contains(&format!("[{}]", e)),
+                    None => false
+                };
+
+                if !matches {
+                    return Response::new(412);
+                }
+            }
+
+            let data = state.files.remove(&path).unwrap();
+            write_file(state, &dest, data);
+            return Response::new(204);
+        },
+        _ => return Response::new(405)
+    }
+}
+
+impl MockDav {
+    pub fn start() -> MockDav {
+        let mut initial = DavState::default();
+        initial.collections.insert(String::from("/"));
+        let state = Arc::new(Mutex::new(initial));
+        let port = serve(state.clone(), handle);
+
+        return MockDav { state: state, port: port };
+    }
+
+    pub fn server(&self) -> String {
+        return format!("http://127.0.0.1:{}/", self.port);
+    }
+}
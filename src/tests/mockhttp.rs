@@ -0,0 +1,120 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use sha2::{Sha256, Digest};
+
+// HTTP plumbing shared by the in-process mock servers
+
+pub fn etag(data: &Vec<u8>) -> String {
+    let hash = Sha256::digest(data);
+    let hex: String = hash.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+
+    return format!("\"{}\"", hex);
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        return Response { status: status, headers: vec![], body: vec![] };
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = String::from(parts.next()?);
+    let path = String::from(parts.next()?);
+    let mut headers: HashMap<String, String> = HashMap::new();
+
+    loop {
+        let mut header_line = String::new();
+        reader.read_line(&mut header_line).ok()?;
+        let header_line = header_line.trim_end();
+
+        if header_line.is_empty() {
+            break;
+        }
+
+        if let Some((k, v)) = header_line.split_once(':') {
+            headers.insert(k.trim().to_lowercase(), String::from(v.trim()));
+        }
+    }
+
+    let len: usize = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).ok()?;
+
+    return Some(Request { method: method, path: path, headers: headers, body: body });
+}
+
+fn write_response(stream: &mut TcpStream, method: &str, response: &Response) {
+    let mut head = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+
+    for (k, v) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", k, v));
+    }
+
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes());
+    if method != "HEAD" {
+        let _ = stream.write_all(&response.body);
+    }
+    let _ = stream.flush();
+}
+
+// Answers requests with handle in a separate thread and returns the port on which the server listens. Each
+// connection carries exactly one request. The thread ends with the test process.
+pub fn serve<S: Send + 'static>(state: Arc<Mutex<S>>, handle: fn(&mut S, &Request) -> Response) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue
+            };
+
+            if let Some(request) = read_request(&mut stream) {
+                let response = handle(&mut state.lock().unwrap(), &request);
+                write_response(&mut stream, &request.method, &response);
+            }
+        }
+    });
+
+    return port;
+}
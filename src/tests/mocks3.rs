@@ -0,0 +1,133 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::mockhttp::{Request, Response, serve, etag};
+use crate::s3::{Credentials, authorization, sha256_hex};
+
+pub const MOCK_BUCKET: &str = "vaults";
+pub const MOCK_ACCESS_KEY: &str = "AKIDMOCK";
+pub const MOCK_SECRET: &str = "mocksecret";
+pub const MOCK_REGION: &str = "us-east-1";
+
+#[derive(Default)]
+pub struct S3State {
+    // Objects by key
+    pub objects: HashMap<String, Vec<u8>>,
+    // If set every request is answered with this status code
+    pub forced_status: Option<u16>,
+    // If set responses to PUT requests do not contain an ETag
+    pub no_put_etags: bool,
+    // Method and path of all requests which were received
+    pub requests: Vec<String>
+}
+
+// A minimal in-process S3 server with a single bucket. It supports GET, HEAD and conditional PUT of objects
+// via path style URLs and checks the signature of every request.
+pub struct MockS3 {
+    pub state: Arc<Mutex<S3State>>,
+    port: u16
+}
+
+// Recalculates the signature from the headers named in the Authorization header
+fn is_authorized(request: &Request) -> bool {
+    let auth = match request.headers.get("authorization") {
+        Some(a) => a,
+        None => return false
+    };
+
+    let signed_headers = match auth.split(", ").find_map(|p| p.strip_prefix("SignedHeaders=")) {
+        Some(s) => s,
+        None => return false
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for name in signed_headers.split(';') {
+        match request.headers.get(name) {
+            Some(v) => headers.push((String::from(name), v.clone())),
+            None => return false
+        }
+    }
+
+    // The payload hash is part of the signature and has to match the body
+    let payload_hash = request.headers.get("x-amz-content-sha256").cloned().unwrap_or_default();
+    if payload_hash != sha256_hex(&request.body) {
+        return false;
+    }
+
+    let credentials = Credentials { access_key: String::from(MOCK_ACCESS_KEY), secret: String::from(MOCK_SECRET), region: String::from(MOCK_REGION) };
+
+    return *auth == authorization(&credentials, &request.method, &request.path, &headers, &payload_hash);
+}
+
+fn handle(state: &mut S3State, request: &Request) -> Response {
+    state.requests.push(format!("{} {}", request.method, request.path));
+
+    if let Some(s) = state.forced_status {
+        return Response::new(s);
+    }
+
+    if !is_authorized(request) {
+        return Response::new(403);
+    }
+
+    let key = match request.path.strip_prefix(&format!("/{}/", MOCK_BUCKET)) {
+        Some(k) => String::from(k),
+        None => return Response::new(404)
+    };
+
+    match request.method.as_str() {
+        "GET" | "HEAD" => {
+            return match state.objects.get(&key) {
+                Some(d) => Response { status: 200, headers: vec![(String::from("ETag"), etag(d))], body: d.clone() },
+                None => Response::new(404)
+            };
+        },
+        "PUT" => {
+            let current = state.objects.get(&key).map(etag);
+
+            if let Some(expected) = request.headers.get("if-match") {
+                if current.as_ref() != Some(expected) {
+                    return Response::new(412);
+                }
+            }
+
+            if (request.headers.get("if-none-match").map(|v| v.as_str()) == Some("*")) && current.is_some() {
+                return Response::new(412);
+            }
+
+            state.objects.insert(key, request.body.clone());
+            let headers = if state.no_put_etags { vec![] } else { vec![(String::from("ETag"), etag(&request.body))] };
+            return Response { status: 200, headers: headers, body: vec![] };
+        },
+        _ => return Response::new(405)
+    }
+}
+
+impl MockS3 {
+    pub fn start() -> MockS3 {
+        let state = Arc::new(Mutex::new(S3State::default()));
+        let port = serve(state.clone(), handle);
+
+        return MockS3 { state: state, port: port };
+    }
+
+    pub fn endpoint(&self) -> String {
+        return format!("http://127.0.0.1:{}", self.port);
+    }
+}
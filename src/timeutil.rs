@@ -0,0 +1,94 @@
+/* Copyright 2021 Martin Grap
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License. */
+
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECONDS_PER_DAY: u64 = 86400;
+
+// Converts a number of days since 1970-01-01 into year, month and day. See
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+
+    return (if m <= 2 { y + 1 } else { y }, m, d);
+}
+
+// The inverse of civil_from_days
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = (y - era * 400) as u64;
+    let mp = if m > 2 { m - 3 } else { m + 9 } as u64;
+    let doy = (153 * mp + 2) / 5 + (d as u64) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    return era * 146097 + (doe as i64) - 719468;
+}
+
+// Turns a date of the form 2024-01-31 into the seconds since 1970-01-01 at midnight UTC of that day
+pub fn parse_date(date: &str) -> Option<u64> {
+    let parts: Vec<&str> = date.trim().split('-').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    let y = parts[0].parse::<i64>().ok()?;
+    let m = parts[1].parse::<u32>().ok()?;
+    let d = parts[2].parse::<u32>().ok()?;
+
+    // days_from_civil overflows for values which are far out of range
+    if !(1970..=9999).contains(&y) || !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+
+    let days = days_from_civil(y, m, d);
+
+    // Rejects dates like 2023-02-30
+    if (days < 0) || (civil_from_days(days) != (y, m, d)) {
+        return None;
+    }
+
+    return Some((days as u64) * SECONDS_PER_DAY);
+}
+
+// Turns seconds since 1970-01-01 into 2024-01-31
+pub fn format_date(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / SECONDS_PER_DAY) as i64);
+
+    return format!("{:04}-{:02}-{:02}", y, m, d);
+}
+
+pub fn now_millis() -> u128 {
+    return match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis(),
+        Err(_) => 0
+    };
+}
+
+// Turns seconds since 1970-01-01 into 2024-01-31 23:59:59 UTC
+pub fn format_secs(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / SECONDS_PER_DAY) as i64);
+    let rem = secs % SECONDS_PER_DAY;
+
+    return format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", y, m, d, rem / 3600, (rem % 3600) / 60, rem % 60);
+}
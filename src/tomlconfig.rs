@@ -20,7 +20,36 @@ use std::fs::File;
 use std::io::BufWriter;
 use std::io::Write;
 
-#[derive(Serialize, Deserialize, Debug)]
+// Optional settings for the HTTP client which is used to access WebDAV shares
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct WebDavClientParams {
+    // PEM file with additional CA certificates
+    pub webdav_ca_file: Option<String>,
+    // PEM file with a client certificate. It may also contain the private key.
+    pub webdav_client_cert: Option<String>,
+    // PEM file with the private key of the client certificate
+    pub webdav_client_key: Option<String>,
+    // If set the token is used for bearer authentication instead of user and password
+    pub webdav_token: Option<String>,
+    pub webdav_proxy: Option<String>,
+    // Timeout for requests in seconds
+    pub webdav_timeout: Option<u64>
+}
+
+// Parameters for accessing password files in an S3 compatible object store
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct S3Params {
+    // Base URL of the object store, e.g. https://minio.example.com:9000
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    // Defaults to us-east-1 which is accepted by most S3 compatible stores
+    pub s3_region: Option<String>,
+    pub s3_access_key: Option<String>,
+    // Can be obfuscated in the same way as the WebDAV password
+    pub s3_secret: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct RustPwManSerialize {
     pub seclevel: usize,
     pub pbkdf: String,
@@ -30,29 +59,21 @@ pub struct RustPwManSerialize {
     pub copy_cmd: String,
     pub viewer_cmd: Option<String>,
     pub bkp_file_name: Option<String>,
+    pub bkp_count: Option<usize>,
+    pub bkp_max_age: Option<u64>,
     pub webdav_user: String,
     pub webdav_pw: String,
     pub webdav_server: String,
-    pub template_strings: Option<Vec<String>>
-}
-
-impl RustPwManSerialize {
-    pub fn new(seclevel: usize, pbkdf: &str, pwgen: &str, clip_command: &str, copy_command: &str, user: &str, pw: &str, server: &str, view: Option<String>, bkp_file: Option<String>, c: Option<String>, templ: Option<Vec<String>>) -> Self {
-        return RustPwManSerialize {
-            seclevel: seclevel,
-            pbkdf: String::from(pbkdf),
-            cipher: c,
-            pwgen: String::from(pwgen),
-            clip_cmd: String::from(clip_command),
-            copy_cmd: String::from(copy_command),
-            viewer_cmd: view,
-            bkp_file_name: bkp_file,
-            webdav_user: String::from(user),
-            webdav_pw: String::from(pw),
-            webdav_server: String::from(server),
-            template_strings: templ,
-        }
-    }
+    pub webdav_offline: Option<String>,
+    #[serde(flatten)]
+    pub webdav_client: WebDavClientParams,
+    // Name of the git remote to which password files in git repositories are pushed
+    pub git_remote: Option<String>,
+    #[serde(flatten)]
+    pub s3: S3Params,
+    pub template_strings: Option<Vec<String>>,
+    pub argon2_params: Option<String>,
+    pub scrypt_params: Option<String>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -31,6 +31,8 @@ use crate::modtui::show_message;
 use crate::strat_helper::StratHelper;
 use crate::RustPwMan;
 #[cfg(feature = "webdav")]
+use crate::webdav;
+#[cfg(feature = "webdav")]
 use crate::OBFUSCATION_ENV_VAR;
 #[cfg(feature = "webdav")]
 use crate::obfuscate::is_obfuscated;
@@ -46,8 +48,15 @@ const PW_LEN_NAME: &str = "cfgpwlen";
 const EDIT_PASTE_COMMAND: &str = "pastecmd";
 const EDIT_COPY_COMMAND: &str = "copycmd";
 const EDIT_VIEWER_COMMAND: &str = "viewercmd";
+#[cfg(feature = "git")]
+const EDIT_GIT_REMOTE: &str = "gitremote";
 const EDIT_TEMPL_STRINGS: &str = "cfg_template_strings";
 const EDIT_BACKUP_FILE: &str = "backupfile";
+const EDIT_BACKUP_COUNT: &str = "backupcount";
+const EDIT_BACKUP_AGE: &str = "backupage";
+const EDIT_ARGON2_PARAMS: &str = "argon2params";
+#[cfg(feature = "withscrypt")]
+const EDIT_SCRYPT_PARAMS: &str = "scryptparams";
 
 #[cfg(feature = "webdav")]
 const EDIT_WEBDAV_USER: &str = "webdav_user";
@@ -55,6 +64,8 @@ const EDIT_WEBDAV_USER: &str = "webdav_user";
 const EDIT_WEBDAV_PASSWORD: &str = "webdav_password";
 #[cfg(feature = "webdav")]
 const EDIT_WEBDAV_SERVER: &str = "webdav_server";
+#[cfg(feature = "webdav")]
+const EDIT_WEBDAV_OFFLINE: &str = "webdav_offline";
 
 
 #[cfg(not(feature = "chacha20"))]
@@ -62,6 +73,11 @@ const CHACHA20:bool = false;
 #[cfg(feature = "chacha20")]
 const CHACHA20:bool = true;
 
+#[cfg(not(feature = "withscrypt"))]
+const WITH_SCRYPT:bool = false;
+#[cfg(feature = "withscrypt")]
+const WITH_SCRYPT:bool = true;
+
 #[cfg(not(feature = "writebackup"))]
 const WRITE_BACKUP:bool = false;
 #[cfg(feature = "writebackup")]
@@ -111,9 +127,17 @@ pub struct OptionalConfigEntries {
     webdav_user: String,
     webdav_password: String,
     webdav_server: String,
+    webdav_offline: Option<String>,
+    webdav_client: tomlconfig::WebDavClientParams,
+    git_remote: Option<String>,
+    s3: tomlconfig::S3Params,
     viewer_command: Option<String>,
     bkp_file_name: Option<String>,
+    bkp_count: Option<usize>,
+    bkp_max_age: Option<u64>,
     template_strings: Option<Vec<String>>,
+    argon2_params: Option<String>,
+    scrypt_params: Option<String>,
 }
 
 macro_rules! get_string_value_from_ui_no_shadow {
@@ -146,11 +170,27 @@ pub fn save_new_config(s: &mut Cursive, old_values: Box<OptionalConfigEntries>,
     #[allow(unused_mut, unused_assignments)]
     let mut server = old_values.webdav_server;
     #[allow(unused_mut, unused_assignments)]
+    let mut offline = old_values.webdav_offline;
+    // The client parameters can not be changed in the UI
+    let client = old_values.webdav_client;
+    // The same is true for the S3 parameters
+    let s3 = old_values.s3;
+    #[allow(unused_mut, unused_assignments)]
+    let mut git_remote = old_values.git_remote;
+    #[allow(unused_mut, unused_assignments)]
     let mut viewer_command = old_values.viewer_command;
     #[allow(unused_mut, unused_assignments)]
     let mut backup_file_name = old_values.bkp_file_name;
     #[allow(unused_mut, unused_assignments)]
+    let mut backup_count = old_values.bkp_count;
+    #[allow(unused_mut, unused_assignments)]
+    let mut backup_max_age = old_values.bkp_max_age;
+    #[allow(unused_mut, unused_assignments)]
     let mut template_string_data = old_values.template_strings;
+    #[allow(unused_mut, unused_assignments)]
+    let mut argon2_params = old_values.argon2_params;
+    #[allow(unused_mut, unused_assignments)]
+    let mut scrypt_params = old_values.scrypt_params;
 
     let mut temp_template: String;
     get_string_value_from_ui_no_shadow!(s, temp_template, EDIT_TEMPL_STRINGS, "Unable to determine template strings");
@@ -200,6 +240,33 @@ pub fn save_new_config(s: &mut Cursive, old_values: Box<OptionalConfigEntries>,
         let backup_file_name_txt: String;
         get_string_value_from_ui_no_shadow!(s, backup_file_name_txt, EDIT_BACKUP_FILE, "Unable to determine backup file name");
         backup_file_name = to_option(&backup_file_name_txt);
+
+        // Read number and maximum age of backups to keep. An empty field selects the default value.
+        let backup_count_txt: String;
+        get_string_value_from_ui_no_shadow!(s, backup_count_txt, EDIT_BACKUP_COUNT, "Unable to determine number of backups");
+        backup_count = match to_option(&String::from(backup_count_txt.trim())) {
+            Some(c) => match c.parse::<usize>() {
+                Ok(v) if v > 0 => Some(v),
+                _ => {
+                    show_message(s, "Number of backups has to be a positive integer");
+                    return;
+                }
+            },
+            None => None
+        };
+
+        let backup_age_txt: String;
+        get_string_value_from_ui_no_shadow!(s, backup_age_txt, EDIT_BACKUP_AGE, "Unable to determine maximum age of backups");
+        backup_max_age = match to_option(&String::from(backup_age_txt.trim())) {
+            Some(a) => match a.parse::<u64>() {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    show_message(s, "Maximum age of backups has to be a number of days");
+                    return;
+                }
+            },
+            None => None
+        };
     }
 
     // Read WebDAV user name
@@ -208,6 +275,51 @@ pub fn save_new_config(s: &mut Cursive, old_values: Box<OptionalConfigEntries>,
         get_string_value_from_ui_no_shadow!(s, user, EDIT_WEBDAV_USER, "Unable to determine WebDAV user");
         get_string_value_from_ui_no_shadow!(s, pw, EDIT_WEBDAV_PASSWORD, "Unable to determine WebDAV password");
         get_string_value_from_ui_no_shadow!(s, server, EDIT_WEBDAV_SERVER, "Unable to determine WebDAV server");
+
+        // An empty field selects the default mode
+        let offline_txt: String;
+        get_string_value_from_ui_no_shadow!(s, offline_txt, EDIT_WEBDAV_OFFLINE, "Unable to determine WebDAV offline mode");
+        offline = to_option(&String::from(offline_txt.trim()));
+
+        if let Some(m) = &offline {
+            if webdav::OfflineMode::from_str(m).is_none() {
+                show_message(s, &format!("Offline mode has to be one of {}, {} or {}", webdav::OFFLINE_QUEUE, webdav::OFFLINE_READ_ONLY, webdav::OFFLINE_OFF));
+                return;
+            }
+        }
+    }
+
+    #[cfg(feature = "git")]
+    {
+        let git_remote_txt: String;
+        get_string_value_from_ui_no_shadow!(s, git_remote_txt, EDIT_GIT_REMOTE, "Unable to determine git remote");
+        git_remote = to_option(&String::from(git_remote_txt.trim()));
+    }
+
+    // Read PBKDF parameters. An empty field selects the default values.
+    let argon2_params_txt: String;
+    get_string_value_from_ui_no_shadow!(s, argon2_params_txt, EDIT_ARGON2_PARAMS, "Unable to determine argon2 parameters");
+    argon2_params = to_option(&String::from(argon2_params_txt.trim()));
+
+    if let Some(p) = &argon2_params {
+        if let Err(e) = fcrypt::KdfParams::from_str(fcrypt::KdfId::Argon2, p) {
+            show_message(s, &format!("Invalid argon2 parameters: {}", e));
+            return;
+        }
+    }
+
+    #[cfg(feature = "withscrypt")]
+    {
+        let scrypt_params_txt: String;
+        get_string_value_from_ui_no_shadow!(s, scrypt_params_txt, EDIT_SCRYPT_PARAMS, "Unable to determine scrypt parameters");
+        scrypt_params = to_option(&String::from(scrypt_params_txt.trim()));
+
+        if let Some(p) = &scrypt_params {
+            if let Err(e) = fcrypt::KdfParams::from_str(fcrypt::KdfId::Scrypt, p) {
+                show_message(s, &format!("Invalid scrypt parameters: {}", e));
+                return;
+            }
+        }
     }
 
     // Read selected password generation strategy
@@ -224,7 +336,28 @@ pub fn save_new_config(s: &mut Cursive, old_values: Box<OptionalConfigEntries>,
     }
 
     // Write new config
-    let new_config = RustPwManSerialize::new(rand_bytes, pbkdf.to_str(), strategy.to_str(), clip_command.as_str(), copy_command.as_str(), user.as_str(), pw.as_str(), server.as_str(), viewer_command, backup_file_name, cipher_id, template_string_data);
+    let new_config = RustPwManSerialize {
+        seclevel: rand_bytes,
+        pbkdf: String::from(pbkdf.to_str()),
+        cipher: cipher_id,
+        pwgen: String::from(strategy.to_str()),
+        clip_cmd: clip_command,
+        copy_cmd: copy_command,
+        viewer_cmd: viewer_command,
+        bkp_file_name: backup_file_name,
+        bkp_count: backup_count,
+        bkp_max_age: backup_max_age,
+        webdav_user: user,
+        webdav_pw: pw,
+        webdav_server: server,
+        webdav_offline: offline,
+        webdav_client: client,
+        git_remote: git_remote,
+        s3: s3,
+        template_strings: template_string_data,
+        argon2_params: argon2_params,
+        scrypt_params: scrypt_params
+    };
 
     match tomlconfig::save(config_file, new_config) {
         Some(e) => {
@@ -236,7 +369,7 @@ pub fn save_new_config(s: &mut Cursive, old_values: Box<OptionalConfigEntries>,
     };
 }
 
-fn create_algo_selection_ui<T: Send + Sync + Eq + StrGetter+ 'static + Copy>(algo_id: T, msg: &str) -> (LinearLayout, RadioGroup<T>) {
+pub fn create_algo_selection_ui<T: Send + Sync + Eq + StrGetter+ 'static + Copy>(algo_id: T, msg: &str) -> (LinearLayout, RadioGroup<T>) {
     let mut algo_group: RadioGroup<T> = RadioGroup::new();
 
     let mut linear_layout_cipher = LinearLayout::horizontal()
@@ -282,10 +415,22 @@ fn create_miscelleneous_ui() -> Panel<PaddedView<LinearLayout>> {
     if WRITE_BACKUP {
         misc_layout.add_child(create_edit_field_with_label("Backup Filename : ", EDIT_BACKUP_FILE, 65));
         misc_layout.add_child(TextView::new("\n"));
+        misc_layout.add_child(LinearLayout::horizontal()
+            .child(create_edit_field_with_label("Backups to keep : ", EDIT_BACKUP_COUNT, 5))
+            .child(create_edit_field_with_label("  Max. age in days (0 = unlimited): ", EDIT_BACKUP_AGE, 5)));
+        misc_layout.add_child(TextView::new("\n"));
     }
 
     misc_layout.add_child(create_edit_field_with_label("Template strings: ", EDIT_TEMPL_STRINGS, 65));
 
+    #[cfg(feature = "git")]
+    {
+        misc_layout.add_child(TextView::new("\n"));
+        misc_layout.add_child(LinearLayout::horizontal()
+            .child(create_edit_field_with_label("Git remote      : ", EDIT_GIT_REMOTE, 20))
+            .child(TextView::new(" Every commit is pushed to this remote if set")));
+    }
+
     return Panel::new(PaddedView::new(Margins::lrtb(1,1,1,1),misc_layout)).title("Miscellaneous settings")
 }
 
@@ -308,6 +453,10 @@ fn create_webdav_ui() -> Panel<PaddedView<LinearLayout>> {
         .child(TextView::new("\n"))
         .child(create_edit_field_with_label("Server  : ", EDIT_WEBDAV_SERVER, 65))
         .child(TextView::new("\n"))
+        .child(LinearLayout::horizontal()
+            .child(create_edit_field_with_label("Offline : ", EDIT_WEBDAV_OFFLINE, 10))
+            .child(TextView::new(format!(" {}, {} or {}. Empty means {}.", webdav::OFFLINE_QUEUE, webdav::OFFLINE_READ_ONLY, webdav::OFFLINE_OFF, webdav::OFFLINE_QUEUE))))
+        .child(TextView::new("\n"))
         .child(Button::new("Obfuscate password", |s| obfuscate_password(s)))
         )
     )
@@ -364,6 +513,21 @@ fn create_algo_select_ui(linear_layout_pbkdf: LinearLayout, cipher_select: Linea
         lin.add_child(cipher_select);
     }
 
+    lin.add_child(TextView::new("\n"));
+    lin.add_child(create_edit_field_with_label("Argon2 parameters: ", EDIT_ARGON2_PARAMS, 30));
+
+    #[cfg(feature = "withscrypt")]
+    {
+        lin.add_child(TextView::new("\n"));
+        lin.add_child(create_edit_field_with_label("scrypt parameters: ", EDIT_SCRYPT_PARAMS, 30));
+    }
+
+    if WITH_SCRYPT {
+        lin.add_child(TextView::new("\nLeave empty to use defaults (argon2: m=15360,t=2,p=1, scrypt: ln=15,r=8,p=2)"));
+    } else {
+        lin.add_child(TextView::new("\nLeave empty to use defaults (m=15360,t=2,p=1)"));
+    }
+
     return Panel::new(PaddedView::new(Margins::lrtb(1,1,1,1),lin)).title("Default crypto algorithms");
 }
 
@@ -423,9 +587,17 @@ pub fn config_main(app: &RustPwMan, config_file: std::path::PathBuf, sec_level:
         webdav_user: webdav_user.clone(),
         webdav_password: webdav_pw.clone(),
         webdav_server: webdav_server.clone(),
+        webdav_offline: app.get_webdav_offline(),
+        webdav_client: app.get_webdav_client_params(),
+        git_remote: app.get_git_remote(),
+        s3: app.get_s3_params(),
         viewer_command: viewer_cmd.clone(),
         bkp_file_name: app.get_backup_file_name_str(),
+        bkp_count: app.get_backup_count(),
+        bkp_max_age: app.get_backup_max_age(),
         template_strings: app.get_template_strings(),
+        argon2_params: app.get_argon2_params(),
+        scrypt_params: app.get_scrypt_params(),
     };
 
     let bkp_file_name = old_values.bkp_file_name.clone();
@@ -440,11 +612,22 @@ pub fn config_main(app: &RustPwMan, config_file: std::path::PathBuf, sec_level:
     set_clip_commands_state(&mut siv, clp_cmd, cpy_cmd);
     set_template_strings_state(&mut siv, &app.get_template_strings());
     set_edit_state_by_option(&mut siv, EDIT_VIEWER_COMMAND, viewer_cmd);
+    set_edit_state_by_option(&mut siv, EDIT_ARGON2_PARAMS, &app.get_argon2_params());
+    #[cfg(feature = "withscrypt")]
+    set_edit_state_by_option(&mut siv, EDIT_SCRYPT_PARAMS, &app.get_scrypt_params());
 
     #[cfg(feature = "writebackup")]
     set_edit_state_by_option(&mut siv, EDIT_BACKUP_FILE, &bkp_file_name);
+    #[cfg(feature = "writebackup")]
+    set_edit_state_by_option(&mut siv, EDIT_BACKUP_COUNT, &app.get_backup_count().map(|c| c.to_string()));
+    #[cfg(feature = "writebackup")]
+    set_edit_state_by_option(&mut siv, EDIT_BACKUP_AGE, &app.get_backup_max_age().map(|a| a.to_string()));
     #[cfg(feature = "webdav")]
     set_webdav_state(&mut siv, webdav_user, webdav_server, webdav_pw);
+    #[cfg(feature = "webdav")]
+    set_edit_state_by_option(&mut siv, EDIT_WEBDAV_OFFLINE, &app.get_webdav_offline());
+    #[cfg(feature = "git")]
+    set_edit_state_by_option(&mut siv, EDIT_GIT_REMOTE, &app.get_git_remote());
 
     crate::load_theme!(siv);
 
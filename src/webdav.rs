@@ -18,139 +18,667 @@ use crate::persist::SendSyncPersister;
 #[cfg(feature = "webdav")]
 use crate::persist::Persister;
 #[cfg(feature = "webdav")]
+use crate::persist::{SyncState, conflict_error, is_conflict, write_file_atomic};
+#[cfg(feature = "webdav")]
 use reqwest::{Method, Url, StatusCode};
 #[cfg(feature = "webdav")]
+use reqwest::blocking::{Client, RequestBuilder};
+#[cfg(feature = "webdav")]
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED, IF_UNMODIFIED_SINCE};
+#[cfg(feature = "webdav")]
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "webdav")]
+use sha2::{Sha256, Digest};
+#[cfg(feature = "webdav")]
+use std::fs;
+#[cfg(feature = "webdav")]
 use std::io::{Error, ErrorKind};
+#[cfg(feature = "webdav")]
+use std::path::PathBuf;
+
+#[cfg(feature = "webdav")]
+pub const OFFLINE_QUEUE: &str = "queue";
+#[cfg(feature = "webdav")]
+pub const OFFLINE_READ_ONLY: &str = "readonly";
+#[cfg(feature = "webdav")]
+pub const OFFLINE_OFF: &str = "off";
+
+// Identifies the version of the file on the server. The ETag is preferred but not all servers send one.
+#[cfg(feature = "webdav")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum WebDavVersion {
+    ETag(String),
+    LastModified(String),
+    // The file has been written but the server did not report its new version. It has to be read again
+    // before it can be overwritten.
+    Unknown
+}
+
+#[cfg(feature = "webdav")]
+impl WebDavVersion {
+    fn from_headers(headers: &HeaderMap) -> Option<WebDavVersion> {
+        if let Some(Ok(e)) = headers.get(ETAG).map(|h| h.to_str()) {
+            return Some(WebDavVersion::ETag(String::from(e)));
+        }
+
+        if let Some(Ok(m)) = headers.get(LAST_MODIFIED).map(|h| h.to_str()) {
+            return Some(WebDavVersion::LastModified(String::from(m)));
+        }
+
+        return None;
+    }
+}
+
+// Determines what happens when the server is not reachable
+#[cfg(feature = "webdav")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OfflineMode {
+    // Changes are stored in the mirror and uploaded when the server is reachable again
+    Queue,
+    // The data from the mirror can be viewed but not changed
+    ReadOnly
+}
+
+#[cfg(feature = "webdav")]
+impl OfflineMode {
+    // None means that no mirror is kept
+    pub fn from_str(mode: &str) -> Option<Option<OfflineMode>> {
+        return match mode {
+            OFFLINE_QUEUE => Some(Some(OfflineMode::Queue)),
+            OFFLINE_READ_ONLY => Some(Some(OfflineMode::ReadOnly)),
+            OFFLINE_OFF => Some(None),
+            _ => None
+        };
+    }
+}
+
+#[cfg(feature = "webdav")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MirrorInfo {
+    #[serde(rename(deserialize = "Url"))]
+    #[serde(rename(serialize = "Url"))]
+    url: String,
+    // Version of the mirrored server data
+    #[serde(rename(deserialize = "Version"))]
+    #[serde(rename(serialize = "Version"))]
+    version: Option<WebDavVersion>,
+    // Version of the server data on which the queued changes are based
+    #[serde(rename(deserialize = "PendingBase"))]
+    #[serde(rename(serialize = "PendingBase"))]
+    pending_base: Option<WebDavVersion>
+}
+
+// An encrypted local copy of the last data which was read from or written to the server. Changes which are
+// made while the server is not reachable are stored in a separate file until they can be uploaded.
+#[cfg(feature = "webdav")]
+pub struct Mirror {
+    dir: PathBuf,
+    mode: OfflineMode
+}
+
+#[cfg(feature = "webdav")]
+impl Mirror {
+    pub fn new(dir: &PathBuf, mode: OfflineMode) -> Mirror {
+        return Mirror {
+            dir: dir.clone(),
+            mode: mode
+        };
+    }
+
+    // The mirror is kept in the cache directory of the user
+    pub fn default_dir() -> Option<PathBuf> {
+        return dirs::cache_dir().map(|d| d.join("rustpwman"));
+    }
+
+    fn file_name(&self, url: &str, suffix: &str) -> String {
+        let hash = Sha256::digest(url.as_bytes());
+        let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+
+        return String::from(self.dir.join(format!("{}{}", hex, suffix)).to_string_lossy());
+    }
+
+    fn data_file(&self, url: &str) -> String {
+        return self.file_name(url, ".enc");
+    }
+
+    fn pending_file(&self, url: &str) -> String {
+        return self.file_name(url, ".pending.enc");
+    }
+
+    fn info_file(&self, url: &str) -> String {
+        return self.file_name(url, ".json");
+    }
+
+    fn read_info(&self, url: &str) -> Option<MirrorInfo> {
+        let data = fs::read(self.info_file(url)).ok()?;
+
+        return serde_json::from_slice(&data).ok();
+    }
+
+    fn write_info(&self, info: &MirrorInfo) -> std::io::Result<()> {
+        let data = serde_json::to_vec_pretty(info)?;
+
+        return write_file_atomic(&self.info_file(&info.url), &data);
+    }
+
+    fn read_data(&self, url: &str) -> Option<Vec<u8>> {
+        return fs::read(self.data_file(url)).ok();
+    }
+
+    fn read_pending(&self, url: &str) -> Option<Vec<u8>> {
+        return fs::read(self.pending_file(url)).ok();
+    }
+
+    // Stores data which is identical to the data on the server. This is not done while changes are queued
+    // because the mirrored data is the version on which the queued changes are based.
+    fn store(&self, url: &str, data: &Vec<u8>, version: &Option<WebDavVersion>) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_file_atomic(&self.data_file(url), data)?;
+
+        return self.write_info(&MirrorInfo { url: String::from(url), version: version.clone(), pending_base: None });
+    }
+
+    fn queue(&self, url: &str, data: &Vec<u8>, base: &Option<WebDavVersion>) -> std::io::Result<()> {
+        let mut info = match self.read_info(url) {
+            Some(i) => i,
+            None => MirrorInfo { url: String::from(url), version: None, pending_base: None }
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        write_file_atomic(&self.pending_file(url), data)?;
+
+        // Changes which are queued later are based on the same version as the first ones
+        if info.pending_base.is_none() {
+            info.pending_base = base.clone();
+        }
+
+        return self.write_info(&info);
+    }
+
+    fn clear_pending(&self, url: &str) -> std::io::Result<()> {
+        if let Err(e) = fs::remove_file(self.pending_file(url)) {
+            if e.kind() != ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+
+        return match self.read_info(url) {
+            Some(i) => self.write_info(&MirrorInfo { pending_base: None, ..i }),
+            None => Ok(())
+        };
+    }
+}
 
 #[cfg(feature = "webdav")]
 pub struct WebDavPersister {
     user_id: String,
     password: String,
+    // Is used instead of user_id and password if present
+    token: Option<String>,
+    // The error message is reported when the client is used
+    client: Result<Client, String>,
     server: String,
-    store_id: String
+    store_id: String,
+    // Version of the file as seen by the last call to retrieve or persist
+    version: Option<WebDavVersion>,
+    mirror: Option<Mirror>,
+    sync_state: SyncState
+}
+
+#[cfg(feature = "webdav")]
+fn is_offline(e: &Error) -> bool {
+    return (e.kind() == ErrorKind::NotConnected) || (e.kind() == ErrorKind::TimedOut);
+}
+
+#[cfg(feature = "webdav")]
+fn to_io_error(e: reqwest::Error) -> Error {
+    let kind = if e.is_connect() {
+        ErrorKind::NotConnected
+    } else if e.is_timeout() {
+        ErrorKind::TimedOut
+    } else {
+        ErrorKind::Other
+    };
+
+    return Error::new(kind, format!("Error: '{}'", e));
 }
 
 #[cfg(feature = "webdav")]
 impl WebDavPersister {
-    pub fn new(u: &String, p: &String, s: &String, s_id: &String) -> SendSyncPersister {
+    pub fn new(u: &String, p: &String, token: &Option<String>, client: &Result<Client, String>, s: &String, s_id: &String, mirror: Option<Mirror>) -> SendSyncPersister {
         let res = WebDavPersister {
             user_id: u.clone(),
             password: p.clone(),
+            token: token.clone(),
+            client: client.clone(),
             server: s.clone(),
-            store_id: s_id.clone()
+            store_id: s_id.clone(),
+            version: None,
+            mirror: mirror,
+            sync_state: SyncState::Online
         };
 
         return Box::new(res);
     }
-}
 
-#[cfg(feature = "webdav")]
-impl Persister for WebDavPersister {
-    fn does_exist(&self) -> std::io::Result<bool> {
-        let body = r#"<?xml version="1.0" encoding="utf-8" ?>
-            <D:propfind xmlns:D="DAV:">
-                <D:prop><D:getcontentlength/></D:prop>
-            </D:propfind>
-        "#;
+    fn url_str(&self) -> String {
+        return format!("{}{}", &self.server, &self.store_id);
+    }
 
-        let url_str = format!("{}{}", &self.server, &self.store_id);
-        let url = match Url::parse(&url_str) {
-            Ok(u) => u,
-            Err(_) => {
-                return Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
-            }
+    fn make_url(&self) -> std::io::Result<Url> {
+        let url_str = self.url_str();
+
+        return match Url::parse(&url_str) {
+            Ok(u) => Ok(u),
+            Err(_) => Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
         };
+    }
 
-        let response = reqwest::blocking::Client::new()
-            .request(Method::from_bytes(b"PROPFIND").unwrap(), url)
-            .basic_auth(self.user_id.as_str(), Some(self.password.as_str()))
-            .header("depth", "0")
-            .body(body)
+    fn request(&self, method: Method) -> std::io::Result<RequestBuilder> {
+        return self.request_url(method, self.make_url()?);
+    }
+
+    fn request_url(&self, method: Method, url: Url) -> std::io::Result<RequestBuilder> {
+        let client = match &self.client {
+            Ok(c) => c,
+            Err(e) => return Err(Error::new(ErrorKind::Other, e.clone()))
+        };
+
+        let request = client.request(method, url);
+
+        return Ok(match &self.token {
+            Some(t) => request.bearer_auth(t),
+            None => request.basic_auth(self.user_id.as_str(), Some(self.password.as_str()))
+        });
+    }
+
+    fn read_data(&self) -> std::io::Result<(Box<Vec<u8>>, Option<WebDavVersion>)> {
+        let response = self.request(Method::GET)?
             .send();
 
-        let status = match response {
-            Ok(r) => r.status(),
+        let resp = match response {
+            Ok(r) => r,
             Err(e) => {
-                return Err(Error::new(ErrorKind::Other, format!("Error: '{}'", e)))
+                return Err(to_io_error(e))
             }
         };
 
-        if status.is_success() {
-            return Ok(true)
+        if !resp.status().is_success() {
+            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", resp.status().as_u16())));
         }
 
-        if status == StatusCode::NOT_FOUND {
-            return Ok(false);
-        }
+        let version = WebDavVersion::from_headers(resp.headers());
 
-        return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
+        let res_bytes = match resp.bytes() {
+            Ok(b) => b,
+            Err(e) => {
+                return Err(to_io_error(e))
+            },
+        };
+
+        let res_data: Vec<u8> = res_bytes.into_iter().collect();
+
+        return Ok((Box::<Vec<u8>>::new(res_data), version));
     }
 
-    fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
-        let url_str = format!("{}{}", &self.server, &self.store_id);
-        let url = match Url::parse(&url_str) {
-            Ok(u) => u,
-            Err(_) => {
-                return Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
-            }
+    // Uploads are first written to a temporary resource next to the target which is then moved over
+    // the target. This prevents a partially uploaded file if the connection drops.
+    fn make_temp_url(&self) -> std::io::Result<Url> {
+        let url_str = self.url_str();
+        let (dir, name) = match url_str.rfind('/') {
+            Some(pos) => url_str.split_at(pos + 1),
+            None => ("", url_str.as_str())
         };
+        let temp_str = format!("{}.{}.{:016x}.tmp", dir, name, rand::random::<u64>());
 
-        let response = reqwest::blocking::Client::new()
-            .request(Method::PUT, url)
-            .basic_auth(self.user_id.as_str(), Some(self.password.as_str()))
+        return match Url::parse(&temp_str) {
+            Ok(u) => Ok(u),
+            Err(_) => Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &temp_str)))
+        };
+    }
+
+    fn send(request: RequestBuilder) -> std::io::Result<StatusCode> {
+        return match request.send() {
+            Ok(r) => Ok(r.status()),
+            Err(e) => Err(to_io_error(e))
+        };
+    }
+
+    // Also returns the version of the resource if the response contains one
+    fn send_versioned(request: RequestBuilder) -> std::io::Result<(StatusCode, Option<WebDavVersion>)> {
+        return match request.send() {
+            Ok(r) => Ok((r.status(), WebDavVersion::from_headers(r.headers()))),
+            Err(e) => Err(to_io_error(e))
+        };
+    }
+
+    // Used when a write succeeded but the response contains no version. If the server identified versions
+    // before the file has to be read again, otherwise conflicts can not be detected anyway.
+    fn unknown_version(expected_version: &Option<WebDavVersion>) -> Option<WebDavVersion> {
+        return match expected_version {
+            Some(_) => Some(WebDavVersion::Unknown),
+            None => None
+        };
+    }
+
+    // Uploads data if the file on the server still has the version expected_version. Returns the new version.
+    fn write_data(&self, data: &Vec<u8>, expected_version: &Option<WebDavVersion>) -> std::io::Result<Option<WebDavVersion>> {
+        match expected_version {
+            Some(WebDavVersion::Unknown) => return Err(conflict_error()),
+            // A MOVE can only be made conditional on the ETag of the target. Servers which only send the
+            // modification time therefore get a conditional PUT straight to the target.
+            Some(WebDavVersion::LastModified(m)) => return self.put_target(data, m, expected_version),
+            _ => ()
+        }
+
+        let target = self.make_url()?;
+        let temp = self.make_temp_url()?;
+
+        let request = self.request_url(Method::PUT, temp.clone())?
             .header("content-type", "application/octet-stream")
-            .body(data.clone())
-            .send();
-        
-        let status = match response {
-            Ok(r) => r.status(),
-            Err(e) => {
-                return Err(Error::new(ErrorKind::Other, format!("Error: '{}'", e)))
+            .body(data.clone());
+
+        let (status, uploaded) = WebDavPersister::send_versioned(request)?;
+
+        if !status.is_success() {
+            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
+        };
+
+        let uploaded = match uploaded {
+            Some(WebDavVersion::ETag(e)) => Some(WebDavVersion::ETag(e)),
+            _ => None
+        };
+
+        let res = self.move_over_target(&temp, &target, expected_version, uploaded);
+
+        if res.is_err() {
+            // The temporary resource is useless now. Failing to remove it does not change the outcome.
+            if let Ok(r) = self.request_url(Method::DELETE, temp) {
+                let _ = r.send();
             }
+        }
+
+        return res;
+    }
+
+    fn put_target(&self, data: &Vec<u8>, last_modified: &str, expected_version: &Option<WebDavVersion>) -> std::io::Result<Option<WebDavVersion>> {
+        let request = self.request(Method::PUT)?
+            .header("content-type", "application/octet-stream")
+            .header(IF_UNMODIFIED_SINCE, last_modified)
+            .body(data.clone());
+
+        let (status, version) = WebDavPersister::send_versioned(request)?;
+
+        if status == StatusCode::PRECONDITION_FAILED {
+            return Err(conflict_error());
+        }
+
+        if !status.is_success() {
+            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
+        };
+
+        return Ok(version.or(WebDavPersister::unknown_version(expected_version)));
+    }
+
+    // uploaded is the ETag which the temporary resource got when it was written
+    fn move_over_target(&self, temp: &Url, target: &Url, expected_version: &Option<WebDavVersion>, uploaded: Option<WebDavVersion>) -> std::io::Result<Option<WebDavVersion>> {
+        let mut request = self.request_url(Method::from_bytes(b"MOVE").unwrap(), temp.clone())?
+            .header("destination", target.as_str());
+
+        match expected_version {
+            // The file did not exist when it was read. If someone else has created it in the meantime it is not replaced.
+            None => request = request.header("overwrite", "F"),
+            // Let the server reject the request if the file has been changed since it was read. In contrast to
+            // If-Match, which refers to the temporary resource, the tagged list of the If header refers to the target.
+            Some(WebDavVersion::ETag(e)) => {
+                request = request.header("overwrite", "T")
+                    .header("if", format!("<{}> ([{}])", target.as_str(), e));
+            },
+            // A MOVE can not depend on the modification time of the target. write_data uses put_target instead.
+            Some(_) => return Err(conflict_error())
+        }
+
+        let (status, version) = WebDavPersister::send_versioned(request)?;
+
+        if status == StatusCode::PRECONDITION_FAILED {
+            return Err(conflict_error());
+        }
+
+        if !status.is_success() {
+            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
         };
 
+        // Servers rarely report a version for MOVE. A moved resource usually keeps the ETag of the upload. If it
+        // does not, the next save reports a conflict instead of overwriting the changes of someone else.
+        let version = version.or(uploaded);
+
+        // A new file can not be replaced without a version. Servers without ETags are therefore asked for it.
+        if version.is_none() && expected_version.is_none() {
+            return Ok(Some(self.head_version().ok().flatten().unwrap_or(WebDavVersion::Unknown)));
+        }
+
+        return Ok(version.or(WebDavPersister::unknown_version(expected_version)));
+    }
+
+    fn head_version(&self) -> std::io::Result<Option<WebDavVersion>> {
+        let (status, version) = WebDavPersister::send_versioned(self.request(Method::HEAD)?)?;
+
         if !status.is_success() {
             return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
         };
 
-        return Ok(());
+        return Ok(version);
     }
 
-    fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
-        let url_str = format!("{}{}", &self.server, &self.store_id);
-        let url = match Url::parse(&url_str) {
-            Ok(u) => u,
-            Err(_) => {
-                return Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
+    // Returns the status of a PROPFIND request for the given URL
+    fn propfind(&self, url: Url) -> std::io::Result<StatusCode> {
+        let body = r#"<?xml version="1.0" encoding="utf-8" ?>
+            <D:propfind xmlns:D="DAV:">
+                <D:prop><D:getcontentlength/></D:prop>
+            </D:propfind>
+        "#;
+
+        let request = self.request_url(Method::from_bytes(b"PROPFIND").unwrap(), url)?
+            .header("depth", "0")
+            .body(body);
+
+        return WebDavPersister::send(request);
+    }
+
+    // Returns the URLs of all collections above the file, outermost first
+    fn parent_urls(&self) -> std::io::Result<Vec<Url>> {
+        let mut res: Vec<Url> = Vec::new();
+        let mut url = self.make_url()?;
+
+        loop {
+            let parent = match url.join(if url.path().ends_with('/') { ".." } else { "." }) {
+                Ok(p) => p,
+                Err(_) => break
+            };
+
+            if (parent == url) || (parent.path() == "/") {
+                break;
             }
+
+            res.push(parent.clone());
+            url = parent;
+        }
+
+        res.reverse();
+
+        return Ok(res);
+    }
+
+    // Returns the data from the mirror when the server is not reachable. Queued changes are preferred.
+    fn read_mirror(&mut self) -> Option<Vec<u8>> {
+        let url = self.url_str();
+        let mirror = self.mirror.as_ref()?;
+        let info = mirror.read_info(&url)?;
+
+        if let Some(pending) = mirror.read_pending(&url) {
+            self.version = info.pending_base;
+            self.sync_state = SyncState::OfflinePending;
+            return Some(pending);
+        }
+
+        let data = mirror.read_data(&url)?;
+        self.version = info.version;
+        self.sync_state = SyncState::Offline;
+
+        return Some(data);
+    }
+
+    // Called after data has been read from the server. Tries to upload changes which were made while the server
+    // was not reachable. Returns the data which is now current.
+    fn sync_pending(&mut self, data: Box<Vec<u8>>) -> std::io::Result<Box<Vec<u8>>> {
+        let url = self.url_str();
+
+        let (pending, info) = match &self.mirror {
+            Some(m) => (m.read_pending(&url), m.read_info(&url)),
+            None => return Ok(data)
         };
 
-        let response = reqwest::blocking::Client::new()
-            .request(Method::GET, url)
-            .basic_auth(self.user_id.as_str(), Some(self.password.as_str()))
-            .send();
-        
-        let resp = match response {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::new(ErrorKind::Other, format!("Error: '{}'", e)))
+        let pending = match pending {
+            Some(p) => p,
+            None => {
+                self.sync_state = SyncState::Online;
+                if let Some(m) = &self.mirror {
+                    // A failure to update the mirror does not affect the data on the server
+                    let _ = m.store(&url, &data, &self.version);
+                }
+
+                return Ok(data);
             }
         };
 
-        if !resp.status().is_success() {
-            return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", resp.status().as_u16())));
+        let pending_base = info.and_then(|i| i.pending_base);
+
+        // The queued changes can only be uploaded without a merge if nobody else has changed the file in the meantime
+        if (pending_base.is_none()) || (pending_base != self.version) {
+            self.sync_state = SyncState::Conflict;
+            return Ok(data);
         }
 
-        let res_bytes = match resp.bytes() {
-            Ok(b) => b,
-            Err(e) => {
-                return Err(Error::new(ErrorKind::Other, format!("Error: '{}'", e)))
+        match self.write_data(&pending, &self.version) {
+            Ok(v) => {
+                self.version = v;
+                self.sync_state = SyncState::Online;
+
+                if let Some(m) = &self.mirror {
+                    m.store(&url, &pending, &self.version)?;
+                    m.clear_pending(&url)?;
+                }
+
+                return Ok(Box::new(pending));
             },
+            Err(e) if is_conflict(&e) => {
+                self.sync_state = SyncState::Conflict;
+                return Ok(data);
+            },
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+#[cfg(feature = "webdav")]
+impl Persister for WebDavPersister {
+    fn does_exist(&self) -> std::io::Result<bool> {
+        let status = match self.propfind(self.make_url()?) {
+            Ok(s) => s,
+            Err(err) => {
+                // The file exists if it can be read from the mirror
+                if let Some(m) = &self.mirror {
+                    if is_offline(&err) && m.read_info(&self.url_str()).is_some() {
+                        return Ok(true);
+                    }
+                }
+
+                return Err(err)
+            }
         };
 
-        let res_data: Vec<u8> = res_bytes.into_iter().collect();
+        if status.is_success() {
+            return Ok(true)
+        }
+
+        if status == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        return Err(Error::new(ErrorKind::Other, format!("HTTP error '{}'", status.as_u16())));
+    }
+
+    fn persist(&mut self, data: &Vec<u8>) -> std::io::Result<()> {
+        let url = self.url_str();
+
+        match self.write_data(data, &self.version) {
+            Ok(v) => {
+                self.version = v;
+
+                if let Some(m) = &self.mirror {
+                    // Unmerged changes from an offline session are kept until they are merged
+                    if self.sync_state != SyncState::Conflict {
+                        m.clear_pending(&url)?;
+                        self.sync_state = SyncState::Online;
+                        m.store(&url, data, &self.version)?;
+                    }
+                }
+
+                return Ok(());
+            },
+            Err(e) if is_offline(&e) => {
+                let mirror = match &self.mirror {
+                    Some(m) => m,
+                    None => return Err(e)
+                };
+
+                if mirror.mode == OfflineMode::ReadOnly {
+                    return Err(Error::new(ErrorKind::Other, "The server is not reachable and changes can not be queued in read only offline mode"));
+                }
+
+                mirror.queue(&url, data, &self.version)?;
+                self.sync_state = SyncState::OfflinePending;
+
+                return Ok(());
+            },
+            Err(e) => return Err(e)
+        }
+    }
+
+    fn retrieve(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        match self.read_data() {
+            Ok((data, version)) => {
+                self.version = version;
+                return self.sync_pending(data);
+            },
+            Err(e) if is_offline(&e) => {
+                return match self.read_mirror() {
+                    Some(d) => Ok(Box::new(d)),
+                    None => Err(e)
+                };
+            },
+            Err(e) => return Err(e)
+        }
+    }
 
-        return Ok(Box::<Vec<u8>>::new(res_data));
+    fn peek(&mut self) -> std::io::Result<Box<Vec<u8>>> {
+        match self.read_data() {
+            Ok((data, _)) => return Ok(data),
+            Err(e) if is_offline(&e) => {
+                let url = self.url_str();
+
+                let data = match &self.mirror {
+                    Some(m) => m.read_pending(&url).or(m.read_data(&url)),
+                    None => None
+                };
+
+                return match data {
+                    Some(d) => Ok(Box::new(d)),
+                    None => Err(e)
+                };
+            },
+            Err(e) => return Err(e)
+        }
     }
 
     fn get_canonical_path(&self) -> std::io::Result<String> {
@@ -160,4 +688,71 @@ impl Persister for WebDavPersister {
     fn get_type(&self) -> String {
         return String::from("WebDAV")
     }
-}
\ No newline at end of file
+
+    fn missing_parents(&self) -> std::io::Result<Vec<String>> {
+        let mut res: Vec<String> = Vec::new();
+
+        // Search from the innermost collection outwards until one is found that exists
+        for url in self.parent_urls()?.into_iter().rev() {
+            if self.propfind(url.clone())? != StatusCode::NOT_FOUND {
+                break;
+            }
+
+            res.push(String::from(url.as_str()));
+        }
+
+        res.reverse();
+
+        return Ok(res);
+    }
+
+    fn create_parents(&self) -> std::io::Result<()> {
+        for url_str in self.missing_parents()? {
+            let url = match Url::parse(&url_str) {
+                Ok(u) => u,
+                Err(_) => return Err(Error::new(ErrorKind::Other, format!("Not a valid URL: '{}'", &url_str)))
+            };
+
+            let status = WebDavPersister::send(self.request_url(Method::from_bytes(b"MKCOL").unwrap(), url)?)?;
+
+            // 405 means that the collection already exists
+            if !status.is_success() && (status != StatusCode::METHOD_NOT_ALLOWED) {
+                return Err(Error::new(ErrorKind::Other, format!("Unable to create collection '{}': HTTP error '{}'", url_str, status.as_u16())));
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn sync_state(&self) -> Option<SyncState> {
+        return self.mirror.as_ref().map(|_| self.sync_state);
+    }
+
+    fn unsynced(&self) -> Option<(Vec<u8>, Option<Vec<u8>>)> {
+        if self.sync_state != SyncState::Conflict {
+            return None;
+        }
+
+        let url = self.url_str();
+        let mirror = self.mirror.as_ref()?;
+
+        return Some((mirror.read_pending(&url)?, mirror.read_data(&url)));
+    }
+
+    fn resolve_unsynced(&mut self, discard: bool) -> std::io::Result<()> {
+        if self.sync_state != SyncState::Conflict {
+            return Ok(());
+        }
+
+        // Merged changes are removed by the next call to persist
+        if discard {
+            if let Some(m) = &self.mirror {
+                m.clear_pending(&self.url_str())?;
+            }
+        }
+
+        self.sync_state = SyncState::Online;
+
+        return Ok(());
+    }
+}